@@ -1,5 +1,8 @@
 //! For Command Line Interface for archors_stator
 
+use std::path::PathBuf;
+
+use archors_types::chain::ChainConfig;
 use clap::{Parser, ValueEnum};
 use url::Url;
 
@@ -23,6 +26,43 @@ pub struct AppArgs {
     /// Block number to get state information for (the block that will be re-executed)
     #[clap(short, long)]
     pub block_number: u64,
+    /// Chain that the nodes are expected to follow
+    #[clap(long, value_enum, default_value_t = Chain::Mainnet)]
+    pub chain: Chain,
+    /// Genesis file defining a custom chain (e.g., a devnet). Takes precedence over --chain.
+    /// Only the chain id is used, to check the nodes
+    #[clap(long)]
+    pub genesis: Option<PathBuf>,
+}
+
+impl AppArgs {
+    /// Gets the config of the chain the nodes are expected to follow.
+    ///
+    /// Only the chain id is used, the data fetched does not depend on the fork schedule.
+    pub fn chain_config(&self) -> anyhow::Result<ChainConfig> {
+        Ok(match &self.genesis {
+            Some(path) => ChainConfig::from_genesis_file(path)?,
+            None => self.chain.config(),
+        })
+    }
+}
+
+/// Chains with built-in configurations.
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum Chain {
+    Mainnet,
+    Sepolia,
+    Holesky,
+}
+
+impl Chain {
+    fn config(&self) -> ChainConfig {
+        match self {
+            Chain::Mainnet => ChainConfig::mainnet(),
+            Chain::Sepolia => ChainConfig::sepolia(),
+            Chain::Holesky => ChainConfig::holesky(),
+        }
+    }
 }
 
 /// Format of data to be written to file.
@@ -4,7 +4,10 @@ use std::{
 };
 
 use anyhow::{bail, Result};
-use archors_inventory::{cache::fetch_required_block_state, utils::compress};
+use archors_inventory::{
+    cache::{check_chain, fetch_required_block_state},
+    utils::compress,
+};
 use archors_types::state::RequiredBlockState;
 use clap::Parser;
 use cli::OutputKind;
@@ -20,6 +23,7 @@ mod cli;
 /// peer who can use it to trustlessly trace an historical Ethereum block.
 ///
 /// Involves:
+/// - eth_chainId to check the nodes follow the expected chain
 /// - debug_traceBlock for state accesses
 /// - debug_traceBlock for blockhash use
 /// - eth_getProof for proof of historical state
@@ -29,6 +33,10 @@ async fn main() -> Result<()> {
 
     let args = AppArgs::parse();
 
+    let chain = args.chain_config()?;
+    check_chain(args.trace_block_node.as_ref(), &chain).await?;
+    check_chain(args.get_proof_node.as_ref(), &chain).await?;
+
     let mut file = prepare_file(&args).expect("Could not prepare file");
 
     let required_block_state = fetch_required_block_state(
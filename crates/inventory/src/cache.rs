@@ -7,6 +7,8 @@ use std::{
 };
 
 use archors_types::{
+    beacon::{BEACON_ROOTS_ADDRESS, HISTORY_BUFFER_LENGTH},
+    chain::ChainConfig,
     oracle::TrieNodeOracle,
     state::{RequiredBlockState, StateError},
 };
@@ -24,9 +26,10 @@ use url::{ParseError, Url};
 use crate::{
     oracle::{oracle_from_simulated_state_update, OracleError},
     rpc::{
-        debug_trace_block_default, debug_trace_block_prestate, eth_get_proof, get_block_by_number,
-        AccountProofResponse, BlockDefaultTraceResponse, BlockPrestateResponse,
-        BlockPrestateTransactions, BlockResponse, JsonRpcRequest,
+        debug_trace_block_default, debug_trace_block_prestate, eth_chain_id, eth_get_proof,
+        get_block_by_number, get_uncle_by_block_number_and_index, AccountProofResponse,
+        BlockDefaultTraceResponse, BlockPrestateResponse, BlockPrestateTransactions, BlockResponse,
+        ChainIdResponse, JsonRpcRequest, UncleResponse,
     },
     transferrable::{state_from_parts, TransferrableError},
     types::{BlockHashAccess, BlockHashAccesses, BlockProofs, BlockStateAccesses},
@@ -44,6 +47,12 @@ pub enum CacheError {
     },
     #[error("Block retrieved does not yet have a number")]
     NoBlockNumber,
+    #[error("Node at {url} has chain id {node}, expected chain id {expected}")]
+    ChainMismatch {
+        url: String,
+        node: u64,
+        expected: u64,
+    },
     #[error("Node oracle error {0}")]
     OracleError(#[from] OracleError),
     #[error("Reqwest error {0}")]
@@ -73,28 +82,123 @@ pub enum CacheError {
     },
 }
 
-pub async fn store_block_with_transactions(url: &str, target_block: u64) -> Result<(), CacheError> {
-    let block_number_hex = format!("0x{:x}", target_block);
+/// Gets the chain id of the chain that a node follows.
+pub async fn fetch_chain_id(url: &str) -> Result<u64, CacheError> {
     let client = Client::new();
-    // Get a block.
-    let block = client
+    let response = client
         .post(Url::parse(url)?)
-        .json(&get_block_by_number(&block_number_hex))
+        .json(&eth_chain_id())
         .send()
         .await?
-        .json::<BlockResponse>()
+        .json::<ChainIdResponse>()
         .await?;
+    Ok(response.result.as_u64())
+}
+
+/// Checks that a node follows the expected chain. Prevents state for one chain being
+/// assembled from the data of another.
+pub async fn check_chain(url: &str, chain: &ChainConfig) -> Result<(), CacheError> {
+    let node = fetch_chain_id(url).await?;
+    if node != chain.chain_id {
+        return Err(CacheError::ChainMismatch {
+            url: url.to_string(),
+            node,
+            expected: chain.chain_id,
+        });
+    }
+    Ok(())
+}
+
+/// Retrieves a block (with transactions) and its uncles and caches them.
+pub async fn store_block_with_transactions(url: &str, target_block: u64) -> Result<(), CacheError> {
+    let block = request_block(url, target_block).await?;
+    let uncles = fetch_uncles(url, &block).await?;
 
-    let Some(block_number) = block.result.number else {
+    let Some(block_number) = block.number else {
         return Err(CacheError::NoBlockNumber);
     };
     let names = CacheFileNames::new(block_number.as_u64());
     fs::create_dir_all(names.dirname())?;
     let mut block_file = File::create(names.block_with_transactions())?;
-    block_file.write_all(serde_json::to_string_pretty(&block.result)?.as_bytes())?;
+    block_file.write_all(serde_json::to_string_pretty(&block)?.as_bytes())?;
+    let mut uncles_file = File::create(names.block_uncles())?;
+    uncles_file.write_all(serde_json::to_string_pretty(&uncles)?.as_bytes())?;
     Ok(())
 }
 
+/// Calls eth_getBlockByNumber for a block with transactions.
+async fn request_block(url: &str, target_block: u64) -> Result<Block<Transaction>, CacheError> {
+    let block_number_hex = format!("0x{:x}", target_block);
+    let client = Client::new();
+    let block = client
+        .post(Url::parse(url)?)
+        .json(&get_block_by_number(&block_number_hex))
+        .send()
+        .await?
+        .json::<BlockResponse>()
+        .await?;
+    Ok(block.result)
+}
+
+/// Gets the headers of the uncles (ommers) of a block, in the order they appear in the block.
+///
+/// These are used to apply pre-merge uncle rewards during block execution.
+pub async fn fetch_uncles(
+    url: &str,
+    block: &Block<Transaction>,
+) -> Result<Vec<Block<H256>>, CacheError> {
+    let block_number = block.number.ok_or(CacheError::NoBlockNumber)?;
+    let block_number_hex = format!("0x{:x}", block_number.as_u64());
+    let client = Client::new();
+    let mut uncles = vec![];
+    for index in 0..block.uncles.len() {
+        let request = get_uncle_by_block_number_and_index(&block_number_hex, index);
+        let response: UncleResponse = client
+            .post(Url::parse(url)?)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await
+            .map_err(|e| CacheError::DecodingFailed { source: e, request })?;
+        uncles.push(response.result);
+    }
+    Ok(uncles)
+}
+
+/// Includes accounts that are not necessarily accessed by transactions, but whose
+/// state changes during the block.
+///
+/// - Block reward recipients (block author and uncle authors).
+/// - Withdrawal recipients.
+/// - Beacon roots contract slots written with the parent beacon block root (EIP-4788).
+fn include_block_state_changes(
+    accesses: &mut BlockStateAccesses,
+    block: &Block<Transaction>,
+    uncles: &[Block<H256>],
+) {
+    let authors = block
+        .author
+        .iter()
+        .chain(uncles.iter().filter_map(|u| u.author.as_ref()));
+    for author in authors {
+        accesses.include_account(*author);
+    }
+    for withdrawal in block.withdrawals.iter().flatten() {
+        accesses.include_account(withdrawal.address);
+    }
+    if block.parent_beacon_block_root.is_some() {
+        let contract = H160(BEACON_ROOTS_ADDRESS.0 .0);
+        let slot = block.timestamp.low_u64() % HISTORY_BUFFER_LENGTH;
+        accesses
+            .include_storage(contract, H256::from_low_u64_be(slot))
+            .include_storage(
+                contract,
+                H256::from_low_u64_be(slot + HISTORY_BUFFER_LENGTH),
+            );
+    }
+}
+
 /// Retrieves required state for a particular cached block.
 ///
 /// Creates a transferrable state parcel without the creation of intermediate cache files.
@@ -106,8 +210,11 @@ pub async fn fetch_required_block_state(
     // Prestate-trace the block. Then deduplicate. Then getProof for prior block.
     info!("1/6 requesting debug_traceBlock with prestate tracer");
     let tx_prestates = request_prestate_tracer(url, target_block).await?;
-    info!("2/6 extracting unique state accesses");
-    let accesses = BlockStateAccesses::from_prestate_accesses(tx_prestates);
+    info!("2/6 extracting unique state accesses, block reward and withdrawal recipients");
+    let mut accesses = BlockStateAccesses::from_prestate_accesses(tx_prestates);
+    let block = request_block(url, target_block).await?;
+    let uncles = fetch_uncles(url, &block).await?;
+    include_block_state_changes(&mut accesses, &block, &uncles);
     let account_num = accesses.access_data.len();
     info!("3/6 requesting eth_getProof for accessed states ({account_num} separate calls)");
     let proofs = request_proofs(get_proof_url, &accesses, target_block).await?;
@@ -273,6 +380,8 @@ async fn fetch_blockhashes(url: &str, target_block: u64) -> Result<BlockHashAcce
 ///
 /// Note that accounts can have the same bytecode (e.g., redeployments) and this
 /// represent duplication that can be resolved with compression.
+///
+/// The block (and uncles, if any) must already be cached, see store_block_with_transactions().
 pub fn store_deduplicated_state(target_block: u64) -> Result<(), CacheError> {
     let names = CacheFileNames::new(target_block);
     let filename = names.block_prestate_trace();
@@ -280,8 +389,11 @@ pub fn store_deduplicated_state(target_block: u64) -> Result<(), CacheError> {
         source: e,
         filename,
     })?;
-    let block: Vec<BlockPrestateTransactions> = serde_json::from_str(&data)?;
-    let state_accesses = BlockStateAccesses::from_prestate_accesses(block);
+    let block_prestates: Vec<BlockPrestateTransactions> = serde_json::from_str(&data)?;
+    let mut state_accesses = BlockStateAccesses::from_prestate_accesses(block_prestates);
+    let block = get_block_from_cache(target_block)?;
+    let uncles = get_uncles_from_cache(&block)?;
+    include_block_state_changes(&mut state_accesses, &block, &uncles);
     fs::create_dir_all(names.dirname())?;
     let mut block_file = File::create(names.block_accessed_state_deduplicated())?;
     block_file.write_all(serde_json::to_string_pretty(&state_accesses)?.as_bytes())?;
@@ -497,6 +609,25 @@ pub fn get_block_from_cache(block: u64) -> Result<Block<Transaction>, CacheError
     Ok(block)
 }
 
+/// Retrieves the uncle (ommer) headers for a single block that has been stored.
+///
+/// The uncles file is only required if the block header lists uncles. This allows caches
+/// made without an uncles file to be used for blocks without uncles (E.g., post-merge).
+pub fn get_uncles_from_cache<T>(block: &Block<T>) -> Result<Vec<Block<H256>>, CacheError> {
+    if block.uncles.is_empty() {
+        return Ok(vec![]);
+    }
+    let number = block.number.ok_or(CacheError::NoBlockNumber)?.as_u64();
+    let uncles_cache_path = CacheFileNames::new(number).block_uncles();
+    let file = File::open(&uncles_cache_path).map_err(|e| CacheError::FileOpener {
+        source: e,
+        filename: uncles_cache_path,
+    })?;
+    let reader = BufReader::new(file);
+    let uncles = serde_json::from_reader(reader)?;
+    Ok(uncles)
+}
+
 /// Retrieves all BLOCKHASH use values for a single block.
 pub fn get_blockhashes_from_cache(block: u64) -> Result<BlockHashAccesses, CacheError> {
     let blockhash_path = CacheFileNames::new(block).blockhashes();
@@ -578,6 +709,9 @@ impl CacheFileNames {
     fn block_with_transactions(&self) -> PathBuf {
         self.dirname().join("block_with_transactions.json")
     }
+    fn block_uncles(&self) -> PathBuf {
+        self.dirname().join("block_uncles.json")
+    }
     fn blockhashes(&self) -> PathBuf {
         self.dirname().join("blockhash_opcode_use.json")
     }
@@ -14,7 +14,7 @@ use archors_multiproof::{
 use archors_types::oracle::TrieNodeOracle;
 use archors_verify::path::{NibblePath, PathError};
 use ethers::{
-    types::{H160, H256, U256},
+    types::{EIP1186ProofResponse, H160, H256, U256},
     utils::{keccak256, rlp},
 };
 use thiserror::Error;
@@ -30,6 +30,8 @@ pub enum OracleError {
     NoPostStateAddress(String),
     #[error("Unable to find address in pre-state proof {0}")]
     NoPreStateAddress(String),
+    #[error("Post-state account proof for address {0} has no nodes")]
+    NoPostStateAccountProof(String),
     #[error("Unable to find key {key} in post-state proof for address {address}")]
     NoPostStateKey { address: String, key: String },
     #[error("Multiproof Error {0}")]
@@ -136,7 +138,7 @@ pub fn oracle_from_simulated_state_update(
     let mut updates: Vec<InterestingUpdate> = vec![];
     for (address, account) in post.proofs.iter() {
         for storage_proof_post in &account.storage_proof {
-            let key = storage_proof_post.key;
+            let key = H256::from(<[u8; 32]>::from(storage_proof_post.key));
 
             let acc_proof_pre = pre
                 .proofs
@@ -145,7 +147,7 @@ pub fn oracle_from_simulated_state_update(
             let val_pre: U256 = acc_proof_pre
                 .storage_proof
                 .iter()
-                .find(|x| x.key == key)
+                .find(|x| x.key == storage_proof_post.key)
                 .ok_or_else(|| OracleError::NoPostStateKey {
                     address: hex_encode(address),
                     key: hex_encode(key),
@@ -163,6 +165,17 @@ pub fn oracle_from_simulated_state_update(
             });
         }
     }
+    // Detect accounts removed by the block.
+    let mut removed_accounts: Vec<H160> = vec![];
+    for (address, account_post) in post.proofs.iter() {
+        let account_pre = pre
+            .proofs
+            .get(address)
+            .ok_or_else(|| OracleError::NoPreStateAddress(hex_encode(address)))?;
+        if account_removed(account_pre, account_post) {
+            removed_accounts.push(address.to_owned());
+        }
+    }
     // sort updates by storage key for consistency. If keys are sorted while executing the
     // post-execution changes, trie updates that require an oracle will be simpler.
     updates.sort_by_key(|x| x.key);
@@ -194,6 +207,16 @@ pub fn oracle_from_simulated_state_update(
         }
     }
 
+    // Accounts removed by the block (SELFDESTRUCT, EIP-161) may also require an oracle.
+    let mut account_tasks: Vec<(H160, usize)> = vec![];
+    for address in removed_accounts {
+        if let ProofOutcome::IndexForOracle(traversal_index) =
+            multiproof_pre.remove_account(&address)?
+        {
+            account_tasks.push((address, traversal_index))
+        }
+    }
+
     let mut oracle = TrieNodeOracle::default();
     // Populate the oracle
     for task in &tasks {
@@ -204,7 +227,7 @@ pub fn oracle_from_simulated_state_update(
         let storage = account
             .storage_proof
             .iter()
-            .find(|x| x.key == task.key)
+            .find(|x| x.key == U256::from_big_endian(task.key.as_bytes()))
             .ok_or(OracleError::NoPostStateKey {
                 address: hex_encode(task.address),
                 key: hex_encode(task.key),
@@ -220,26 +243,70 @@ pub fn oracle_from_simulated_state_update(
             true => Intent::VerifyExclusion,
             false => Intent::VerifyInclusion(rlp::encode(&storage.value).to_vec()),
         };
-        let visited = proof
-            .traverse(path.into(), &intent)
-            .expect("All tasks should be for exclusion proofs (in post-block state)");
-
-        // Skip the first part of the proof. Only include the required nodes.
-        let mut proof_subset: Vec<Vec<u8>> = vec![];
-        for node in visited {
-            if node.traversal_record.visiting_index() >= task.traversal_index {
-                let node_bytes = proof.get_node(&node.node_hash)?;
-                proof_subset.push(node_bytes.to_vec())
-            }
-        }
-
-        let path_nibbles = NibblePath::init(&path);
-        let nibbles_to_target = path_nibbles.traversal_to_index(task.traversal_index)?;
-        oracle.insert_nodes(task.address, nibbles_to_target.to_vec(), proof_subset)
+        let (nibbles_to_target, proof_subset) =
+            nodes_at_and_below(&mut proof, path, intent, task.traversal_index)?;
+        oracle.insert_nodes(task.address, nibbles_to_target, proof_subset)
+    }
+    for (address, traversal_index) in account_tasks {
+        let account = post
+            .proofs
+            .get(&address)
+            .ok_or(OracleError::NoPostStateAddress(hex_encode(address)))?;
+        let root_node = account
+            .account_proof
+            .first()
+            .ok_or(OracleError::NoPostStateAccountProof(hex_encode(address)))?;
+        let mut proof = MultiProof::init(keccak256(root_node).into());
+        proof
+            .insert_proof(account.account_proof.to_owned())
+            .expect("Cachecd proof from RPC expected to be valid.");
+        let path = keccak256(address);
+        let (nibbles_to_target, proof_subset) =
+            nodes_at_and_below(&mut proof, path, Intent::VerifyExclusion, traversal_index)?;
+        oracle.insert_account_nodes(nibbles_to_target, proof_subset)
     }
     Ok(oracle)
 }
 
+/// Traverses a (post-block) proof and returns the nodes at and below the traversal index,
+/// along with the nibbles that lead to the first of those nodes.
+fn nodes_at_and_below(
+    proof: &mut MultiProof,
+    path: [u8; 32],
+    intent: Intent,
+    traversal_index: usize,
+) -> Result<(Vec<u8>, Vec<Vec<u8>>), OracleError> {
+    let visited = proof
+        .traverse(path.into(), &intent)
+        .expect("All tasks should be for exclusion proofs (in post-block state)");
+
+    // Skip the first part of the proof. Only include the required nodes.
+    let mut proof_subset: Vec<Vec<u8>> = vec![];
+    for node in visited {
+        if node.traversal_record.visiting_index() >= traversal_index {
+            let node_bytes = proof.get_node(&node.node_hash)?;
+            proof_subset.push(node_bytes.to_vec())
+        }
+    }
+
+    let path_nibbles = NibblePath::init(&path);
+    let nibbles_to_target = path_nibbles.traversal_to_index(traversal_index)?;
+    Ok((nibbles_to_target.to_vec(), proof_subset))
+}
+
+/// Detects if an account went (pre- and post- block) from present to absent. That is, the
+/// account was removed by SELFDESTRUCT or by being touched while empty (EIP-161).
+fn account_removed(pre: &EIP1186ProofResponse, post: &EIP1186ProofResponse) -> bool {
+    !account_is_empty(pre) && account_is_empty(post)
+}
+
+/// An account with no code, zero nonce and zero balance. Absent accounts appear this way
+/// in an EIP-1186 proof.
+fn account_is_empty(account: &EIP1186ProofResponse) -> bool {
+    let no_code = account.code_hash.is_zero() || account.code_hash == H256(keccak256([]));
+    account.nonce.is_zero() && account.balance.is_zero() && no_code
+}
+
 /// Detects if storage went (pre- and post- block) from absent to present, or from present to absent. That is, from exclusion proof to inclusion proof or vice versa.
 fn storage_created_or_destroyed(val_pre: &U256, val_post: &U256) -> bool {
     let e_to_i = val_pre.is_zero() && !val_post.is_zero();
@@ -273,4 +340,18 @@ mod test {
         assert!(!storage_created_or_destroyed(&one, &one));
         assert!(!storage_created_or_destroyed(&one, &two));
     }
+
+    #[test]
+    fn test_account_removed() {
+        let absent = EIP1186ProofResponse::default();
+        let mut present = EIP1186ProofResponse::default();
+        present.balance = U256::from(1);
+        assert!(account_removed(&present, &absent));
+        assert!(!account_removed(&absent, &present));
+        assert!(!account_removed(&present, &present));
+        // Empty code hash is equivalent to a zero code hash.
+        let mut empty = EIP1186ProofResponse::default();
+        empty.code_hash = H256(keccak256([]));
+        assert!(!account_removed(&empty, &absent));
+    }
 }
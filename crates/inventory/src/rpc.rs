@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use ethers::types::{Block, EIP1186ProofResponse, Transaction};
+use ethers::types::{Block, EIP1186ProofResponse, Transaction, H256, U64};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
@@ -34,6 +34,20 @@ pub(crate) struct BlockResponse {
     pub(crate) result: Block<Transaction>,
 }
 
+#[derive(Deserialize, Serialize)]
+pub(crate) struct UncleResponse {
+    id: u32,
+    jsonrpc: String,
+    pub(crate) result: Block<H256>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ChainIdResponse {
+    id: u32,
+    jsonrpc: String,
+    pub(crate) result: U64,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct TxPrestateResponse {
     id: u32,
@@ -86,6 +100,27 @@ pub(crate) fn get_block_by_number(block: &str) -> JsonRpcRequest {
     }
 }
 
+/// Generates a JSON-RPC request for eth_getUncleByBlockNumberAndIndex for
+/// the specified block and uncle index.
+pub(crate) fn get_uncle_by_block_number_and_index(block: &str, index: usize) -> JsonRpcRequest {
+    JsonRpcRequest {
+        jsonrpc: "2.0".to_owned(),
+        method: "eth_getUncleByBlockNumberAndIndex".to_owned(),
+        params: vec![json!(block), json!(format!("0x{:x}", index))],
+        id: 1,
+    }
+}
+
+/// Generates a JSON-RPC request for eth_chainId.
+pub(crate) fn eth_chain_id() -> JsonRpcRequest {
+    JsonRpcRequest {
+        jsonrpc: "2.0".to_owned(),
+        method: "eth_chainId".to_owned(),
+        params: vec![],
+        id: 1,
+    }
+}
+
 /// Generates a JSON-RPC request for eth_getProof for
 /// the given account and storage slots at the specified block.
 pub(crate) fn eth_get_proof(account: &AccountToProve, block_number: &str) -> JsonRpcRequest {
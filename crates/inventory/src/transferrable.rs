@@ -112,7 +112,7 @@ fn get_compact_storage_proofs(
     for storage_proof in storage_proofs {
         // key, value
         let compact_storage_proof = CompactStorageProof {
-            key: h256_to_ssz_h256(storage_proof.key)?,
+            key: h256_to_ssz_h256(H256::from(<[u8; 32]>::from(storage_proof.key)))?,
             value: u256_to_ssz_u256(storage_proof.value),
         };
         compact_storage_proofs.push(compact_storage_proof);
@@ -4,7 +4,7 @@ use std::{collections::HashMap, fmt::Display};
 use ethers::types::{EIP1186ProofResponse, H160, H256, U64};
 use serde::{Deserialize, Serialize};
 
-use crate::rpc::BlockPrestateTransactions;
+use crate::{rpc::BlockPrestateTransactions, utils::hex_encode};
 
 /// Helper for caching
 #[derive(Deserialize, Serialize)]
@@ -155,6 +155,37 @@ impl BlockStateAccesses {
         }
         self
     }
+    /// Adds an account whose state changes outside of transaction execution (e.g., a block
+    /// reward recipient), if the account has not been accessed by a transaction.
+    ///
+    /// Only the account is proven (no storage). The balance is not observed by a
+    /// tracer and so is recorded as zero.
+    pub fn include_account(&mut self, address: H160) -> &mut Self {
+        self.access_data
+            .entry(hex_encode(address))
+            .or_insert_with(|| AccountState {
+                balance: String::from("0x0"),
+                code: None,
+                nonce: None,
+                storage: None,
+            });
+        self
+    }
+    /// Adds a storage slot whose value changes outside of transaction execution (e.g., a
+    /// system contract write), if the slot has not been accessed by a transaction.
+    ///
+    /// The value is not observed by a tracer and so is recorded as zero.
+    pub fn include_storage(&mut self, address: H160, key: H256) -> &mut Self {
+        self.include_account(address);
+        if let Some(account) = self.access_data.get_mut(&hex_encode(address)) {
+            account
+                .storage
+                .get_or_insert_with(HashMap::new)
+                .entry(hex_encode(key))
+                .or_insert_with(|| String::from("0x0"));
+        }
+        self
+    }
     /// Returns a vector of accounts with storage slots that can be used to query
     /// eth_getProof for a specific block.
     ///
@@ -226,6 +257,8 @@ fn include_unseen_states(existing: &AccountState, accessed_state: &AccountState)
 
 #[cfg(test)]
 mod tests {
+    use archors_types::beacon::{BEACON_ROOTS_ADDRESS, HISTORY_BUFFER_LENGTH};
+
     use super::*;
 
     fn dummy_state_1() -> AccountState {
@@ -302,4 +335,68 @@ mod tests {
         assert_eq!(result.nonce, Some(2));
         assert_eq!(result.storage.unwrap().len(), 1);
     }
+    /// Tests that accounts without transaction accesses are included without altering
+    /// accessed accounts.
+    #[test]
+    fn test_include_account() {
+        let accessed = H160::from_low_u64_be(1);
+        let unaccessed = H160::from_low_u64_be(2);
+        let mut tx_states = TransactionAccountStates::new();
+        tx_states.insert(hex_encode(accessed), dummy_state_1());
+        let mut accesses = BlockStateAccesses::new();
+        accesses
+            .include_new_state_accesses_for_tx(&tx_states)
+            .include_account(accessed)
+            .include_account(unaccessed);
+        assert_eq!(accesses.access_data.len(), 2);
+        assert_eq!(
+            accesses.access_data.get(&hex_encode(accessed)),
+            Some(&dummy_state_1())
+        );
+        assert!(accesses.access_data.contains_key(&hex_encode(unaccessed)));
+    }
+    /// Tests that the beacon roots contract slots read by transactions and the slots
+    /// written by the block are both proven, so that roots are read from proven storage.
+    #[test]
+    fn test_beacon_root_slots_are_proven() {
+        let contract = H160(BEACON_ROOTS_ADDRESS.0 .0);
+        let read_timestamp = 1_710_338_123u64;
+        let block_timestamp = 1_710_338_135u64;
+        let slot = |n: u64| hex_encode(H256::from_low_u64_be(n));
+        let read_slot = read_timestamp % HISTORY_BUFFER_LENGTH;
+        let mut storage = HashMap::new();
+        storage.insert(slot(read_slot), slot(read_timestamp));
+        storage.insert(slot(read_slot + HISTORY_BUFFER_LENGTH), slot(9));
+        let mut tx_states = TransactionAccountStates::new();
+        tx_states.insert(
+            hex_encode(contract),
+            AccountState {
+                balance: "0x0".to_string(),
+                code: None,
+                nonce: None,
+                storage: Some(storage),
+            },
+        );
+        let mut accesses = BlockStateAccesses::new();
+        accesses.include_new_state_accesses_for_tx(&tx_states);
+        let block_slot = block_timestamp % HISTORY_BUFFER_LENGTH;
+        accesses
+            .include_storage(contract, H256::from_low_u64_be(block_slot))
+            .include_storage(
+                contract,
+                H256::from_low_u64_be(block_slot + HISTORY_BUFFER_LENGTH),
+            );
+        let accounts = accesses.get_all_accounts_to_prove();
+        assert_eq!(accounts.len(), 1);
+        let mut proven = accounts[0].slots.clone();
+        proven.sort();
+        let mut expected = vec![
+            slot(read_slot),
+            slot(read_slot + HISTORY_BUFFER_LENGTH),
+            slot(block_slot),
+            slot(block_slot + HISTORY_BUFFER_LENGTH),
+        ];
+        expected.sort();
+        assert_eq!(proven, expected);
+    }
 }
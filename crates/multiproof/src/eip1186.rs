@@ -12,8 +12,8 @@ use ethers::types::{EIP1186ProofResponse, H160, H256, U256 as eU256, U64};
 use ethers::utils::keccak256;
 use log::{debug, info};
 use revm::primitives::{
-    Account, AccountInfo, Bytecode, BytecodeState, Bytes, HashMap as rHashMap, StorageSlot, B160,
-    B256, U256,
+    Account, AccountInfo, Address, Bytecode, BytecodeState, Bytes, HashMap as rHashMap,
+    StorageSlot, B256, U256,
 };
 use rlp::Encodable;
 use rlp_derive::{RlpDecodable, RlpEncodable};
@@ -21,7 +21,7 @@ use serde::Deserialize;
 use thiserror::Error;
 
 use crate::oracle::{OracleTask, TaskType};
-use crate::proof::ProofOutcome;
+use crate::proof::{empty_trie_root, ProofOutcome};
 use crate::utils::hex_encode;
 use crate::{
     proof::{Intent, MultiProof, ProofError},
@@ -102,7 +102,7 @@ impl EIP1186MultiProof {
                 // Storage for account
                 storage_multiproof.insert_proof(storage_proof.proof)?;
                 acc_storage.push(StorageData {
-                    key: storage_proof.key,
+                    key: H256::from(<[u8; 32]>::from(storage_proof.key)),
                     value: storage_proof.value,
                 });
             }
@@ -171,12 +171,11 @@ impl EIP1186MultiProof {
     /// Returns the updated state root.
     fn update_account_proof(
         &mut self,
-        address: &B160,
+        address: &Address,
         account: AccountData,
     ) -> Result<H256, MultiProofError> {
         let path = keccak256(address);
-        // Even if SELFDESCTRUCT is used, Intent::Remove is not used because the account is kept
-        // in the trie (with null storage/code hashes).
+        // Removed accounts (SELFDESTRUCT, EIP-161) use remove_account() instead.
         let intent = Intent::Modify(account.rlp_bytes().into());
         self.account_proofs
             .traverse(path.into(), &intent)
@@ -186,6 +185,59 @@ impl EIP1186MultiProof {
             })?;
         Ok(self.current_state_root())
     }
+    /// Removes an account from the account multiproof (SELFDESTRUCT or EIP-161 removal of
+    /// an empty account). The account storage is discarded.
+    ///
+    /// Returns the updated state root, or if the removal requires an oracle lookup, the
+    /// traversal index for the oracle task.
+    pub fn remove_account(&mut self, address: &H160) -> Result<ProofOutcome, MultiProofError> {
+        debug!(
+            "Account removal started for address {}",
+            hex_encode(address)
+        );
+        self.accounts.remove(address);
+        self.storage_proofs.remove(address);
+        self.storage.remove(address);
+        let path = keccak256(address);
+        self.account_proofs
+            .traverse(path.into(), &Intent::Remove)
+            .map_err(|e| MultiProofError::AccountProofError {
+                source: e,
+                address: hex_encode(address),
+            })?;
+        Ok(
+            match self.account_proofs.traversal_index_for_oracle_task.take() {
+                Some(index) => ProofOutcome::IndexForOracle(index),
+                None => ProofOutcome::Root(self.current_state_root()),
+            },
+        )
+    }
+    /// Completes account trie oracle tasks (address, traversal index) that arose from
+    /// account removals. Returns the updated state root.
+    ///
+    /// Should be called after all other account changes have been applied.
+    pub fn complete_account_oracle_tasks(
+        &mut self,
+        mut tasks: Vec<(H160, usize)>,
+    ) -> Result<H256, MultiProofError> {
+        // Start with oracle tasks with deepest traversal depth. This prevents tasks from clashing.
+        tasks.sort_by_key(|x| x.1);
+        let task_count = tasks.len();
+        for (index, (address, traversal_index)) in tasks.into_iter().rev().enumerate() {
+            debug!(
+                "Starting account oracle task for {} ({} of {})",
+                hex_encode(address),
+                index + 1,
+                task_count
+            );
+            self.account_proofs.traverse_account_oracle_update(
+                address,
+                traversal_index,
+                &self.node_oracle,
+            )?;
+        }
+        Ok(self.current_state_root())
+    }
     /// Accepts all changes for a single account returned from REVM and returns the
     /// updated state root.
     ///
@@ -193,11 +245,17 @@ impl EIP1186MultiProof {
     /// 2. Use storage hash and update account
     /// 3. Return state root.
     ///
+    /// Account flags are interpreted as follows:
+    /// - `is_selfdestructed()`: The account is removed from the trie.
+    /// - `is_created()`: The storage trie is rebuilt starting from an empty trie (account
+    ///   removed and then created again).
+    /// - `is_touched()`: An account absent from the pre-state is added, even if empty.
+    ///
     // Data outside the proofs is not updated because it is not required.
     // Though this is technically feasible.
     pub fn apply_account_delta(
         &mut self,
-        address: &B160,
+        address: &Address,
         account_updates: Account,
     ) -> Result<H256, MultiProofError> {
         debug!(
@@ -205,12 +263,28 @@ impl EIP1186MultiProof {
             hex_encode(address)
         );
         let address_eh = rb160_to_eh160(address);
-        let existing_account = self
-            .accounts
-            .get(&address_eh)
-            .ok_or_else(|| MultiProofError::NoAccount(hex_encode(address)))?
-            .clone();
+        if account_updates.is_selfdestructed() {
+            return match self.remove_account(&address_eh)? {
+                ProofOutcome::Root(root) => Ok(root),
+                ProofOutcome::IndexForOracle(traversal_index) => {
+                    self.complete_account_oracle_tasks(vec![(address_eh, traversal_index)])
+                }
+            };
+        }
+        // Pre-EIP-161, a touched account that did not exist is added even if empty.
+        let insert_empty = !self.accounts.contains_key(&address_eh) && account_updates.is_touched();
+        let existing_account = match self.accounts.get(&address_eh) {
+            Some(account) => account.clone(),
+            // E.g., a contract deployed during the block.
+            None => self.insert_empty_account(address_eh),
+        };
         let mut storage_hash = existing_account.storage_hash;
+        if account_updates.is_created() && storage_hash != empty_trie_root() {
+            // Prior storage no longer exists.
+            storage_hash = empty_trie_root();
+            self.storage_proofs
+                .insert(address_eh, MultiProof::init(storage_hash));
+        }
         let mut tasks: Vec<OracleTask> = vec![];
         // Sort keys before applying oracle update. This is for consistency with testing and
         // to prevent oracle misses for complex trie rearrangements. It may not be necessary.
@@ -265,9 +339,9 @@ impl EIP1186MultiProof {
             nonce: account_updates.info.nonce.into(),
             balance: account_updates.info.balance,
             storage_hash,
-            code_hash: account_updates.info.code_hash.into(),
+            code_hash: H256::from(account_updates.info.code_hash.0),
         };
-        match updated_account.eq(&existing_account) {
+        match updated_account.eq(&existing_account) && !insert_empty {
             true => Ok(self.current_state_root()),
             false => {
                 let state_root = self.update_account_proof(address, updated_account)?;
@@ -275,6 +349,19 @@ impl EIP1186MultiProof {
             }
         }
     }
+    /// Adds an account that is absent from the pre-state (nonce 0, balance 0, no storage,
+    /// no code), with an empty storage trie. Returns the new account.
+    ///
+    /// The account proof must still contain the exclusion proof for the address for the
+    /// account to be inserted into the state trie.
+    fn insert_empty_account(&mut self, address: H160) -> AccountData {
+        let account = AccountData::empty();
+        self.accounts.insert(address, account.clone());
+        self.storage_proofs
+            .insert(address, MultiProof::init(account.storage_hash));
+        self.storage.insert(address, vec![]);
+        account
+    }
     /// Verifies that every key present in this multiproof is valid with respect to the
     /// root.
     pub fn check_every_key() -> Result<(), MultiProofError> {
@@ -300,6 +387,18 @@ pub struct AccountData {
     pub code_hash: H256,
 }
 
+impl AccountData {
+    /// An account that is not in the state trie: no storage and no code.
+    pub fn empty() -> Self {
+        AccountData {
+            nonce: U64::zero(),
+            balance: U256::ZERO,
+            storage_hash: empty_trie_root(),
+            code_hash: H256::from(keccak256([])),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct StorageData {
     pub key: H256,
@@ -307,7 +406,7 @@ pub struct StorageData {
 }
 
 impl StateForEvm for EIP1186MultiProof {
-    fn get_account_info(&self, address: &B160) -> Result<AccountInfo, EvmStateError> {
+    fn get_account_info(&self, address: &Address) -> Result<AccountInfo, EvmStateError> {
         let acc = self
             .accounts
             .get(&rb160_to_eh160(address))
@@ -316,10 +415,9 @@ impl StateForEvm for EIP1186MultiProof {
         let info = AccountInfo {
             balance: acc.balance,
             nonce: acc.nonce.as_u64(),
-            code_hash: acc.code_hash.into(),
+            code_hash: B256::from(acc.code_hash.0),
             code: self.code.get(&acc.code_hash).map(|code| Bytecode {
                 bytecode: Bytes::copy_from_slice(code),
-                hash: B256::from(acc.code_hash.0),
                 state: BytecodeState::Raw,
             }),
         };
@@ -327,11 +425,17 @@ impl StateForEvm for EIP1186MultiProof {
         Ok(info)
     }
 
-    fn addresses(&self) -> Vec<B160> {
-        self.accounts.keys().map(|key| B160::from(*key)).collect()
+    fn addresses(&self) -> Vec<Address> {
+        self.accounts
+            .keys()
+            .map(|key| Address::from(key.0))
+            .collect()
     }
 
-    fn get_account_storage(&self, address: &B160) -> Result<rHashMap<U256, U256>, EvmStateError> {
+    fn get_account_storage(
+        &self,
+        address: &Address,
+    ) -> Result<rHashMap<U256, U256>, EvmStateError> {
         let mut storage_map = rHashMap::new();
         if let Some(storage) = self.storage.get(&rb160_to_eh160(address)) {
             for entry in storage {
@@ -356,20 +460,43 @@ impl StateForEvm for EIP1186MultiProof {
 
     fn state_root_post_block(
         &mut self,
-        changes: HashMap<B160, Account>,
+        changes: HashMap<Address, Account>,
     ) -> Result<B256, EvmStateError> {
         // Sort by address for debugging reliability. TODO remove if not needed or use BTreeMap.
-        let mut changes: Vec<(B160, Account)> = changes.into_iter().collect();
+        let mut changes: Vec<(Address, Account)> = changes.into_iter().collect();
         changes.sort_by_key(|x| x.0);
+        // Removals are applied last because they may require the oracle, after which the
+        // affected part of the trie can no longer be traversed.
+        let (removed, modified): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .partition(|(_, account)| account.is_selfdestructed());
         let mut root = self.account_proofs.root;
-        for (address, account_updates) in changes.into_iter() {
+        for (address, account_updates) in modified.into_iter() {
             root = self
                 .apply_account_delta(&address, account_updates)
                 .map_err(|e| EvmStateError::PostRoot(e.to_string()))?;
         }
+        let mut tasks: Vec<(H160, usize)> = vec![];
+        for (address, _) in removed.into_iter() {
+            let address = rb160_to_eh160(&address);
+            match self
+                .remove_account(&address)
+                .map_err(|e| EvmStateError::PostRoot(e.to_string()))?
+            {
+                ProofOutcome::Root(hash) => root = hash,
+                ProofOutcome::IndexForOracle(traversal_index) => {
+                    tasks.push((address, traversal_index))
+                }
+            }
+        }
+        if !tasks.is_empty() {
+            root = self
+                .complete_account_oracle_tasks(tasks)
+                .map_err(|e| EvmStateError::PostRoot(e.to_string()))?;
+        }
 
         info!("Post-execution state root computed");
-        Ok(B256::from(root))
+        Ok(B256::from(root.0))
     }
 
     fn print_account_proof<T: AsRef<str>>(
@@ -419,7 +546,7 @@ mod test {
     use archors_verify::path::{NibblePath, TargetNodeEncoding};
     use ethers::types::H256;
 
-    use revm::primitives::{HashMap as rHashMap, StorageSlot};
+    use revm::primitives::{AccountStatus, HashMap as rHashMap, StorageSlot};
 
     use crate::{proof::Node, utils::hex_decode, EIP1186MultiProof};
     fn load_proof(path: &str) -> EIP1186MultiProof {
@@ -491,7 +618,7 @@ mod test {
             U256::from_str("0x0000000000000000000000000000000000000000000000000000000000000001")
                 .unwrap(),
             StorageSlot {
-                original_value: U256::from_str("0").unwrap(),
+                previous_or_original_value: U256::from_str("0").unwrap(),
                 present_value: U256::from_str("0").unwrap(),
             },
         );
@@ -506,13 +633,10 @@ mod test {
                 code: None,
             },
             storage: storage_update,
-            storage_cleared: false,
-            is_destroyed: false,
-            is_touched: false,
-            is_not_existing: false,
+            status: AccountStatus::Loaded,
         };
         let post_root = proof
-            .apply_account_delta(&B160::from_str(address).unwrap(), account_updates)
+            .apply_account_delta(&Address::from_str(address).unwrap(), account_updates)
             .unwrap();
         // Check the root fetcher returns the same new value.
         assert_eq!(post_root, proof.current_state_root());
@@ -574,15 +698,12 @@ mod test {
                 code: None,
             },
             storage: rHashMap::default(),
-            storage_cleared: false,
-            is_destroyed: false,
-            is_touched: false,
-            is_not_existing: false,
+            status: AccountStatus::Loaded,
         };
         let mut proof = load_proof_str(PROOF_1);
         let address = "aa00000000000000000000000000000000000000";
         let post_root = proof
-            .apply_account_delta(&B160::from_str(address).unwrap(), account_updates)
+            .apply_account_delta(&Address::from_str(address).unwrap(), account_updates)
             .unwrap();
         // Check the computed root matches the manual calcuation.
         assert_eq!(
@@ -675,7 +796,7 @@ mod test {
             U256::from_str("0x0000000000000000000000000000000000000000000000000000000000000001")
                 .unwrap(),
             StorageSlot {
-                original_value: U256::from_str("0x0").unwrap(),
+                previous_or_original_value: U256::from_str("0x0").unwrap(),
                 present_value: U256::from_str("0x7").unwrap(),
             },
         );
@@ -690,16 +811,13 @@ mod test {
                 code: None,
             },
             storage, // updated
-            storage_cleared: false,
-            is_destroyed: false,
-            is_touched: false,
-            is_not_existing: false,
+            status: AccountStatus::Loaded,
         };
         let mut proof = load_proof_str(PROOF_1);
         let address = "aa00000000000000000000000000000000000000";
         // Use the update function to compute the new root.
         let post_root = proof
-            .apply_account_delta(&B160::from_str(address).unwrap(), account_updates)
+            .apply_account_delta(&Address::from_str(address).unwrap(), account_updates)
             .unwrap();
         // Check the computed root matches the manual calcuation.
         assert_eq!(
@@ -731,7 +849,7 @@ mod test {
         storage_update.insert(
             U256::from_str("0").unwrap(),
             StorageSlot {
-                original_value: U256::from_str("0").unwrap(),
+                previous_or_original_value: U256::from_str("0").unwrap(),
                 present_value: U256::from_str("0").unwrap(),
             },
         );
@@ -746,13 +864,10 @@ mod test {
                 code: None,
             },
             storage: storage_update,
-            storage_cleared: false,
-            is_destroyed: false,
-            is_touched: false,
-            is_not_existing: false,
+            status: AccountStatus::Loaded,
         };
         let post_root = proof
-            .apply_account_delta(&B160::from_str(address).unwrap(), account_updates)
+            .apply_account_delta(&Address::from_str(address).unwrap(), account_updates)
             .unwrap();
         // Check the root fetcher returns the same new value.
         assert_eq!(post_root, proof.current_state_root());
@@ -812,4 +927,64 @@ mod test {
     fn test_root_after_storage_change_requiring_oracle() {
         todo!()
     }
+
+    #[test]
+    fn test_empty_account_data() {
+        let account = AccountData::empty();
+        assert_eq!(
+            account.storage_hash,
+            H256::from_str("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+                .unwrap()
+        );
+        assert_eq!(
+            account.code_hash,
+            H256::from_str("0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_storage_update_for_account_absent_from_pre_state() {
+        let mut proof = load_proof_str(PROOF_1);
+        let address = H160::from_low_u64_be(0xbb);
+        proof.insert_empty_account(address);
+        let outcome = proof
+            .update_storage_proof(&address, H256::zero(), eU256::from(1))
+            .unwrap();
+        match outcome {
+            ProofOutcome::Root(root) => assert_ne!(root, empty_trie_root()),
+            ProofOutcome::IndexForOracle(_) => panic!("Oracle not required for empty trie"),
+        }
+    }
+
+    #[test]
+    fn test_destroyed_account_removed_from_trie() {
+        let mut proof = load_proof_str(PROOF_1);
+        let address = "aa00000000000000000000000000000000000000";
+        let account_updates = Account {
+            info: AccountInfo::default(),
+            storage: rHashMap::default(),
+            status: AccountStatus::SelfDestructed | AccountStatus::Touched,
+        };
+        // Root branch has children at 0x5, 0x7 and 0x8. The account leaf (path 0x7335...)
+        // is removed, leaving a branch with two children.
+        let mut items: Vec<Vec<u8>> = (0..17).map(|_| vec![]).collect();
+        items[5] = hex_decode("0xa2bd2175aed7ed88ed854c914fab94115c092ffb3c3c2ef647b70b7e73e33458")
+            .unwrap();
+        items[8] = hex_decode("0xc4bdbdbb240f8343b7f84bc83d4b7426e803a914138792d1e369907be8098b2d")
+            .unwrap();
+        let expected_root = keccak256(Node::try_from(items).unwrap().to_rlp_list());
+
+        let post_root = proof
+            .apply_account_delta(&Address::from_str(address).unwrap(), account_updates)
+            .unwrap();
+        assert_eq!(post_root, H256::from(expected_root));
+        let address = H160::from_str(address).unwrap();
+        assert!(proof.accounts.get(&address).is_none());
+        assert!(proof.storage_proofs.get(&address).is_none());
+        proof
+            .account_proofs
+            .traverse(keccak256(address).into(), &Intent::VerifyExclusion)
+            .unwrap();
+    }
 }
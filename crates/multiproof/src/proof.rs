@@ -12,7 +12,7 @@ use archors_verify::{
     },
 };
 use ethers::{
-    types::{Bytes, H256, U256},
+    types::{Bytes, H160, H256, U256},
     utils::keccak256,
 };
 use log::debug;
@@ -181,6 +181,9 @@ impl MultiProof {
         path: H256,
         intent: &Intent,
     ) -> Result<Vec<VisitedNode>, ProofError> {
+        if self.root == empty_trie_root() {
+            return self.traverse_empty_trie(path, intent);
+        }
         let mut traversal = NibblePath::init(path.as_bytes());
         let mut next_node_hash = self.root;
         let mut visited_nodes: Vec<VisitedNode> = vec![];
@@ -330,6 +333,33 @@ impl MultiProof {
             }
         }
     }
+    /// Traverse a path in a trie that has no keys (the root is the empty trie root).
+    ///
+    /// A modification makes a single leaf, containing the full path, that becomes the root.
+    fn traverse_empty_trie(
+        &mut self,
+        path: H256,
+        intent: &Intent,
+    ) -> Result<Vec<VisitedNode>, ProofError> {
+        match intent {
+            Intent::Modify(new_rlp_value) => {
+                let leaf_path = NibblePath::init(path.as_bytes()).get_encoded_path(
+                    TargetNodeEncoding::Leaf,
+                    0,
+                    63,
+                )?;
+                let leaf_rlp =
+                    Node::try_from(vec![leaf_path, new_rlp_value.clone()])?.to_rlp_list();
+                let leaf_hash = keccak256(&leaf_rlp);
+                self.data.insert(leaf_hash.into(), leaf_rlp);
+                self.root = leaf_hash.into();
+                Ok(vec![])
+            }
+            // Key already not in trie.
+            Intent::Remove | Intent::VerifyExclusion => Ok(vec![]),
+            Intent::VerifyInclusion(_) => Err(ProofError::InclusionRequired),
+        }
+    }
 
     /// Traverse a path with the goal of updating a specific node along the way.
     ///
@@ -352,13 +382,65 @@ impl MultiProof {
         task: OracleTask,
         oracle: &TrieNodeOracle,
     ) -> Result<(), ProofError> {
-        // Traverse the proof. Once the oracle-requiring node is reached, replace and cascade changes.
         let path: H256 = keccak256(task.key).into();
+        let task_description = task.to_string();
+        let intent = match task.purpose {
+            crate::oracle::TaskType::ForInclusion(rlp_val) => Intent::VerifyInclusion(rlp_val),
+            crate::oracle::TaskType::ForExclusion => Intent::VerifyExclusion,
+        };
+        self.apply_oracle_update(
+            path,
+            task.traversal_index,
+            intent,
+            task_description,
+            |traversal| oracle.lookup(task.address, traversal),
+        )
+    }
+    /// Traverse the account trie to update a node using the oracle. Used after an account is
+    /// removed from the trie (SELFDESTRUCT or EIP-161) and the parent branch of the account
+    /// leaf is also removed.
+    ///
+    /// See traverse_oracle_update() for the algorithm.
+    pub fn traverse_account_oracle_update(
+        &mut self,
+        address: H160,
+        traversal_index: usize,
+        oracle: &TrieNodeOracle,
+    ) -> Result<(), ProofError> {
+        let path: H256 = keccak256(address).into();
+        let task_description = format!(
+            "account trie oracle task for address {} and traversal index {}",
+            hex_encode(address),
+            traversal_index
+        );
+        self.apply_oracle_update(
+            path,
+            traversal_index,
+            Intent::VerifyExclusion,
+            task_description,
+            |traversal| oracle.lookup_account(traversal),
+        )
+    }
+    /// Replaces the node at the traversal index with the node from the oracle, then updates
+    /// the nodes between it and the root. Finally, the path is traversed to verify the intent.
+    fn apply_oracle_update<F>(
+        &mut self,
+        path: H256,
+        traversal_index: usize,
+        intent: Intent,
+        task_description: String,
+        lookup: F,
+    ) -> Result<(), ProofError>
+    where
+        F: Fn(Vec<u8>) -> Option<Vec<Vec<u8>>>,
+    {
+        // Traverse the proof. Once the oracle-requiring node is reached, replace and cascade changes.
         let mut traversal = NibblePath::init(path.as_bytes());
         let mut visited_nodes: Vec<VisitedNode> = vec![];
         let mut next_node_hash = self.root;
-        // Gather the list of nodes that require updating.
-        loop {
+        // Gather the list of nodes that require updating. If the root is the node to be
+        // replaced, there are none.
+        while traversal.visiting_index() < traversal_index {
             let next_node_rlp = self
                 .data
                 .get(&next_node_hash)
@@ -408,20 +490,15 @@ impl MultiProof {
                 }
                 NodeKind::Leaf => return Err(ProofError::LeafInOracleTask),
             }
-            if traversal.visiting_index() >= task.traversal_index {
-                // Traversal has reached the node to be replaced.
-                break;
-            }
         }
         // Consult the oracle.
         let traversal_for_oracle = traversal
             .history_with_next()
             .map_err(|_| ProofError::NoTraversalHistory)?;
 
-        let oracle_nodes: Vec<Vec<u8>> = oracle
-            .lookup(task.address, traversal_for_oracle.to_owned())
-            .ok_or_else(|| ProofError::NoNodeInOracle {
-                task: task.to_string(),
+        let oracle_nodes: Vec<Vec<u8>> =
+            lookup(traversal_for_oracle).ok_or_else(|| ProofError::NoNodeInOracle {
+                task: task_description,
             })?;
         let oracle_node = oracle_nodes
             .first()
@@ -435,10 +512,7 @@ impl MultiProof {
             updated_hash = self.update_node_with_child_hash(outdated, &updated_hash)?;
         }
         // Update the storage root.
-        debug!(
-            "storage root updated via oracle to {}",
-            hex_encode(updated_hash)
-        );
+        debug!("root updated via oracle to {}", hex_encode(updated_hash));
         self.root = updated_hash.into();
 
         // Add nodes to the proof map.
@@ -448,10 +522,6 @@ impl MultiProof {
         }
         // Finally finish the traversal, demonstrating that the key is removed from the trie.
         // The traversal should now have enough information now that the oracle update is complete.
-        let intent = match task.purpose {
-            crate::oracle::TaskType::ForInclusion(rlp_val) => Intent::VerifyInclusion(rlp_val),
-            crate::oracle::TaskType::ForExclusion => Intent::VerifyExclusion,
-        };
         self.traverse(path, &intent)
             .map_err(|e| ProofError::PostOracleTraversalFailed(e.to_string()))?;
         Ok(())
@@ -609,6 +679,12 @@ impl MultiProof {
                 //   - If extension, remove that
                 //   - If branch, go to 1.
 
+                if visited.len() == 1 {
+                    // The leaf is the root, removing the only key leaves an empty trie.
+                    self.data.remove(&old_terminal_hash);
+                    self.root = empty_trie_root();
+                    return Ok(());
+                }
                 // Modify the parent (and higher ancestors if needed).
                 let (highest_hash, nodes_processed) = self.process_leaf_child_removal(visited)?;
 
@@ -616,8 +692,9 @@ impl MultiProof {
                 let mut updated_hash = highest_hash;
                 for outdated in visited.iter().rev().skip(nodes_processed) {
                     updated_hash = self.update_node_with_child_hash(outdated, &updated_hash)?;
-                    self.root = updated_hash.into();
                 }
+                // The root may itself have been processed as part of the removal.
+                self.root = updated_hash.into();
             }
         }
 
@@ -806,7 +883,8 @@ impl MultiProof {
         // Visit_record
         let leaf_visit_record_index = visit_record.len() - 1;
         let parent_visit_record_index = leaf_visit_record_index - 1;
-        let grandparent_visit_record_index = parent_visit_record_index - 1;
+        // Absent if the parent is the root.
+        let grandparent_visit_record_index = parent_visit_record_index.checked_sub(1);
 
         let parent = visit_record
             .get(parent_visit_record_index)
@@ -848,8 +926,17 @@ impl MultiProof {
                 // This may require oracle knowledge, so it is returned as a task.
 
                 // Need to attach this single item at some point.
+                let grandparent_index = match grandparent_visit_record_index {
+                    Some(index) => index,
+                    None => {
+                        // The parent is the root. The new root is obtained from the oracle.
+                        self.traversal_index_for_oracle_task = Some(0);
+                        debug!("Creating oracle task for the root node");
+                        return Ok((self.root.into(), visit_record.len()));
+                    }
+                };
                 let visited_grandparent = visit_record
-                    .get(grandparent_visit_record_index)
+                    .get(grandparent_index)
                     .ok_or(ModifyError::NoVisitedNode)?;
 
                 self.traversal_index_for_oracle_task =
@@ -1124,6 +1211,13 @@ pub enum Intent {
     VerifyExclusion,
 }
 
+/// The root of a trie with no keys, keccak(rlp("")).
+///
+/// Used for the storage root of accounts without storage.
+pub fn empty_trie_root() -> H256 {
+    keccak256(rlp::NULL_RLP).into()
+}
+
 /// Detects if an RLP encoded value is for an empty storage value or account.
 ///
 /// This is useful to ensure that an exclusion proof has not been requested to update to this
@@ -1301,4 +1395,42 @@ mod test {
             panic!("Expected post proof != Post proof")
         };
     }
+
+    #[test]
+    fn test_modify_key_in_empty_trie() {
+        let mut multi = MultiProof::init(empty_trie_root());
+        let path: H256 = keccak256(H256::from_low_u64_be(1)).into();
+        let value = slot_rlp_from_value(ru256::from(5));
+        multi.traverse(path, &Intent::VerifyExclusion).unwrap();
+        multi
+            .traverse(path, &Intent::Modify(value.clone()))
+            .unwrap();
+        assert_ne!(multi.root, empty_trie_root());
+        // The root is now the leaf.
+        let visited = multi
+            .traverse(path, &Intent::VerifyInclusion(value))
+            .unwrap();
+        assert_eq!(visited.len(), 1);
+        assert_eq!(visited[0].node_hash, multi.root);
+        // Modify the only leaf.
+        let updated_value = slot_rlp_from_value(ru256::from(6));
+        multi
+            .traverse(path, &Intent::Modify(updated_value.clone()))
+            .unwrap();
+        multi
+            .traverse(path, &Intent::VerifyInclusion(updated_value))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_remove_only_key_leaves_empty_trie() {
+        let mut multi = MultiProof::init(empty_trie_root());
+        let path: H256 = keccak256(H256::from_low_u64_be(1)).into();
+        let value = slot_rlp_from_value(ru256::from(5));
+        multi.traverse(path, &Intent::Modify(value)).unwrap();
+        assert_ne!(multi.root, empty_trie_root());
+        multi.traverse(path, &Intent::Remove).unwrap();
+        assert_eq!(multi.root, empty_trie_root());
+        multi.traverse(path, &Intent::VerifyExclusion).unwrap();
+    }
 }
@@ -0,0 +1,162 @@
+//! For producing the access list (EIP-2930) of a transaction from its execution.
+//!
+//! Every address and storage key that a transaction touches is recorded. The access list
+//! can be compared to the access list a transaction declares, or used to select the state
+//! a transaction needs.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use archors_types::utils::access_list_r_to_e;
+use ethers::types::transaction::eip2930::AccessList;
+use revm::{
+    interpreter::{opcode, CallInputs, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{Address, Bytes as rBytes, U256},
+    Database, EVMData, Inspector,
+};
+
+/// Records the addresses and storage keys accessed during execution.
+///
+/// Addresses are recorded when their account is read (E.g., BALANCE), called, created or
+/// sent a selfdestruct balance. Storage keys are recorded on SLOAD and SSTORE. The
+/// payment of fees to the block author after execution is not recorded.
+#[derive(Clone, Debug, Default)]
+pub struct AccessListInspector {
+    /// Storage keys accessed, by address.
+    accessed: BTreeMap<Address, BTreeSet<U256>>,
+    /// Addresses left out of the access list.
+    excluded: HashSet<Address>,
+}
+
+impl AccessListInspector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Leaves addresses out of the access list. Storage keys of the addresses are also
+    /// left out.
+    ///
+    /// E.g., geth `eth_createAccessList` leaves out the sender, recipient and precompiles,
+    /// which are accessed at the start of every transaction.
+    pub fn exclude<I>(&mut self, addresses: I) -> &mut Self
+    where
+        I: IntoIterator<Item = Address>,
+    {
+        self.excluded.extend(addresses);
+        self
+    }
+    /// The access list, ordered by address then storage key.
+    pub fn into_access_list(self) -> AccessList {
+        let list = self
+            .accessed
+            .into_iter()
+            .filter(|(address, _)| !self.excluded.contains(address))
+            .map(|(address, keys)| (address, keys.into_iter().collect()))
+            .collect();
+        access_list_r_to_e(list)
+    }
+    fn access_address(&mut self, address: Address) {
+        self.accessed.entry(address).or_default();
+    }
+    fn access_storage(&mut self, address: Address, key: U256) {
+        self.accessed.entry(address).or_default().insert(key);
+    }
+}
+
+impl<DB: Database> Inspector<DB> for AccessListInspector {
+    fn step(&mut self, interp: &mut Interpreter, _data: &mut EVMData<'_, DB>) -> InstructionResult {
+        let op = interp.current_opcode();
+        let Some(top) = interp.stack.data().last().copied() else {
+            return InstructionResult::Continue;
+        };
+        match op {
+            opcode::SLOAD | opcode::SSTORE => self.access_storage(interp.contract.address, top),
+            opcode::BALANCE | opcode::EXTCODESIZE | opcode::EXTCODECOPY | opcode::EXTCODEHASH => {
+                self.access_address(stack_address(top))
+            }
+            _ => {}
+        }
+        InstructionResult::Continue
+    }
+
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, rBytes) {
+        self.access_address(inputs.transfer.source);
+        self.access_address(inputs.context.address);
+        self.access_address(inputs.contract);
+        (InstructionResult::Continue, Gas::new(0), rBytes::new())
+    }
+
+    fn create(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<Address>, Gas, rBytes) {
+        self.access_address(inputs.caller);
+        (
+            InstructionResult::Continue,
+            None,
+            Gas::new(0),
+            rBytes::new(),
+        )
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: rBytes,
+    ) -> (InstructionResult, Option<Address>, Gas, rBytes) {
+        if let Some(created) = address {
+            self.access_address(created);
+        }
+        (ret, address, remaining_gas, out)
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, _value: U256) {
+        self.access_address(contract);
+        self.access_address(target);
+    }
+}
+
+/// An address held as a stack item (the low 20 bytes).
+fn stack_address(item: U256) -> Address {
+    Address::from_slice(&item.to_be_bytes::<32>()[12..])
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::{transaction::eip2930::AccessListItem, H160, H256};
+
+    use super::*;
+    use crate::test_utils::{caller, evm_calling};
+
+    /// Reads slot 1, stores slot 2, then reads the balance of 0xaa.
+    const CODE: [u8; 11] = [
+        0x60, 0x01, 0x54, 0x60, 0x02, 0x55, 0x60, 0xaa, 0x31, 0x50, 0x00,
+    ];
+
+    #[test]
+    fn test_access_list_inspector() {
+        let mut evm = evm_calling(&CODE);
+        let mut inspector = AccessListInspector::new();
+        let outcome = evm.inspect(&mut inspector).unwrap();
+        assert!(outcome.result.is_success());
+        inspector.exclude([caller()]);
+        let expected = AccessList(vec![
+            AccessListItem {
+                address: H160::from_low_u64_be(0xaa),
+                storage_keys: vec![],
+            },
+            AccessListItem {
+                address: H160::from_low_u64_be(0xbb),
+                storage_keys: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+            },
+        ]);
+        assert_eq!(inspector.into_access_list(), expected);
+    }
+}
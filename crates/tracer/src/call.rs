@@ -0,0 +1,373 @@
+//! For making calls against the state of a block (historical `eth_call` and
+//! `eth_estimateGas`).
+//!
+//! The transactions of the block before a given position are executed, then calls are
+//! made against the resulting state without being committed. Missing state is always
+//! an error, so a call that reads state absent from the block state data fails rather
+//! than returning a result computed from empty state.
+
+use archors_types::{
+    chain::ChainConfig,
+    execution::StateForEvm,
+    utils::{eu256_to_ru256, rb160_to_eh160, rb256_to_eh256, ru256_to_u64},
+};
+use ethers::types::{Block, Bytes, Log, Transaction, H160, U256, U64};
+use revm::{
+    db::CacheDB,
+    inspectors::NoOpInspector,
+    primitives::{Address, CreateScheme, ExecutionResult, TransactTo, TxEnv, U256 as rU256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    evm::{BlockEvm, EvmError},
+    lazy_state::LazyStateDb,
+    trace::{BlockExecutor, PostExecutionProof, TraceError},
+};
+
+/// Gas of a transaction that does nothing. A lower bound when estimating gas.
+const MIN_TX_GAS: u64 = 21_000;
+
+/// A call, as accepted by `eth_call` and `eth_estimateGas`.
+///
+/// Absent fields take the defaults of a node: the zero address as sender, the block gas
+/// limit as gas, zero gas price and zero value. Without a recipient a contract is created.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<H160>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<H160>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<U64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    #[serde(default, alias = "input", skip_serializing_if = "Option::is_none")]
+    pub data: Option<Bytes>,
+}
+
+/// The result of a call.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallOutcome {
+    pub success: bool,
+    /// Data returned, or the revert data if the call reverted.
+    pub output: Bytes,
+    pub gas_used: u64,
+    /// Logs emitted by a successful call. The logs are not part of a block, so have no
+    /// block, transaction or index values.
+    pub logs: Vec<Log>,
+    /// Reason the call failed (reverted or halted).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Holds an EVM with the state of a block at a position (before the transaction at that
+/// index), against which calls are made.
+///
+/// Calls are not committed, so any number of calls can be made against the same state.
+pub struct CallExecutor<T: StateForEvm> {
+    block_evm: BlockEvm<CacheDB<LazyStateDb<T>>>,
+    /// Index of the transaction that calls are made before.
+    position: usize,
+}
+
+impl<T: StateForEvm> CallExecutor<T> {
+    /// Loads the state of a mainnet block before the transaction at the given index.
+    ///
+    /// An index equal to the number of transactions gives the state after every
+    /// transaction.
+    pub fn load(
+        block: Block<Transaction>,
+        block_proofs: T,
+        position: usize,
+    ) -> Result<Self, TraceError> {
+        Self::load_with_config(block, block_proofs, position, ChainConfig::mainnet())
+    }
+    /// Loads the state of a block from the specified chain before the transaction at the
+    /// given index.
+    pub fn load_with_config(
+        block: Block<Transaction>,
+        block_proofs: T,
+        position: usize,
+        chain: ChainConfig,
+    ) -> Result<Self, TraceError> {
+        let mut executor = BlockExecutor::load_with_config(
+            block,
+            block_proofs,
+            PostExecutionProof::Ignore,
+            chain,
+        )?;
+        executor.set_strict_state(true);
+        let block_evm = executor.into_evm_before(position)?;
+        Ok(Self {
+            block_evm,
+            position,
+        })
+    }
+    /// Index of the transaction that calls are made before.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// Makes a call against the state, as `eth_call` does.
+    ///
+    /// A call that reverts or halts is an outcome, not an error. Errors are for calls
+    /// that cannot be made (E.g., insufficient balance for the value) or that read state
+    /// missing from the block state data.
+    pub fn call(&mut self, request: &CallRequest) -> Result<CallOutcome, TraceError> {
+        let gas = self.gas_limit(request);
+        let result = self
+            .execute(request, gas)
+            .map_err(|source| TraceError::CallError {
+                source,
+                index: self.position,
+            })?;
+        Ok(CallOutcome::from(result))
+    }
+    /// Estimates the gas required for a call to succeed, as `eth_estimateGas` does.
+    ///
+    /// The lowest gas limit at which the call succeeds is found by binary search, between
+    /// the gas used by the call and the gas of the request (or the block gas limit).
+    pub fn estimate_gas(&mut self, request: &CallRequest) -> Result<u64, TraceError> {
+        let mut high = self.gas_limit(request);
+        let gas_used = match self.attempt(request, high)? {
+            Ok(gas_used) => gas_used,
+            Err(error) => return Err(TraceError::GasEstimation { gas: high, error }),
+        };
+        // Gas used is after refunds, the call needs at least this much.
+        let mut low = gas_used.max(MIN_TX_GAS) - 1;
+        while low + 1 < high {
+            let mid = low + (high - low) / 2;
+            match self.attempt(request, mid)? {
+                Ok(_) => high = mid,
+                Err(_) => low = mid,
+            }
+        }
+        Ok(high)
+    }
+    /// Gas limit of a call, the block gas limit if the request does not have one.
+    fn gas_limit(&self, request: &CallRequest) -> u64 {
+        request
+            .gas
+            .map(|gas| gas.as_u64())
+            .unwrap_or_else(|| ru256_to_u64(self.block_evm.evm.env.block.gas_limit))
+    }
+    /// Makes a call with a gas limit. Returns the gas used if the call succeeds, or the
+    /// reason it failed. A call that is an invalid transaction (E.g., gas below intrinsic
+    /// gas) has failed, errors reading state are returned as errors.
+    fn attempt(
+        &mut self,
+        request: &CallRequest,
+        gas: u64,
+    ) -> Result<Result<u64, String>, TraceError> {
+        match self.execute(request, gas) {
+            Ok(result) => Ok(match CallOutcome::from(result) {
+                CallOutcome {
+                    success: true,
+                    gas_used,
+                    ..
+                } => Ok(gas_used),
+                outcome => Err(outcome.error.unwrap_or_default()),
+            }),
+            Err(EvmError::RevmError(error)) => Ok(Err(error)),
+            Err(source) => Err(TraceError::CallError {
+                source,
+                index: self.position,
+            }),
+        }
+    }
+    /// Executes a call with a gas limit without committing the state changes.
+    ///
+    /// A call without a gas price is free. So that it is not rejected for being below
+    /// the block base fee, the base fee is zero for the call.
+    fn execute(&mut self, request: &CallRequest, gas: u64) -> Result<ExecutionResult, EvmError> {
+        let tx = call_environment(request, gas)?;
+        let base_fee = self.block_evm.evm.env.block.basefee;
+        if tx.gas_price == rU256::ZERO {
+            self.block_evm.evm.env.block.basefee = rU256::ZERO;
+        }
+        let outcome = self
+            .block_evm
+            .add_call_environment(tx)
+            .and_then(|evm| evm.execute_without_commit(NoOpInspector {}));
+        self.block_evm.evm.env.block.basefee = base_fee;
+        Ok(outcome?.result)
+    }
+}
+
+/// Creates the transaction environment for a call. There is no nonce or chain id, so
+/// neither is checked.
+fn call_environment(request: &CallRequest, gas: u64) -> Result<TxEnv, EvmError> {
+    Ok(TxEnv {
+        caller: Address::from(request.from.unwrap_or_default().0),
+        gas_limit: gas,
+        gas_price: eu256_to_ru256(request.gas_price.unwrap_or_default())?,
+        gas_priority_fee: None,
+        transact_to: match request.to {
+            Some(to) => TransactTo::Call(Address::from(to.0)),
+            None => TransactTo::Create(CreateScheme::Create),
+        },
+        value: eu256_to_ru256(request.value.unwrap_or_default())?,
+        data: request.data.clone().unwrap_or_default().0.into(),
+        chain_id: None,
+        nonce: None,
+        access_list: vec![],
+        blob_hashes: vec![],
+        max_fee_per_blob_gas: None,
+    })
+}
+
+impl From<ExecutionResult> for CallOutcome {
+    fn from(result: ExecutionResult) -> Self {
+        let error = match &result {
+            ExecutionResult::Success { .. } => None,
+            ExecutionResult::Revert { .. } => Some(String::from("execution reverted")),
+            ExecutionResult::Halt { reason, .. } => Some(format!("{reason:?}")),
+        };
+        let logs = result
+            .logs()
+            .iter()
+            .map(|log| Log {
+                address: rb160_to_eh160(&log.address),
+                topics: log.topics.iter().map(|t| rb256_to_eh256(*t)).collect(),
+                data: Bytes::from(log.data.to_vec()),
+                ..Default::default()
+            })
+            .collect();
+        CallOutcome {
+            success: result.is_success(),
+            gas_used: result.gas_used(),
+            output: Bytes::from(result.into_output().unwrap_or_default().to_vec()),
+            logs,
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use archors_types::execution::EvmStateError;
+    use ethers::{
+        types::{EIP1186ProofResponse, StorageProof, H256},
+        utils::keccak256,
+    };
+
+    use super::*;
+    use crate::{
+        state::BlockProofsBasic,
+        test_utils::{block, recipient as contract, sender, sender_state, transfer},
+    };
+
+    /// Returns storage slot 0 and emits it in a log.
+    const CONTRACT_CODE: &str = "0x60005460005260206000a060206000f3";
+
+    /// A block with one transaction that sends value to the contract, where the contract
+    /// has slot 0 set to 7.
+    fn block_and_state() -> (Block<Transaction>, BlockProofsBasic) {
+        let mut state = sender_state();
+        let code = Bytes::from_str(CONTRACT_CODE).unwrap().to_vec();
+        let code_hash = H256::from(keccak256(&code));
+        state.code.insert(code_hash, code);
+        state.proofs.insert(
+            contract(),
+            EIP1186ProofResponse {
+                address: contract(),
+                code_hash,
+                storage_proof: vec![StorageProof {
+                    key: U256::zero(),
+                    value: 7.into(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+        let tx = Transaction {
+            gas: 100_000.into(),
+            ..transfer(1, 0, 4)
+        };
+        (block(10_000_000, vec![tx]), state)
+    }
+
+    #[test]
+    fn test_call_before_and_after_transaction() {
+        let (block, state) = block_and_state();
+        let mut caller = CallExecutor::load(block, state, 0).unwrap();
+        let request = CallRequest {
+            from: Some(sender()),
+            to: Some(contract()),
+            value: Some(9.into()),
+            ..Default::default()
+        };
+        let outcome = caller.call(&request).unwrap();
+        assert!(outcome.success);
+        assert_eq!(
+            outcome.output,
+            Bytes::from(H256::from_low_u64_be(7).0.to_vec())
+        );
+        assert_eq!(outcome.logs.len(), 1);
+        assert_eq!(outcome.logs[0].address, contract());
+        // Calls are not committed.
+        assert!(caller.call(&request).unwrap().success);
+        // After the transaction, the sender can no longer send 9.
+        let (block, state) = block_and_state();
+        let mut caller = CallExecutor::load(block, state, 1).unwrap();
+        assert!(matches!(
+            caller.call(&request),
+            Err(TraceError::CallError {
+                source: EvmError::RevmError(_),
+                index: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_call_missing_state_rejected() {
+        let (block, state) = block_and_state();
+        let mut caller = CallExecutor::load(block, state, 0).unwrap();
+        let request = CallRequest {
+            from: Some(sender()),
+            to: Some(H160::from_low_u64_be(0xaa)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            caller.call(&request),
+            Err(TraceError::CallError {
+                source: EvmError::StateError(EvmStateError::MissingAccount(_)),
+                index: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_estimate_gas() {
+        let (block, state) = block_and_state();
+        let mut caller = CallExecutor::load(block, state, 0).unwrap();
+        let request = CallRequest {
+            from: Some(sender()),
+            to: Some(contract()),
+            ..Default::default()
+        };
+        let gas = caller.estimate_gas(&request).unwrap();
+        let used = caller.call(&request).unwrap().gas_used;
+        assert_eq!(gas, used);
+        let too_little = CallRequest {
+            gas: Some((gas - 1).into()),
+            ..request
+        };
+        assert!(!caller.call(&too_little).unwrap().success);
+        assert!(matches!(
+            caller.estimate_gas(&too_little),
+            Err(TraceError::GasEstimation { .. })
+        ));
+        let (block, state) = block_and_state();
+        assert!(matches!(
+            CallExecutor::load(block, state, 2).err(),
+            Some(TraceError::TxIndexOutOfRange { index: 2, count: 1 })
+        ));
+    }
+}
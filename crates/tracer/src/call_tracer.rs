@@ -0,0 +1,432 @@
+//! For producing the trace of geth `debug_traceTransaction` with the `callTracer`.
+//!
+//! The trace is a tree of call frames, one for each call, create and selfdestruct made
+//! during the transaction.
+
+use archors_types::utils::{rb160_to_eh160, rb256_to_eh256, ru256_to_eu256};
+use ethers::{
+    abi::{decode, ParamType, Token},
+    types::{Bytes, H160, H256, U256, U64},
+};
+use revm::{
+    interpreter::{CallInputs, CallScheme, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{Address, Bytes as rBytes, CreateScheme, ExecutionResult, B256, U256 as rU256},
+    Database, EVMData, Inspector,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::struct_log::{op_name, returns_gas, step_error};
+
+/// Selector of `Error(string)`, used by solidity for revert reasons.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, used by solidity for failed assertions.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Options for the call tracer, as accepted by geth `debug_traceTransaction`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CallTracerConfig {
+    /// Only the frame of the transaction is produced, without nested calls.
+    pub only_top_call: bool,
+    /// Logs emitted in each frame are included.
+    pub with_log: bool,
+}
+
+/// A call, create or selfdestruct, with the frames of the calls it made.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    pub from: H160,
+    /// Gas available to the frame. For the transaction frame this is the gas limit of the
+    /// transaction.
+    pub gas: U64,
+    pub gas_used: U64,
+    /// The address called or created. Absent if a create failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<H160>,
+    pub input: Bytes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<CallLog>,
+    /// Value transferred. Absent for STATICCALL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    /// CALL, CALLCODE, DELEGATECALL, STATICCALL, CREATE, CREATE2 or SELFDESTRUCT.
+    #[serde(rename = "type")]
+    pub call_type: String,
+}
+
+/// A log emitted by a frame.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CallLog {
+    pub address: H160,
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+    /// Number of calls the frame had made when the log was emitted.
+    pub position: U64,
+}
+
+/// An inspector that records the call frames of a transaction.
+#[derive(Clone, Debug, Default)]
+pub struct CallTracer {
+    config: CallTracerConfig,
+    /// Frames that have been entered but not exited.
+    stack: Vec<ActiveFrame>,
+    /// The frame of the transaction, once exited.
+    root: Option<CallFrame>,
+    /// Gas available for execution of the transaction (gas limit less intrinsic gas).
+    root_gas_limit: u64,
+}
+
+/// A frame that is executing.
+#[derive(Clone, Debug)]
+struct ActiveFrame {
+    frame: CallFrame,
+    gas_limit: u64,
+    /// Most recent opcode executed in the frame.
+    last_op: u8,
+}
+
+impl CallTracer {
+    pub fn new(config: CallTracerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+    /// Combines the recorded frames with the result of the transaction.
+    pub fn into_trace(self, result: &ExecutionResult) -> CallFrame {
+        let mut root = self.root.unwrap_or_default();
+        root.gas_used = match result {
+            ExecutionResult::Success { gas_used, .. }
+            | ExecutionResult::Revert { gas_used, .. }
+            | ExecutionResult::Halt { gas_used, .. } => U64::from(*gas_used),
+        };
+        if self.config.only_top_call {
+            root.calls.clear();
+        }
+        clear_failed_logs(&mut root, false);
+        root
+    }
+    /// The frame of the transaction, along with the gas that was available for execution.
+    ///
+    /// Unlike into_trace(), the gas used by the frame excludes intrinsic gas and refunds.
+    pub(crate) fn into_execution_frame(self) -> Option<(CallFrame, u64)> {
+        self.root.map(|root| (root, self.root_gas_limit))
+    }
+    /// Starts a new frame. The transaction frame is given the gas limit of the transaction.
+    fn enter(&mut self, mut frame: CallFrame, gas_limit: u64, tx_gas_limit: u64) {
+        frame.gas = match self.stack.is_empty() {
+            true => {
+                self.root_gas_limit = gas_limit;
+                U64::from(tx_gas_limit)
+            }
+            false => U64::from(gas_limit),
+        };
+        self.stack.push(ActiveFrame {
+            frame,
+            gas_limit,
+            last_op: 0,
+        });
+    }
+    /// Completes the current frame and adds it to its parent.
+    fn exit(&mut self, result: InstructionResult, remaining: &Gas, output: &rBytes) {
+        let Some(active) = self.stack.pop() else {
+            return;
+        };
+        let mut frame = active.frame;
+        let output = (!output.is_empty()).then(|| Bytes::from(output.to_vec()));
+        frame.gas_used = match returns_gas(result) {
+            true => U64::from(active.gas_limit.saturating_sub(remaining.remaining())),
+            false => U64::from(active.gas_limit),
+        };
+        match result {
+            InstructionResult::Continue
+            | InstructionResult::Stop
+            | InstructionResult::Return
+            | InstructionResult::SelfDestruct => frame.output = output,
+            InstructionResult::Revert => {
+                frame.error = Some("execution reverted".to_string());
+                frame.revert_reason = output.as_ref().and_then(|out| revert_reason(out));
+                frame.output = output;
+            }
+            other => frame.error = Some(frame_error(other, active.last_op)),
+        }
+        if frame.error.is_some() && frame.call_type.starts_with("CREATE") {
+            frame.to = None;
+        }
+        self.add_to_parent(frame);
+    }
+    fn add_to_parent(&mut self, frame: CallFrame) {
+        match self.stack.last_mut() {
+            Some(parent) => parent.frame.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for CallTracer {
+    fn step(&mut self, interp: &mut Interpreter, _data: &mut EVMData<'_, DB>) -> InstructionResult {
+        let op = interp.current_opcode();
+        if let Some(active) = self.stack.last_mut() {
+            active.last_op = op;
+        }
+        InstructionResult::Continue
+    }
+
+    fn log(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        address: &Address,
+        topics: &[B256],
+        data: &rBytes,
+    ) {
+        if !self.config.with_log {
+            return;
+        }
+        if let Some(active) = self.stack.last_mut() {
+            active.frame.logs.push(CallLog {
+                address: rb160_to_eh160(address),
+                topics: topics.iter().map(|topic| rb256_to_eh256(*topic)).collect(),
+                data: Bytes::from(data.to_vec()),
+                position: U64::from(active.frame.calls.len()),
+            });
+        }
+    }
+
+    fn call(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, rBytes) {
+        let (call_type, value) = match inputs.context.scheme {
+            CallScheme::Call => ("CALL", Some(inputs.transfer.value)),
+            CallScheme::CallCode => ("CALLCODE", Some(inputs.transfer.value)),
+            CallScheme::DelegateCall => ("DELEGATECALL", Some(inputs.context.apparent_value)),
+            CallScheme::StaticCall => ("STATICCALL", None),
+        };
+        let frame = CallFrame {
+            from: rb160_to_eh160(&inputs.transfer.source),
+            to: Some(rb160_to_eh160(&inputs.contract)),
+            input: Bytes::from(inputs.input.to_vec()),
+            value: value.map(ru256_to_eu256),
+            call_type: call_type.to_string(),
+            ..Default::default()
+        };
+        self.enter(frame, inputs.gas_limit, data.env.tx.gas_limit);
+        (InstructionResult::Continue, Gas::new(0), rBytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: rBytes,
+    ) -> (InstructionResult, Gas, rBytes) {
+        self.exit(ret, &remaining_gas, &out);
+        (ret, remaining_gas, out)
+    }
+
+    fn create(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<Address>, Gas, rBytes) {
+        let call_type = match inputs.scheme {
+            CreateScheme::Create => "CREATE",
+            CreateScheme::Create2 { .. } => "CREATE2",
+        };
+        let frame = CallFrame {
+            from: rb160_to_eh160(&inputs.caller),
+            input: Bytes::from(inputs.init_code.to_vec()),
+            value: Some(ru256_to_eu256(inputs.value)),
+            call_type: call_type.to_string(),
+            ..Default::default()
+        };
+        self.enter(frame, inputs.gas_limit, data.env.tx.gas_limit);
+        (
+            InstructionResult::Continue,
+            None,
+            Gas::new(0),
+            rBytes::new(),
+        )
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: rBytes,
+    ) -> (InstructionResult, Option<Address>, Gas, rBytes) {
+        if let Some(active) = self.stack.last_mut() {
+            active.frame.to = address.as_ref().map(rb160_to_eh160);
+        }
+        self.exit(ret, &remaining_gas, &out);
+        (ret, address, remaining_gas, out)
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: rU256) {
+        let Some(active) = self.stack.last_mut() else {
+            return;
+        };
+        active.frame.calls.push(CallFrame {
+            from: rb160_to_eh160(&contract),
+            to: Some(rb160_to_eh160(&target)),
+            value: Some(ru256_to_eu256(value)),
+            call_type: "SELFDESTRUCT".to_string(),
+            ..Default::default()
+        });
+    }
+}
+
+/// The error (as worded by geth) for a frame that failed.
+fn frame_error(result: InstructionResult, last_op: u8) -> String {
+    use InstructionResult::*;
+    let error = match result {
+        CallTooDeep => "max call depth exceeded",
+        OutOfFund => "insufficient balance for transfer",
+        CreateCollision => "contract address collision",
+        CreateContractSizeLimit => "max code size exceeded",
+        CreateContractStartingWithEF => "invalid code: must not begin with 0xef",
+        NonceOverflow => "nonce uint64 overflow",
+        other => {
+            return step_error(other, &op_name(last_op)).unwrap_or_else(|| format!("{other:?}"))
+        }
+    };
+    error.to_string()
+}
+
+/// Decodes the reason for a revert from the solidity `Error(string)` or `Panic(uint256)`
+/// encodings.
+fn revert_reason(output: &[u8]) -> Option<String> {
+    if output.len() < 4 {
+        return None;
+    }
+    let (selector, data) = output.split_at(4);
+    if selector == ERROR_SELECTOR {
+        match decode(&[ParamType::String], data).ok()?.pop()? {
+            Token::String(reason) => Some(reason),
+            _ => None,
+        }
+    } else if selector == PANIC_SELECTOR {
+        match decode(&[ParamType::Uint(256)], data).ok()?.pop()? {
+            Token::Uint(code) => Some(panic_reason(code)),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// Describes a solidity panic code, as geth does.
+fn panic_reason(code: U256) -> String {
+    let reason = match code.low_u64() {
+        _ if code > U256::from(u8::MAX) => None,
+        0x00 => Some("generic panic"),
+        0x01 => Some("assert(false)"),
+        0x11 => Some("arithmetic underflow or overflow"),
+        0x12 => Some("division or modulo by zero"),
+        0x21 => Some("enum overflow"),
+        0x22 => Some("invalid encoded storage byte array accessed"),
+        0x31 => Some("out-of-bounds array access; popping on an empty array"),
+        0x32 => Some("out-of-bounds access of an array or bytesN"),
+        0x41 => Some("out of memory"),
+        0x51 => Some("uninitialized function"),
+        _ => None,
+    };
+    match reason {
+        Some(reason) => reason.to_string(),
+        None => format!("unknown panic code: {code:#x}"),
+    }
+}
+
+/// Removes logs from frames that failed, along with logs of the frames they called.
+fn clear_failed_logs(frame: &mut CallFrame, parent_failed: bool) {
+    let failed = parent_failed || frame.error.is_some();
+    if failed {
+        frame.logs.clear();
+    }
+    for call in frame.calls.iter_mut() {
+        clear_failed_logs(call, failed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::abi::encode;
+
+    use super::*;
+
+    #[test]
+    fn test_revert_reason_error_string() {
+        let mut output = ERROR_SELECTOR.to_vec();
+        output.extend(encode(&[Token::String("Insufficient balance".to_string())]));
+        assert_eq!(
+            revert_reason(&output),
+            Some("Insufficient balance".to_string())
+        );
+        assert_eq!(revert_reason(&output[..3]), None);
+    }
+
+    #[test]
+    fn test_revert_reason_panic() {
+        let mut output = PANIC_SELECTOR.to_vec();
+        output.extend(encode(&[Token::Uint(U256::from(0x11))]));
+        assert_eq!(
+            revert_reason(&output),
+            Some("arithmetic underflow or overflow".to_string())
+        );
+        assert_eq!(panic_reason(U256::from(0x99)), "unknown panic code: 0x99");
+    }
+
+    #[test]
+    fn test_call_frame_serialization() {
+        let frame = CallFrame {
+            from: H160::from_low_u64_be(1),
+            gas: U64::from(21_000),
+            gas_used: U64::from(21_000),
+            to: Some(H160::from_low_u64_be(2)),
+            value: Some(U256::zero()),
+            call_type: "CALL".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&frame).unwrap(),
+            r#"{"from":"0x0000000000000000000000000000000000000001","gas":"0x5208","gasUsed":"0x5208","to":"0x0000000000000000000000000000000000000002","input":"0x","value":"0x0","type":"CALL"}"#
+        );
+    }
+
+    #[test]
+    fn test_failed_frame_logs_cleared() {
+        let log = CallLog::default();
+        let mut root = CallFrame {
+            logs: vec![log.clone()],
+            calls: vec![CallFrame {
+                error: Some("execution reverted".to_string()),
+                logs: vec![log.clone()],
+                calls: vec![CallFrame {
+                    logs: vec![log],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        clear_failed_logs(&mut root, false);
+        assert_eq!(root.logs.len(), 1);
+        assert!(root.calls[0].logs.is_empty());
+        assert!(root.calls[0].calls[0].logs.is_empty());
+    }
+}
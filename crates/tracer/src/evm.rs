@@ -1,19 +1,45 @@
 //! For creation and use of an EVM for a single block.
 
-use std::io::stdout;
+use std::{convert::Infallible, io::Write};
 
-use archors_types::utils::{
-    access_list_e_to_r, eu256_to_ru256, eu256_to_u64, eu64_to_ru256, ru256_to_u64, UtilsError,
+use archors_types::{
+    beacon::{beacon_root_storage, BEACON_ROOTS_ADDRESS},
+    chain::ChainConfig,
+    execution::EvmStateError,
+    utils::{
+        access_list_e_to_r, eu256_to_ru256, eu256_to_u64, eu64_to_ru256, ru256_to_u64, UtilsError,
+    },
 };
-use ethers::types::{Block, Transaction};
+use ethers::types::{Block, Transaction, H256, U256 as EU256};
 use revm::{
-    db::{CacheDB, EmptyDB},
+    db::{CacheDB, DatabaseCommit, DatabaseRef, EmptyDB},
     inspectors::{NoOpInspector, TracerEip3155},
-    primitives::{EVMError, ResultAndState, TransactTo, TxEnv, U256},
-    EVM,
+    primitives::{
+        Account, AccountStatus, Address, CreateScheme, EVMError, HashMap as rHashMap,
+        InvalidHeader, ResultAndState, SpecId, StorageSlot, TransactTo, TxEnv, B256, KECCAK_EMPTY,
+        U256,
+    },
+    Database, Inspector, EVM,
 };
 use thiserror::Error;
 
+use crate::{
+    call_tracer::{CallTracer, CallTracerConfig},
+    filter::{FilteredTracer, StepFilter},
+    parity::{state_diff, ParityTraceTypes, ParityTracer},
+    prestate::{prestate_trace, PrestateTracerConfig},
+    struct_log::{StructLogger, StructLoggerConfig},
+};
+
+/// Legacy transaction type (including pre-EIP-2718 transactions that have no type).
+const LEGACY_TX_TYPE: u64 = 0;
+/// EIP-2930 access list transaction type.
+const ACCESS_LIST_TX_TYPE: u64 = 1;
+/// EIP-1559 dynamic fee transaction type.
+const DYNAMIC_FEE_TX_TYPE: u64 = 2;
+/// EIP-4844 blob transaction type.
+const BLOB_TX_TYPE: u64 = 3;
+
 /// An error with tracing a block
 #[derive(Debug, Error, PartialEq)]
 pub enum EvmError {
@@ -23,43 +49,142 @@ pub enum EvmError {
     NoBlockAuthor,
     #[error("Expected a block number to set up the EVM, found none")]
     NoBlockNumber,
+    #[error("Expected a post-merge block mix hash (prevrandao) to set up the EVM, found none")]
+    NoMixHash,
+    #[error("Expected a gas price for a legacy or access list transaction, found none")]
+    NoGasPrice,
+    #[error("Expected a max fee per gas for a dynamic fee transaction, found none")]
+    NoMaxFeePerGas,
+    #[error("Expected a max priority fee per gas for a dynamic fee transaction, found none")]
+    NoMaxPriorityFeePerGas,
+    #[error("Expected excess blob gas to set up the EVM for a Cancun block, found none")]
+    NoExcessBlobGas,
+    #[error("Expected blob versioned hashes for a blob transaction, found none")]
+    NoBlobVersionedHashes,
+    #[error("Expected a max fee per blob gas for a blob transaction, found none")]
+    NoMaxFeePerBlobGas,
+    #[error("Blob transaction field {field} could not be read {error}")]
+    InvalidBlobField { field: String, error: String },
+    #[error("Transaction type {0} is not supported")]
+    UnsupportedTxType(u64),
+    #[error("Legacy transaction signature v value {0} is not 27, 28 or at least 35")]
+    InvalidLegacyV(u64),
+    #[error("The EVM has no database")]
+    NoDatabase,
     #[error("Attempted to execute transaction before setting environment")]
     TxNotSet,
     #[error("Attempted to set transaction environment twice")]
     TxAlreadySet,
     #[error("UtilsError {0}")]
     UtilsError(#[from] UtilsError),
+    #[error("StateError {0}")]
+    StateError(#[from] EvmStateError),
     #[error("revm Error {0}")]
     RevmError(String),
+    #[error("Unable to write trace {0}")]
+    TraceOutput(String),
 }
 
 // A wrapper to implement handy methods for working with the revm EVM.
+//
+// The database holds the state. By default this is an in-memory DB populated before
+// execution, a LazyStateDb (wrapped in a CacheDB) can be used to read state on demand.
 #[derive(Clone)]
-pub struct BlockEvm {
-    pub evm: EVM<CacheDB<EmptyDB>>,
+pub struct BlockEvm<DB = CacheDB<EmptyDB>> {
+    pub evm: EVM<DB>,
+    chain: ChainConfig,
     tx_env_status: TxStatus,
     block_env_status: BlockStatus,
+    /// Blob gas consumed by the transactions executed so far.
+    blob_gas_used: u64,
+    /// Hash of the loaded transaction.
+    tx_hash: Option<H256>,
+}
+
+/// The format of the trace produced for a transaction.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum TraceFormat {
+    /// One JSON line per EVM step (EIP-3155).
+    #[default]
+    Eip3155,
+    /// One JSON line per EVM step (EIP-3155) for only the steps that match the filter.
+    Eip3155Filtered(StepFilter),
+    /// The default trace of geth `debug_traceTransaction` (`structLogs`).
+    StructLog(StructLoggerConfig),
+    /// The trace of geth `debug_traceTransaction` with the `callTracer` (nested call frames).
+    Call(CallTracerConfig),
+    /// The trace of geth `debug_traceTransaction` with the `prestateTracer` (accessed state
+    /// before the transaction, or the pre and post state of modified accounts in diff mode).
+    Prestate(PrestateTracerConfig),
+    /// The traces of OpenEthereum/Erigon `trace_replayTransaction` (flat calls, state
+    /// diff and VM steps).
+    Parity(ParityTraceTypes),
 }
 
-impl BlockEvm {
-    /// Create the EVM and insert a populated database of state values.
+impl<DB> BlockEvm<DB>
+where
+    DB: Database + DatabaseRef + DatabaseCommit,
+    EvmError: From<<DB as Database>::Error> + From<<DB as DatabaseRef>::Error>,
+{
+    /// Create the EVM and insert a database of state values.
     ///
-    /// The DB should contain the states required to execute the intended transactions.
-    pub fn init_from_db(db: CacheDB<EmptyDB>) -> Self {
+    /// The DB should contain (or be able to read) the states required to execute the
+    /// intended transactions.
+    pub fn init_from_db(db: DB) -> Self {
         let mut evm = EVM::new();
         evm.database(db);
         Self {
             evm,
+            chain: ChainConfig::default(),
             tx_env_status: TxStatus::NotLoaded,
             block_env_status: BlockStatus::NotSet,
+            blob_gas_used: 0,
+            tx_hash: None,
+        }
+    }
+    /// Creates an EVM with the same environment (chain, block and any loaded
+    /// transaction) that uses a different database.
+    pub fn with_db<D>(&self, db: D) -> BlockEvm<D> {
+        let mut evm = EVM::new();
+        evm.env = self.evm.env.clone();
+        evm.database(db);
+        BlockEvm {
+            evm,
+            chain: self.chain.clone(),
+            tx_env_status: self.tx_env_status.clone(),
+            block_env_status: self.block_env_status.clone(),
+            blob_gas_used: self.blob_gas_used,
+            tx_hash: self.tx_hash,
         }
     }
     /// Set the chain ID (mainnet = 1).
     pub fn add_chain_id(&mut self, id: U256) -> &mut Self {
-        self.evm.env.cfg.chain_id = U256::from(id);
+        self.evm.env.cfg.chain_id = ru256_to_u64(id);
         self
     }
+    /// Set the chain (chain ID and fork schedule). Mainnet is used if not set.
+    pub fn add_chain_config(&mut self, chain: ChainConfig) -> &mut Self {
+        self.evm.env.cfg.chain_id = chain.chain_id;
+        self.chain = chain;
+        self
+    }
+    /// The chain that the block being executed belongs to.
+    pub fn chain(&self) -> &ChainConfig {
+        &self.chain
+    }
+    /// Price per unit of blob gas for the block (EIP-4844), present from Cancun.
+    pub fn blob_gas_price(&self) -> Option<u128> {
+        self.evm.env.block.get_blob_gasprice()
+    }
+    /// Blob gas consumed by the transactions executed so far.
+    pub fn blob_gas_used(&self) -> u64 {
+        self.blob_gas_used
+    }
     /// Set initial block values (BaseFee, GasLimit, ..., Etc.).
+    ///
+    /// The spec id must be set first, as post-merge blocks source prevrandao from the
+    /// header mix hash and Cancun blocks source the blob gas price from the header
+    /// excess blob gas.
     pub fn add_block_environment(
         &mut self,
         block: &Block<Transaction>,
@@ -67,46 +192,83 @@ impl BlockEvm {
         if self.block_env_status == BlockStatus::Set {
             return Err(EvmError::BlockEnvAlreadySet);
         }
+        let post_merge = SpecId::enabled(self.evm.env.cfg.spec_id, SpecId::MERGE);
+        let cancun = SpecId::enabled(self.evm.env.cfg.spec_id, SpecId::CANCUN);
         let env = &mut self.evm.env.block;
 
         env.number = eu64_to_ru256(block.number.ok_or(EvmError::NoBlockNumber)?);
-        env.coinbase = block.author.ok_or(EvmError::NoBlockAuthor)?.into();
+        env.coinbase = Address::from(block.author.ok_or(EvmError::NoBlockAuthor)?.0);
         env.timestamp = block.timestamp.into();
         env.gas_limit = block.gas_limit.into();
         env.basefee = block.base_fee_per_gas.unwrap_or_default().into();
         env.difficulty = block.difficulty.into();
-        env.prevrandao = Some(block.difficulty.into());
+        env.prevrandao = match post_merge {
+            true => Some(B256::from(block.mix_hash.ok_or(EvmError::NoMixHash)?.0)),
+            false => None,
+        };
+        env.blob_excess_gas_and_price = None;
+        if cancun {
+            let excess = block.excess_blob_gas.ok_or(EvmError::NoExcessBlobGas)?;
+            env.set_blob_excess_gas_and_price(eu256_to_u64(excess));
+        }
         self.block_env_status = BlockStatus::Set;
         Ok(self)
     }
-    /// Set the spec id (hard fork definition).
-    pub fn add_spec_id(&mut self, _block: &Block<Transaction>) -> Result<&mut Self, EvmError> {
-        // TODO. E.g.,
-        // if block x < block.number < y,
-        // self.env.cfg.spec_id = SpecId::Constantinople
+    /// Set the spec id (hard fork definition) using the fork schedule of the chain.
+    ///
+    /// Forks are selected by block number up to the merge and by timestamp thereafter.
+    pub fn add_spec_id(&mut self, block: &Block<Transaction>) -> Result<&mut Self, EvmError> {
+        let number = block.number.ok_or(EvmError::NoBlockNumber)?.as_u64();
+        let timestamp = eu256_to_u64(block.timestamp);
+        self.evm.env.cfg.spec_id = self.chain.spec_id(number, timestamp);
         Ok(self)
     }
     /// Add a single transaction environment (index, sender, recipient, etc.).
+    ///
+    /// Handles legacy, EIP-2930 (access list), EIP-1559 (dynamic fee) and EIP-4844 (blob)
+    /// transactions. Blob transactions require the block environment to be set first.
     pub fn add_transaction_environment(&mut self, tx: Transaction) -> Result<&mut Self, EvmError> {
         self.tx_env_status.ready_to_set()?;
 
-        let caller = tx.from.into();
+        let tx_type = tx.transaction_type.map(|t| t.as_u64()).unwrap_or_default();
+        let tx_hash = tx.hash;
+        let caller = Address::from(tx.from.0);
         let gas_limit = eu256_to_u64(tx.gas);
-        let gas_price = match tx.gas_price {
-            Some(price) => eu256_to_ru256(price)?,
-            None => todo!("handle Type II transaction gas price"),
+        let (gas_price, gas_priority_fee) = match tx_type {
+            LEGACY_TX_TYPE | ACCESS_LIST_TX_TYPE => {
+                let price = tx.gas_price.ok_or(EvmError::NoGasPrice)?;
+                (eu256_to_ru256(price)?, None)
+            }
+            DYNAMIC_FEE_TX_TYPE | BLOB_TX_TYPE => {
+                let max_fee = tx.max_fee_per_gas.ok_or(EvmError::NoMaxFeePerGas)?;
+                let priority_fee = tx
+                    .max_priority_fee_per_gas
+                    .ok_or(EvmError::NoMaxPriorityFeePerGas)?;
+                (
+                    eu256_to_ru256(max_fee)?,
+                    Some(eu256_to_ru256(priority_fee)?),
+                )
+            }
+            unknown => return Err(EvmError::UnsupportedTxType(unknown)),
         };
-        let gas_priority_fee = match tx.max_priority_fee_per_gas {
-            Some(fee) => Some(eu256_to_ru256(fee)?),
-            None => None,
+        let (blob_hashes, max_fee_per_blob_gas) = match tx_type {
+            BLOB_TX_TYPE => self.blob_tx_environment(&tx)?,
+            _ => (vec![], None),
         };
         let transact_to = match tx.to {
-            Some(to) => TransactTo::Call(to.into()),
-            None => todo!("handle tx create scheme"), // TransactTo::Create(),
+            Some(to) => TransactTo::Call(Address::from(to.0)),
+            None => TransactTo::Create(CreateScheme::Create),
         };
         let value = tx.value.into();
-        let data = tx.input.0;
-        let chain_id = Some(ru256_to_u64(self.evm.env.cfg.chain_id));
+        let chain_id = match tx_type {
+            LEGACY_TX_TYPE => legacy_chain_id(&tx)?,
+            _ => Some(
+                tx.chain_id
+                    .map(eu256_to_u64)
+                    .unwrap_or(self.evm.env.cfg.chain_id),
+            ),
+        };
+        let data = tx.input.0.into();
         let nonce = Some(eu256_to_u64(tx.nonce));
         let access_list = match tx.access_list {
             Some(list_in) => access_list_e_to_r(list_in),
@@ -124,48 +286,306 @@ impl BlockEvm {
             chain_id,
             nonce,
             access_list,
+            blob_hashes,
+            max_fee_per_blob_gas,
         };
         self.evm.env.tx = new_tx_env;
+        self.tx_hash = Some(tx_hash);
+        self.tx_env_status.set()?;
+        Ok(self)
+    }
+    /// Add a transaction environment that is not from a transaction in the block (E.g., a
+    /// call). Fields left unset are not checked (E.g., no nonce skips the nonce check).
+    pub fn add_call_environment(&mut self, tx: TxEnv) -> Result<&mut Self, EvmError> {
+        self.tx_env_status.ready_to_set()?;
+        self.evm.env.tx = tx;
+        self.tx_hash = None;
         self.tx_env_status.set()?;
         Ok(self)
     }
+    /// Reads the blob versioned hashes and max fee per blob gas of a blob transaction.
+    ///
+    /// The values are checked against the block (E.g., the blob gas price) by the EVM.
+    fn blob_tx_environment(&self, tx: &Transaction) -> Result<(Vec<B256>, Option<U256>), EvmError> {
+        if !SpecId::enabled(self.evm.env.cfg.spec_id, SpecId::CANCUN) {
+            return Err(EvmError::UnsupportedTxType(BLOB_TX_TYPE));
+        }
+        let hashes: Vec<H256> =
+            read_tx_field(tx, "blobVersionedHashes")?.ok_or(EvmError::NoBlobVersionedHashes)?;
+        let max_fee: EU256 =
+            read_tx_field(tx, "maxFeePerBlobGas")?.ok_or(EvmError::NoMaxFeePerBlobGas)?;
+        Ok((
+            hashes.into_iter().map(|hash| B256::from(hash.0)).collect(),
+            Some(eu256_to_ru256(max_fee)?),
+        ))
+    }
     /// Execute a loaded transaction with an inspector to produce an EIP-3155 style trace.
-    /// Runs the transaction twice (once for state change, once to commit).
     ///
-    /// This applies the transaction, monitors the output and leaves the EVM ready for the
+    /// This applies the transaction, writes the trace to the output and leaves the EVM
+    /// ready for the next transaction to be added.
+    pub fn execute_with_inspector_eip3155(
+        &mut self,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        let inspector = TracerEip3155::new(output, true, true);
+        self.execute_and_commit(inspector)
+    }
+    /// Execute a loaded transaction and write a trace in the given format to the output.
+    pub fn execute_with_trace(
+        &mut self,
+        format: TraceFormat,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        match format {
+            TraceFormat::Eip3155 => self.execute_with_inspector_eip3155(output),
+            TraceFormat::Eip3155Filtered(filter) => {
+                self.execute_with_filtered_tracer(filter, output)
+            }
+            TraceFormat::StructLog(config) => self.execute_with_struct_logger(config, output),
+            TraceFormat::Call(config) => self.execute_with_call_tracer(config, output),
+            TraceFormat::Prestate(config) => self.execute_with_prestate_tracer(config, output),
+            TraceFormat::Parity(types) => self.execute_with_parity_tracer(types, output),
+        }
+    }
+    /// Execute a loaded transaction with an inspector to produce an EIP-3155 style trace
+    /// of only the steps that match the filter.
+    pub fn execute_with_filtered_tracer(
+        &mut self,
+        filter: StepFilter,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        let mut tracer = FilteredTracer::new(filter, output);
+        let outcome = self.execute_and_commit(&mut tracer)?;
+        tracer
+            .finish()
+            .map_err(|e| EvmError::TraceOutput(e.to_string()))?;
+        Ok(outcome)
+    }
+    /// Execute a loaded transaction with an inspector to produce a geth struct logger
+    /// (`debug_traceTransaction`) trace. The trace is written as a single JSON line.
+    pub fn execute_with_struct_logger(
+        &mut self,
+        config: StructLoggerConfig,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        let mut logger = StructLogger::new(config);
+        let outcome = self.execute_and_commit(&mut logger)?;
+        write_json_line(output, &logger.into_trace(&outcome.result))?;
+        Ok(outcome)
+    }
+    /// Execute a loaded transaction with an inspector to produce a geth `callTracer` trace.
+    /// The trace is written as a single JSON line.
+    pub fn execute_with_call_tracer(
+        &mut self,
+        config: CallTracerConfig,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        let mut tracer = CallTracer::new(config);
+        let outcome = self.execute_and_commit(&mut tracer)?;
+        write_json_line(output, &tracer.into_trace(&outcome.result))?;
+        Ok(outcome)
+    }
+    /// Execute a loaded transaction and produce a geth `prestateTracer` trace from the state
+    /// accessed. The trace is written as a single JSON line.
+    pub fn execute_with_prestate_tracer(
+        &mut self,
+        config: PrestateTracerConfig,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        let spec = self.evm.env.cfg.spec_id;
+        let outcome = self.execute_uncommitted(NoOpInspector {})?;
+        let db = self.evm.db().ok_or(EvmError::NoDatabase)?;
+        let trace = prestate_trace(config, &*db, &outcome.state, spec)?;
+        self.commit_outcome(&outcome)?;
+        write_json_line(output, &trace)?;
+        Ok(outcome)
+    }
+    /// Execute a loaded transaction with an inspector to produce an OpenEthereum/Erigon
+    /// `trace_replayTransaction` result. The result is written as a single JSON line.
+    pub fn execute_with_parity_tracer(
+        &mut self,
+        types: ParityTraceTypes,
+        output: Box<dyn Write>,
+    ) -> Result<ResultAndState, EvmError> {
+        let spec = self.evm.env.cfg.spec_id;
+        let mut tracer = ParityTracer::new(types);
+        let outcome = self.execute_uncommitted(&mut tracer)?;
+        let diff = match types.state_diff {
+            true => {
+                let db = self.evm.db().ok_or(EvmError::NoDatabase)?;
+                let diff = state_diff(&*db, &outcome.state, spec)?;
+                Some(diff)
+            }
+            false => None,
+        };
+        let tx_hash = self.tx_hash;
+        self.commit_outcome(&outcome)?;
+        write_json_line(output, &tracer.into_trace(&outcome.result, diff, tx_hash))?;
+        Ok(outcome)
+    }
+    /// Execute a loaded transaction with the given inspector.
+    ///
+    /// This applies the transaction and leaves the EVM ready for the
     /// next transaction to be added.
-    pub fn execute_with_inspector_eip3155(&mut self) -> Result<ResultAndState, EvmError> {
-        self.tx_env_status.ready_to_execute()?;
-        // Run the tx to get the state changes, but don't commit to the EVM env yet.
-        // The changes will be used to compute the post-tx state root.
-        // Use a dummy inspector.
-        let noop_inspector = NoOpInspector {};
-        let state_changes = self.evm.inspect_ref(noop_inspector)?;
-
-        // Now run the tx again and this time commit the changes.
-        // see: https://github.com/bluealloy/revm/blob/main/bins/revme/src/statetest/runner.rs#L259
-        // Initialize the inspector
-        let inspector = TracerEip3155::new(Box::new(stdout()), true, true);
-        let _outcome = self.evm.inspect_commit(inspector).map_err(EvmError::from)?;
-        self.tx_env_status.executed()?;
-        Ok(state_changes)
+    pub fn execute_with_inspector<I>(&mut self, inspector: I) -> Result<ResultAndState, EvmError>
+    where
+        I: Inspector<DB>,
+    {
+        self.execute_and_commit(inspector)
     }
     /// Execute a loaded transaction without an inspector.
     ///
     /// This applies the transaction and leaves the EVM ready for the
     /// next transaction to be added.
     pub fn execute_without_inspector(&mut self) -> Result<ResultAndState, EvmError> {
+        self.execute_and_commit(NoOpInspector {})
+    }
+    /// Unloads a transaction that could not be executed (E.g., an invalid transaction), so
+    /// that another transaction can be loaded. The state is unchanged.
+    pub fn unload_transaction(&mut self) {
+        self.tx_hash = None;
+        self.tx_env_status = TxStatus::NotLoaded;
+    }
+    /// Execute a loaded transaction with the given inspector without committing the state
+    /// changes.
+    ///
+    /// The database is left as it was before execution. Whether or not execution succeeds, the
+    /// EVM is ready for another transaction against the same state.
+    pub fn execute_without_commit<I>(&mut self, inspector: I) -> Result<ResultAndState, EvmError>
+    where
+        I: Inspector<DB>,
+    {
+        let outcome = self.execute_uncommitted(inspector);
+        self.tx_hash = None;
+        self.tx_env_status = TxStatus::NotLoaded;
+        outcome
+    }
+    /// Executes a loaded transaction once with the given inspector, then commits the
+    /// resulting state changes to the database.
+    ///
+    /// The changes are returned so that they can be used to compute the post-block state root.
+    fn execute_and_commit<I>(&mut self, inspector: I) -> Result<ResultAndState, EvmError>
+    where
+        I: Inspector<DB>,
+    {
+        let outcome = self.execute_uncommitted(inspector)?;
+        self.commit_outcome(&outcome)?;
+        Ok(outcome)
+    }
+    /// Executes a loaded transaction with the given inspector. The database is left as it
+    /// was before execution, commit_outcome() must follow.
+    fn execute_uncommitted<I>(&mut self, inspector: I) -> Result<ResultAndState, EvmError>
+    where
+        I: Inspector<DB>,
+    {
         self.tx_env_status.ready_to_execute()?;
-        // Run the tx to get the state changes, but don't commit to the EVM env yet.
-        // The changes will be used to compute the post-tx state root.
-        // Use a dummy inspector.
-        let noop_inspector = NoOpInspector {};
-        let state_changes = self.evm.inspect_ref(noop_inspector)?;
-
-        // Now run the tx again, this time to commit the changes.
-        let _outcome = self.evm.transact_commit().map_err(EvmError::from)?;
+        self.evm.inspect(inspector).map_err(EvmError::from)
+    }
+    /// Commits the state changes of an executed transaction to the database and leaves
+    /// the EVM ready for the next transaction.
+    fn commit_outcome(&mut self, outcome: &ResultAndState) -> Result<(), EvmError> {
+        let db = self.evm.db().ok_or(EvmError::NoDatabase)?;
+        db.commit(outcome.state.clone());
+        self.blob_gas_used += self.evm.env.tx.get_total_blob_gas();
+        self.tx_hash = None;
         self.tx_env_status.executed()?;
-        Ok(state_changes)
+        Ok(())
+    }
+    /// Credits balances outside of transaction execution (e.g., block rewards) and
+    /// returns the changes so that they can be included in the block state delta.
+    ///
+    /// Multiple increments to the same address are combined.
+    pub fn increment_balances(
+        &mut self,
+        increments: &[(Address, U256)],
+    ) -> Result<rHashMap<Address, Account>, EvmError> {
+        let db = self.evm.db().ok_or(EvmError::NoDatabase)?;
+        let mut changes: rHashMap<Address, Account> = rHashMap::new();
+        for (address, amount) in increments {
+            if let Some(account) = changes.get_mut(address) {
+                account.info.balance = account.info.balance.saturating_add(*amount);
+                continue;
+            }
+            let mut info = DatabaseRef::basic(&*db, *address)?.unwrap_or_default();
+            info.balance = info.balance.saturating_add(*amount);
+            let account = Account {
+                info,
+                storage: rHashMap::new(),
+                status: AccountStatus::Touched,
+            };
+            changes.insert(*address, account);
+        }
+        db.commit(changes.clone());
+        Ok(changes)
+    }
+    /// Stores the parent beacon block root in the beacon roots contract (EIP-4788) and
+    /// returns the changes so that they can be included in the block state delta.
+    ///
+    /// Equivalent to the system call made before the transactions in the block. The
+    /// storage writes of the contract are applied directly rather than by executing the
+    /// contract code. If the contract has no code, nothing is stored.
+    pub fn store_beacon_root(
+        &mut self,
+        timestamp: u64,
+        root: B256,
+    ) -> Result<rHashMap<Address, Account>, EvmError> {
+        let db = self.evm.db().ok_or(EvmError::NoDatabase)?;
+        let mut changes: rHashMap<Address, Account> = rHashMap::new();
+        let info = match DatabaseRef::basic(&*db, BEACON_ROOTS_ADDRESS)? {
+            Some(info) if info.code_hash != KECCAK_EMPTY => info,
+            _ => return Ok(changes),
+        };
+        let mut storage = rHashMap::new();
+        for (slot, value) in beacon_root_storage(timestamp, root) {
+            let original_value = DatabaseRef::storage(&*db, BEACON_ROOTS_ADDRESS, slot)?;
+            storage.insert(slot, StorageSlot::new_changed(original_value, value));
+        }
+        let account = Account {
+            info,
+            storage,
+            status: AccountStatus::Touched,
+        };
+        changes.insert(BEACON_ROOTS_ADDRESS, account);
+        db.commit(changes.clone());
+        Ok(changes)
+    }
+}
+
+/// Writes a trace as a single line of JSON.
+fn write_json_line<T: serde::Serialize>(
+    mut output: Box<dyn Write>,
+    trace: &T,
+) -> Result<(), EvmError> {
+    serde_json::to_writer(&mut output, trace).map_err(|e| EvmError::TraceOutput(e.to_string()))?;
+    writeln!(output)
+        .and_then(|_| output.flush())
+        .map_err(|e| EvmError::TraceOutput(e.to_string()))
+}
+
+/// Reads a transaction field that is not part of the ethers transaction type.
+fn read_tx_field<T: serde::de::DeserializeOwned>(
+    tx: &Transaction,
+    field: &str,
+) -> Result<Option<T>, EvmError> {
+    tx.other
+        .get_deserialized(field)
+        .transpose()
+        .map_err(|e| EvmError::InvalidBlobField {
+            field: field.to_string(),
+            error: e.to_string(),
+        })
+}
+
+/// Gets the chain id a legacy transaction was signed for.
+///
+/// Transactions signed before EIP-155 (v = 27 or 28) do not commit to a chain id. Otherwise
+/// v = chain_id * 2 + 35 + y_parity. Any other v is invalid.
+fn legacy_chain_id(tx: &Transaction) -> Result<Option<u64>, EvmError> {
+    let v = tx.v.as_u64();
+    match v {
+        27 | 28 => Ok(None),
+        35.. => Ok(Some((v - 35) / 2)),
+        invalid => Err(EvmError::InvalidLegacyV(invalid)),
     }
 }
 
@@ -211,7 +631,10 @@ impl TxStatus {
 }
 
 /// Convert revm Error type (no Display impl) to local error type.
-impl<DBError> From<EVMError<DBError>> for EvmError {
+impl<DBError> From<EVMError<DBError>> for EvmError
+where
+    EvmError: From<DBError>,
+{
     fn from(value: EVMError<DBError>) -> Self {
         let e = match value {
             EVMError::Transaction(t) => {
@@ -220,10 +643,600 @@ impl<DBError> From<EVMError<DBError>> for EvmError {
                     Err(serde_err) => serde_err,
                 }
             }
-            // _d is Infallible - ignore.
-            EVMError::Database(_d) => "database error".to_string(),
-            EVMError::PrevrandaoNotSet => String::from("prevrandao error"),
+            EVMError::Database(d) => return EvmError::from(d),
+            EVMError::Header(h) => match h {
+                InvalidHeader::PrevrandaoNotSet => String::from("prevrandao error"),
+                InvalidHeader::ExcessBlobGasNotSet => String::from("excess blob gas error"),
+            },
         };
         EvmError::RevmError(e)
     }
 }
+
+/// The in-memory DB cannot fail.
+impl From<Infallible> for EvmError {
+    fn from(value: Infallible) -> Self {
+        match value {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    use archors_inventory::types::TransactionAccountStates;
+    use ethers::types::{
+        transaction::eip2930::{AccessList, AccessListItem},
+        H160, H256, U256 as EU256,
+    };
+    use revm::primitives::{
+        AccountInfo, Bytecode, Bytes, ExecutionResult, Output, VERSIONED_HASH_VERSION_KZG,
+    };
+
+    use crate::{
+        call_tracer::CallFrame,
+        filter::TracedStep,
+        parity::{Delta, ParityTraceResult},
+        sink::SharedBuffer,
+        struct_log::StructLogTrace,
+        test_utils::db_with_code,
+    };
+
+    fn evm_factory() -> BlockEvm {
+        let mut block_evm = BlockEvm::init_from_db(CacheDB::new(EmptyDB::default()));
+        block_evm.add_chain_id(U256::from(1));
+        block_evm
+    }
+
+    fn tx_factory() -> Transaction {
+        Transaction {
+            from: H160::from_str("0x0300000000000000000000000000000000000000").unwrap(),
+            to: Some(H160::from_str("0x0200000000000000000000000000000000000000").unwrap()),
+            gas: EU256::from(21_000),
+            ..Default::default()
+        }
+    }
+
+    /// PUSH1 1, PUSH1 0, SSTORE, STOP
+    const SSTORE_CODE: [u8; 6] = [0x60, 0x01, 0x60, 0x00, 0x55, 0x00];
+
+    /// CALL to 0x42 with no value or data, then STOP.
+    const CALL_CODE: [u8; 15] = [
+        0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x42, 0x5a, 0xf1, 0x00,
+    ];
+
+    /// An EVM with a transaction loaded that calls a contract with the given code.
+    fn contract_call_evm(code: &[u8]) -> (BlockEvm, Transaction) {
+        let mut tx = tx_factory();
+        tx.gas = EU256::from(100_000);
+        tx.gas_price = Some(EU256::zero());
+        tx.v = 27.into();
+        let db = db_with_code(Address::from(tx.to.unwrap().0), code);
+        let mut block_evm = BlockEvm::init_from_db(db);
+        block_evm.add_chain_id(U256::from(1));
+        block_evm.add_transaction_environment(tx.clone()).unwrap();
+        (block_evm, tx)
+    }
+
+    #[test]
+    fn test_pre_eip155_legacy_tx_has_no_chain_id() {
+        let mut tx = tx_factory();
+        tx.gas_price = Some(EU256::from(50));
+        tx.v = 27.into();
+        let mut block_evm = evm_factory();
+        block_evm.add_transaction_environment(tx).unwrap();
+        assert_eq!(block_evm.evm.env.tx.chain_id, None);
+        assert_eq!(block_evm.evm.env.tx.gas_price, U256::from(50));
+        assert_eq!(block_evm.evm.env.tx.gas_priority_fee, None);
+    }
+
+    #[test]
+    fn test_eip155_legacy_tx_chain_id() {
+        let mut tx = tx_factory();
+        tx.gas_price = Some(EU256::from(50));
+        tx.v = 38.into();
+        let mut block_evm = evm_factory();
+        block_evm.add_transaction_environment(tx).unwrap();
+        assert_eq!(block_evm.evm.env.tx.chain_id, Some(1));
+    }
+
+    #[test]
+    fn test_legacy_tx_with_malformed_v_errors() {
+        let mut tx = tx_factory();
+        tx.gas_price = Some(EU256::from(50));
+        tx.v = 30.into();
+        let mut block_evm = evm_factory();
+        assert_eq!(
+            block_evm.add_transaction_environment(tx).err(),
+            Some(EvmError::InvalidLegacyV(30))
+        );
+    }
+
+    #[test]
+    fn test_dynamic_fee_tx() {
+        let mut tx = tx_factory();
+        tx.transaction_type = Some(2.into());
+        // Effective gas price as returned by a node, not used by the EVM.
+        tx.gas_price = Some(EU256::from(30));
+        tx.max_fee_per_gas = Some(EU256::from(100));
+        tx.max_priority_fee_per_gas = Some(EU256::from(2));
+        tx.chain_id = Some(EU256::from(1));
+        let storage_key = H256::from_low_u64_be(7);
+        tx.access_list = Some(AccessList(vec![AccessListItem {
+            address: H160::from_low_u64_be(9),
+            storage_keys: vec![storage_key],
+        }]));
+        let mut block_evm = evm_factory();
+        block_evm.add_transaction_environment(tx).unwrap();
+        let env = &block_evm.evm.env.tx;
+        assert_eq!(env.gas_price, U256::from(100));
+        assert_eq!(env.gas_priority_fee, Some(U256::from(2)));
+        assert_eq!(env.chain_id, Some(1));
+        assert_eq!(env.access_list.len(), 1);
+        assert_eq!(env.access_list[0].1, vec![U256::from(7)]);
+    }
+
+    #[test]
+    fn test_contract_creation_includes_new_account() {
+        let mut tx = tx_factory();
+        tx.to = None;
+        tx.gas = EU256::from(100_000);
+        tx.gas_price = Some(EU256::zero());
+        tx.v = 27.into();
+        let mut block_evm = evm_factory();
+        let outcome = block_evm
+            .add_transaction_environment(tx)
+            .unwrap()
+            .execute_without_inspector()
+            .unwrap();
+        let deployed = match outcome.result {
+            ExecutionResult::Success {
+                output: Output::Create(_, Some(address)),
+                ..
+            } => address,
+            other => panic!("Expected contract creation, got {:?}", other),
+        };
+        assert!(outcome.state.contains_key(&deployed));
+    }
+
+    /// Executes a transaction to a contract that self destructs.
+    fn selfdestruct_outcome(spec: SpecId) -> ResultAndState {
+        // PUSH1 0x00, SELFDESTRUCT
+        let (mut block_evm, tx) = contract_call_evm(&[0x60, 0x00, 0xff]);
+        block_evm.evm.env.cfg.spec_id = spec;
+        let contract = Address::from(tx.to.unwrap().0);
+        let db = block_evm.evm.db().unwrap();
+        db.accounts.get_mut(&contract).unwrap().info.balance = U256::from(1);
+        block_evm.execute_without_inspector().unwrap()
+    }
+
+    #[test]
+    fn test_selfdestruct_by_fork() {
+        let contract = Address::from(tx_factory().to.unwrap().0);
+        // Before Cancun SELFDESTRUCT always removes the account.
+        let outcome = selfdestruct_outcome(SpecId::SHANGHAI);
+        assert!(outcome.state.get(&contract).unwrap().is_selfdestructed());
+        // EIP-6780: only the balance is sent if not created in the same transaction.
+        let outcome = selfdestruct_outcome(SpecId::CANCUN);
+        let account = outcome.state.get(&contract).unwrap();
+        assert!(!account.is_selfdestructed());
+        assert_eq!(account.info.balance, U256::ZERO);
+    }
+
+    #[test]
+    fn test_selfdestruct_of_created_contract_after_cancun() {
+        let mut tx = tx_factory();
+        tx.to = None;
+        tx.gas = EU256::from(100_000);
+        tx.gas_price = Some(EU256::zero());
+        tx.v = 27.into();
+        // Init code: PUSH1 0x00, SELFDESTRUCT
+        tx.input = vec![0x60, 0x00, 0xff].into();
+        let mut block_evm = evm_factory();
+        block_evm.evm.env.cfg.spec_id = SpecId::CANCUN;
+        let outcome = block_evm
+            .add_transaction_environment(tx)
+            .unwrap()
+            .execute_without_inspector()
+            .unwrap();
+        let created = outcome
+            .state
+            .values()
+            .find(|account| account.is_created())
+            .unwrap();
+        assert!(created.is_selfdestructed());
+    }
+
+    #[test]
+    fn test_dynamic_fee_tx_without_max_fee_errors() {
+        let mut tx = tx_factory();
+        tx.transaction_type = Some(2.into());
+        tx.max_priority_fee_per_gas = Some(EU256::from(2));
+        let mut block_evm = evm_factory();
+        assert_eq!(
+            block_evm.add_transaction_environment(tx).err(),
+            Some(EvmError::NoMaxFeePerGas)
+        );
+    }
+
+    #[test]
+    fn test_store_beacon_root() {
+        let mut db = CacheDB::new(EmptyDB::default());
+        let code = Bytecode::new_raw(Bytes::from_static(&[0x00]));
+        db.insert_account_info(
+            BEACON_ROOTS_ADDRESS,
+            AccountInfo {
+                code_hash: code.hash_slow(),
+                code: Some(code),
+                ..Default::default()
+            },
+        );
+        let mut block_evm = BlockEvm::init_from_db(db);
+        let root = B256::with_last_byte(5);
+        let changes = block_evm.store_beacon_root(8192, root).unwrap();
+        let storage = &changes.get(&BEACON_ROOTS_ADDRESS).unwrap().storage;
+        assert_eq!(
+            storage.get(&U256::from(1)).unwrap().present_value,
+            U256::from(8192)
+        );
+        assert_eq!(
+            storage.get(&U256::from(8192)).unwrap().present_value,
+            U256::from(5)
+        );
+        let db = block_evm.evm.db().unwrap();
+        let stored = DatabaseRef::storage(&*db, BEACON_ROOTS_ADDRESS, U256::from(8192)).unwrap();
+        assert_eq!(stored, U256::from(5));
+    }
+
+    #[test]
+    fn test_store_beacon_root_without_contract() {
+        let mut block_evm = evm_factory();
+        let changes = block_evm.store_beacon_root(1, B256::ZERO).unwrap();
+        assert!(changes.is_empty());
+    }
+
+    fn cancun_evm_factory(excess_blob_gas: u64) -> BlockEvm {
+        let mut block_evm = evm_factory();
+        block_evm.evm.env.cfg.spec_id = SpecId::CANCUN;
+        let block: Block<Transaction> = Block {
+            author: Some(H160::default()),
+            number: Some(19_426_587.into()),
+            mix_hash: Some(H256::default()),
+            gas_limit: EU256::from(30_000_000),
+            excess_blob_gas: Some(EU256::from(excess_blob_gas)),
+            ..Default::default()
+        };
+        block_evm.add_block_environment(&block).unwrap();
+        block_evm
+    }
+
+    fn blob_tx_factory(max_fee_per_blob_gas: u64) -> Transaction {
+        let mut tx = tx_factory();
+        tx.transaction_type = Some(3.into());
+        tx.max_fee_per_gas = Some(EU256::zero());
+        tx.max_priority_fee_per_gas = Some(EU256::zero());
+        tx.chain_id = Some(EU256::from(1));
+        let mut hash = H256::from_low_u64_be(1);
+        hash.0[0] = VERSIONED_HASH_VERSION_KZG;
+        tx.other.insert(
+            "blobVersionedHashes".to_string(),
+            serde_json::json!([hash, hash]),
+        );
+        tx.other.insert(
+            "maxFeePerBlobGas".to_string(),
+            serde_json::json!(EU256::from(max_fee_per_blob_gas)),
+        );
+        tx
+    }
+
+    #[test]
+    fn test_blob_tx_environment() {
+        let mut block_evm = cancun_evm_factory(2_314_058);
+        assert_eq!(block_evm.blob_gas_price(), Some(2));
+        block_evm
+            .add_transaction_environment(blob_tx_factory(5))
+            .unwrap();
+        let tx_env = &block_evm.evm.env.tx;
+        assert_eq!(tx_env.blob_hashes.len(), 2);
+        assert_eq!(tx_env.blob_hashes[0].0[0], VERSIONED_HASH_VERSION_KZG);
+        assert_eq!(tx_env.max_fee_per_blob_gas, Some(U256::from(5)));
+    }
+
+    #[test]
+    fn test_blob_tx_max_fee_below_blob_gas_price() {
+        let mut block_evm = cancun_evm_factory(2_314_058);
+        let outcome = block_evm
+            .add_transaction_environment(blob_tx_factory(1))
+            .unwrap()
+            .execute_without_inspector();
+        assert!(matches!(outcome, Err(EvmError::RevmError(_))));
+    }
+
+    #[test]
+    fn test_blob_fee_is_charged_to_sender() {
+        let tx = blob_tx_factory(5);
+        let sender = Address::from(tx.from.0);
+        let mut block_evm = cancun_evm_factory(2_314_058);
+        let initial_balance = U256::from(10_000_000);
+        block_evm.evm.db().unwrap().insert_account_info(
+            sender,
+            AccountInfo {
+                balance: initial_balance,
+                ..Default::default()
+            },
+        );
+        // Not charged if the transaction is not committed.
+        block_evm
+            .add_transaction_environment(tx.clone())
+            .unwrap()
+            .execute_without_commit(NoOpInspector {})
+            .unwrap();
+        let db = block_evm.evm.db().unwrap();
+        let balance = DatabaseRef::basic(&*db, sender).unwrap().unwrap().balance;
+        assert_eq!(balance, initial_balance);
+        assert_eq!(block_evm.blob_gas_used(), 0);
+
+        let outcome = block_evm
+            .add_transaction_environment(tx)
+            .unwrap()
+            .execute_without_inspector()
+            .unwrap();
+        // Two blobs at a blob gas price of 2.
+        let fee = U256::from(2 * 2 * 131_072);
+        let sender_account = outcome.state.get(&sender).unwrap();
+        assert_eq!(sender_account.info.balance, initial_balance - fee);
+        assert_eq!(block_evm.blob_gas_used(), 2 * 131_072);
+    }
+
+    #[test]
+    fn test_blob_opcodes() {
+        let mut tx = blob_tx_factory(5);
+        tx.gas = EU256::from(100_000);
+        let contract = Address::from(tx.to.unwrap().0);
+        let mut block_evm = cancun_evm_factory(2_314_058);
+        // Stores BLOBHASH(1) in slot 0 and BLOBBASEFEE in slot 1.
+        let code = Bytecode::new_raw(Bytes::from_static(&[
+            0x60, 0x01, 0x49, 0x5f, 0x55, 0x4a, 0x60, 0x01, 0x55, 0x00,
+        ]));
+        block_evm.evm.db().unwrap().insert_account_info(
+            contract,
+            AccountInfo {
+                code_hash: code.hash_slow(),
+                code: Some(code),
+                ..Default::default()
+            },
+        );
+        block_evm.evm.db().unwrap().insert_account_info(
+            Address::from(tx.from.0),
+            AccountInfo {
+                balance: U256::from(10_000_000),
+                ..Default::default()
+            },
+        );
+        let outcome = block_evm
+            .add_transaction_environment(tx)
+            .unwrap()
+            .execute_without_inspector()
+            .unwrap();
+        assert!(outcome.result.is_success());
+        let storage = &outcome.state.get(&contract).unwrap().storage;
+        let mut hash = B256::with_last_byte(1);
+        hash.0[0] = VERSIONED_HASH_VERSION_KZG;
+        assert_eq!(
+            storage.get(&U256::ZERO).unwrap().present_value,
+            U256::from_be_bytes(hash.0)
+        );
+        assert_eq!(
+            storage.get(&U256::from(1)).unwrap().present_value,
+            U256::from(2)
+        );
+    }
+
+    #[test]
+    fn test_blob_tx_before_cancun_unsupported() {
+        let mut block_evm = evm_factory();
+        block_evm.evm.env.cfg.spec_id = SpecId::SHANGHAI;
+        assert_eq!(
+            block_evm
+                .add_transaction_environment(blob_tx_factory(5))
+                .err(),
+            Some(EvmError::UnsupportedTxType(3))
+        );
+    }
+
+    #[test]
+    fn test_execution_committed_once() {
+        let mut tx = tx_factory();
+        tx.gas_price = Some(EU256::zero());
+        tx.value = EU256::from(3);
+        tx.v = 27.into();
+        let sender = Address::from(tx.from.0);
+        let recipient = Address::from(tx.to.unwrap().0);
+        let mut block_evm = evm_factory();
+        block_evm.evm.db().unwrap().insert_account_info(
+            sender,
+            AccountInfo {
+                balance: U256::from(10),
+                ..Default::default()
+            },
+        );
+        let outcome = block_evm
+            .add_transaction_environment(tx)
+            .unwrap()
+            .execute_without_inspector()
+            .unwrap();
+        assert!(outcome.result.is_success());
+        let db = block_evm.evm.db().unwrap();
+        let sender_info = DatabaseRef::basic(&*db, sender).unwrap().unwrap();
+        assert_eq!(sender_info.nonce, 1);
+        assert_eq!(sender_info.balance, U256::from(7));
+        let recipient_info = DatabaseRef::basic(&*db, recipient).unwrap().unwrap();
+        assert_eq!(recipient_info.balance, U256::from(3));
+    }
+
+    #[test]
+    fn test_trace_written_to_output() {
+        let mut tx = tx_factory();
+        tx.gas_price = Some(EU256::zero());
+        tx.v = 27.into();
+        let buffer = SharedBuffer::default();
+        let mut block_evm = evm_factory();
+        block_evm
+            .add_transaction_environment(tx)
+            .unwrap()
+            .execute_with_inspector_eip3155(Box::new(buffer.clone()))
+            .unwrap();
+        assert!(!buffer.contents().is_empty());
+    }
+
+    #[test]
+    fn test_struct_logger_trace() {
+        let (mut block_evm, _) = contract_call_evm(&SSTORE_CODE);
+        let buffer = SharedBuffer::default();
+        let config = StructLoggerConfig {
+            disable_memory: true,
+            ..Default::default()
+        };
+        block_evm
+            .execute_with_trace(TraceFormat::StructLog(config), Box::new(buffer.clone()))
+            .unwrap();
+        let trace: StructLogTrace = serde_json::from_slice(&buffer.contents()).unwrap();
+        assert!(!trace.failed);
+        let ops: Vec<&str> = trace.struct_logs.iter().map(|l| l.op.as_str()).collect();
+        assert_eq!(ops, ["PUSH1", "PUSH1", "SSTORE", "STOP"]);
+        let sstore = &trace.struct_logs[2];
+        assert_eq!(sstore.pc, 4);
+        assert_eq!(sstore.depth, 1);
+        assert_eq!(trace.struct_logs[0].gas_cost, 3);
+        assert_eq!(
+            sstore.stack,
+            Some(vec!["0x1".to_string(), "0x0".to_string()])
+        );
+        assert_eq!(sstore.memory, None);
+        let storage = sstore.storage.as_ref().unwrap();
+        assert_eq!(
+            storage.get(&format!("{:064x}", 0)).unwrap(),
+            &format!("{:064x}", 1)
+        );
+        assert_eq!(
+            trace.struct_logs[0].gas - trace.struct_logs[3].gas,
+            3 + 3 + sstore.gas_cost
+        );
+        assert_eq!(trace.gas, 21_000 + 3 + 3 + sstore.gas_cost);
+    }
+
+    #[test]
+    fn test_filtered_trace() {
+        let (mut block_evm, _) = contract_call_evm(&SSTORE_CODE);
+        let buffer = SharedBuffer::default();
+        let filter = StepFilter {
+            opcodes: Some([revm::interpreter::opcode::SSTORE].into()),
+            ..Default::default()
+        };
+        block_evm
+            .execute_with_trace(
+                TraceFormat::Eip3155Filtered(filter),
+                Box::new(buffer.clone()),
+            )
+            .unwrap();
+        let contents = buffer.contents();
+        let lines: Vec<TracedStep> = serde_json::Deserializer::from_slice(&contents)
+            .into_iter()
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].op_name, "SSTORE");
+        assert_eq!(lines[0].pc, 4);
+    }
+
+    #[test]
+    fn test_call_tracer_trace() {
+        let (mut block_evm, tx) = contract_call_evm(&CALL_CODE);
+        let buffer = SharedBuffer::default();
+        block_evm
+            .execute_with_trace(
+                TraceFormat::Call(CallTracerConfig::default()),
+                Box::new(buffer.clone()),
+            )
+            .unwrap();
+        let trace: CallFrame = serde_json::from_slice(&buffer.contents()).unwrap();
+        assert_eq!(trace.call_type, "CALL");
+        assert_eq!(trace.from, tx.from);
+        assert_eq!(trace.to, tx.to);
+        assert_eq!(trace.gas.as_u64(), 100_000);
+        assert_eq!(trace.error, None);
+        assert_eq!(trace.calls.len(), 1);
+        let inner = &trace.calls[0];
+        assert_eq!(inner.call_type, "CALL");
+        assert_eq!(inner.from, tx.to.unwrap());
+        assert_eq!(inner.to, Some(H160::from_low_u64_be(0x42)));
+        assert_eq!(inner.gas_used.as_u64(), 0);
+        assert_eq!(inner.value, Some(EU256::zero()));
+    }
+
+    #[test]
+    fn test_parity_tracer_trace() {
+        let (mut block_evm, tx) = contract_call_evm(&CALL_CODE);
+        let buffer = SharedBuffer::default();
+        block_evm
+            .execute_with_trace(
+                TraceFormat::Parity(ParityTraceTypes::all()),
+                Box::new(buffer.clone()),
+            )
+            .unwrap();
+        let result: ParityTraceResult = serde_json::from_slice(&buffer.contents()).unwrap();
+        assert_eq!(result.transaction_hash, Some(tx.hash));
+        let trace = result.trace.unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].subtraces, 1);
+        assert_eq!(trace[1].trace_address, vec![0]);
+        assert_eq!(trace[1].trace_type, "call");
+        let vm_trace = result.vm_trace.unwrap();
+        // Six PUSH1, GAS, CALL and STOP.
+        assert_eq!(vm_trace.ops.len(), 9);
+        assert_eq!(vm_trace.ops[0].cost, 3);
+        assert_eq!(
+            vm_trace.ops[0].ex.as_ref().unwrap().push,
+            vec![EU256::zero()]
+        );
+        assert!(vm_trace.ops[7].sub.is_some());
+        let state_diff = result.state_diff.unwrap();
+        let sender = &state_diff[&tx.from];
+        assert_eq!(sender.nonce, Delta::Added(EU256::one()));
+        assert!(!state_diff.contains_key(&tx.to.unwrap()));
+    }
+
+    #[test]
+    fn test_prestate_tracer_output_is_inventory_compatible() {
+        let mut tx = tx_factory();
+        tx.gas_price = Some(EU256::zero());
+        tx.value = EU256::from(3);
+        tx.v = 27.into();
+        let sender = Address::from(tx.from.0);
+        let mut block_evm = evm_factory();
+        block_evm.evm.db().unwrap().insert_account_info(
+            sender,
+            AccountInfo {
+                balance: U256::from(10),
+                nonce: 0,
+                ..Default::default()
+            },
+        );
+        let buffer = SharedBuffer::default();
+        block_evm
+            .add_transaction_environment(tx.clone())
+            .unwrap()
+            .execute_with_trace(
+                TraceFormat::Prestate(PrestateTracerConfig::default()),
+                Box::new(buffer.clone()),
+            )
+            .unwrap();
+        let prestate: TransactionAccountStates =
+            serde_json::from_slice(&buffer.contents()).unwrap();
+        let sender_state = prestate.get(&format!("{:?}", tx.from)).unwrap();
+        assert_eq!(sender_state.balance, "0xa");
+        assert_eq!(sender_state.nonce, None);
+        assert!(prestate.contains_key(&format!("{:?}", tx.to.unwrap())));
+    }
+}
@@ -0,0 +1,321 @@
+//! For tracing only the EVM steps of interest (E.g., the REVERTs of a transaction).
+//!
+//! Each step is checked against a filter before anything is read from the interpreter, and
+//! only matching steps are written, as EIP-3155 style JSON lines. Tracing a heavy
+//! transaction to find a few steps then costs little more than executing it.
+//!
+//! The gas cost of calls is that of the struct logger trace (see `struct_log::returned_gas`).
+
+use std::{collections::BTreeSet, io, io::Write, ops::RangeInclusive};
+
+use revm::{
+    interpreter::{CallInputs, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{Address, Bytes},
+    Database, EVMData, Inspector,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::struct_log::{op_name, returned_gas};
+
+/// Selects the EVM steps to trace. A step is traced if it matches every criterion that is
+/// set, so the default filter matches every step.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StepFilter {
+    /// Opcodes (E.g., `revm::interpreter::opcode::REVERT`).
+    pub opcodes: Option<BTreeSet<u8>>,
+    /// Addresses of the code being executed. For DELEGATECALL and CALLCODE this is the
+    /// account the code is from, not the account whose storage is used.
+    pub addresses: Option<BTreeSet<Address>>,
+    /// Call depth, starting at 1.
+    pub depth: Option<RangeInclusive<u64>>,
+    /// Program counter.
+    pub pc: Option<RangeInclusive<usize>>,
+}
+
+impl StepFilter {
+    /// Whether a step is traced.
+    pub fn matches(&self, op: u8, code_address: &Address, depth: u64, pc: usize) -> bool {
+        self.opcodes.as_ref().is_none_or(|ops| ops.contains(&op))
+            && self
+                .depth
+                .as_ref()
+                .is_none_or(|range| range.contains(&depth))
+            && self.pc.as_ref().is_none_or(|range| range.contains(&pc))
+            && self
+                .addresses
+                .as_ref()
+                .is_none_or(|addresses| addresses.contains(code_address))
+    }
+}
+
+/// A traced EVM step, in the form of an EIP-3155 trace line.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracedStep {
+    pub pc: usize,
+    pub op: u8,
+    /// Gas remaining before the step, as a 0x-prefixed quantity.
+    pub gas: String,
+    /// Gas used by the step, as a 0x-prefixed quantity.
+    pub gas_cost: String,
+    pub mem_size: usize,
+    /// Stack items (bottom first), as 0x-prefixed quantities.
+    pub stack: Vec<String>,
+    /// Call depth, starting at 1.
+    pub depth: u64,
+    pub op_name: String,
+}
+
+/// An inspector that writes the EVM steps that match a filter, one JSON line per step.
+///
+/// Unlike the EIP-3155 tracer, no summary line is written at the end of the transaction.
+pub struct FilteredTracer {
+    filter: StepFilter,
+    output: Box<dyn Write>,
+    /// Steps that have started but not ended, one per call depth. Steps that do not match
+    /// the filter are held as None.
+    pending: Vec<Option<PendingStep>>,
+    /// Address of the code executing at each call depth.
+    code_addresses: Vec<Address>,
+    /// Address of the code of the most recent call, for the frame it enters.
+    next_code_address: Option<Address>,
+    /// The first error writing to the output.
+    error: Option<io::Error>,
+}
+
+/// A matching step, awaiting its gas cost.
+#[derive(Clone, Debug)]
+struct PendingStep {
+    step: TracedStep,
+    /// Gas remaining before the step.
+    gas: u64,
+    /// Gas returned from a call or create made by the step.
+    returned_gas: u64,
+}
+
+impl FilteredTracer {
+    pub fn new(filter: StepFilter, output: Box<dyn Write>) -> Self {
+        Self {
+            filter,
+            output,
+            pending: vec![],
+            code_addresses: vec![],
+            next_code_address: None,
+            error: None,
+        }
+    }
+    /// Flushes the output, returning the first error met while writing steps.
+    pub fn finish(mut self) -> Result<(), io::Error> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.output.flush(),
+        }
+    }
+    /// Address of the code executing at the given depth, noting a newly entered frame.
+    fn code_address(&mut self, depth: usize, context_address: Address) -> Address {
+        if depth > self.code_addresses.len() {
+            let address = self.next_code_address.take().unwrap_or(context_address);
+            self.code_addresses.push(address);
+        }
+        self.code_addresses.truncate(depth);
+        self.code_addresses
+            .last()
+            .copied()
+            .unwrap_or(context_address)
+    }
+    fn write_step(&mut self, step: &TracedStep) {
+        if self.error.is_some() {
+            return;
+        }
+        let written = serde_json::to_writer(&mut self.output, step)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(self.output));
+        if let Err(error) = written {
+            self.error = Some(error);
+        }
+    }
+    /// Adds gas returned by a completed call or create to the step that made it.
+    fn return_gas(&mut self, result: InstructionResult, remaining: &Gas) {
+        if let Some(Some(step)) = self.pending.last_mut() {
+            step.returned_gas += returned_gas(result, remaining);
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for FilteredTracer {
+    fn step(&mut self, interp: &mut Interpreter, data: &mut EVMData<'_, DB>) -> InstructionResult {
+        let depth = data.journaled_state.depth();
+        let op = interp.current_opcode();
+        let pc = interp.program_counter();
+        let code_address = self.code_address(depth as usize, interp.contract.address);
+
+        let pending = self.filter.matches(op, &code_address, depth, pc).then(|| {
+            let gas = interp.gas.remaining();
+            PendingStep {
+                step: TracedStep {
+                    pc,
+                    op,
+                    gas: format!("{gas:#x}"),
+                    gas_cost: String::new(),
+                    mem_size: interp.memory.len(),
+                    stack: interp
+                        .stack
+                        .data()
+                        .iter()
+                        .map(|item| format!("0x{item:x}"))
+                        .collect(),
+                    depth,
+                    op_name: op_name(op),
+                },
+                gas,
+                returned_gas: 0,
+            }
+        });
+        self.pending.truncate(depth as usize - 1);
+        self.pending.push(pending);
+        InstructionResult::Continue
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        _data: &mut EVMData<'_, DB>,
+        _eval: InstructionResult,
+    ) -> InstructionResult {
+        let Some(Some(mut pending)) = self.pending.pop() else {
+            return InstructionResult::Continue;
+        };
+        let cost = (pending.gas + pending.returned_gas).saturating_sub(interp.gas.remaining());
+        pending.step.gas_cost = format!("{cost:#x}");
+        self.write_step(&pending.step);
+        InstructionResult::Continue
+    }
+
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.next_code_address = Some(inputs.contract);
+        (InstructionResult::Continue, Gas::new(0), Bytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.return_gas(ret, &remaining_gas);
+        (ret, remaining_gas, out)
+    }
+
+    fn create(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        // Init code runs as the new account.
+        self.next_code_address = None;
+        (InstructionResult::Continue, None, Gas::new(0), Bytes::new())
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: Bytes,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        self.return_gas(ret, &remaining_gas);
+        (ret, address, remaining_gas, out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use revm::interpreter::opcode;
+
+    use super::*;
+    use crate::{
+        sink::SharedBuffer,
+        test_utils::{contract, evm_calling},
+    };
+
+    /// Reads slot 1, stores slot 2, then reverts.
+    const CODE: [u8; 11] = [
+        0x60, 0x01, 0x54, 0x60, 0x02, 0x55, 0x60, 0x00, 0x60, 0x00, 0xfd,
+    ];
+
+    fn trace(filter: StepFilter) -> Vec<TracedStep> {
+        let mut evm = evm_calling(&CODE);
+        let buffer = SharedBuffer::default();
+        let mut tracer = FilteredTracer::new(filter, Box::new(buffer.clone()));
+        let outcome = evm.inspect(&mut tracer).unwrap();
+        assert!(!outcome.result.is_success());
+        tracer.finish().unwrap();
+        buffer
+            .contents()
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let address = contract();
+        let filter = StepFilter {
+            opcodes: Some(BTreeSet::from([opcode::SSTORE])),
+            depth: Some(1..=2),
+            ..Default::default()
+        };
+        assert!(filter.matches(opcode::SSTORE, &address, 2, 7));
+        assert!(!filter.matches(opcode::SLOAD, &address, 2, 7));
+        assert!(!filter.matches(opcode::SSTORE, &address, 3, 7));
+        assert!(StepFilter::default().matches(opcode::STOP, &address, 1024, 0));
+        let filter = StepFilter {
+            addresses: Some(BTreeSet::from([Address::with_last_byte(0xaa)])),
+            pc: Some(0..=10),
+            ..Default::default()
+        };
+        assert!(!filter.matches(opcode::STOP, &address, 1, 0));
+    }
+
+    #[test]
+    fn test_filtered_tracer_writes_matching_steps() {
+        let steps = trace(StepFilter {
+            opcodes: Some(BTreeSet::from([opcode::SSTORE, opcode::REVERT])),
+            ..Default::default()
+        });
+        let ops: Vec<&str> = steps.iter().map(|s| s.op_name.as_str()).collect();
+        assert_eq!(ops, ["SSTORE", "REVERT"]);
+        let sstore = &steps[0];
+        assert_eq!(sstore.pc, 5);
+        assert_eq!(sstore.depth, 1);
+        assert_eq!(sstore.stack, ["0x0", "0x2"]);
+        assert_ne!(sstore.gas_cost, "0x0");
+        assert_eq!(steps[1].gas_cost, "0x0");
+    }
+
+    #[test]
+    fn test_filtered_tracer_by_pc_and_address() {
+        let steps = trace(StepFilter {
+            pc: Some(2..=3),
+            addresses: Some(BTreeSet::from([contract()])),
+            ..Default::default()
+        });
+        let ops: Vec<&str> = steps.iter().map(|s| s.op_name.as_str()).collect();
+        assert_eq!(ops, ["SLOAD", "PUSH1"]);
+        let other_code = trace(StepFilter {
+            addresses: Some(BTreeSet::from([Address::with_last_byte(0xaa)])),
+            ..Default::default()
+        });
+        assert!(other_code.is_empty());
+    }
+}
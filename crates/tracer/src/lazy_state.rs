@@ -0,0 +1,405 @@
+//! For reading state for an historical block on demand.
+//!
+//! Rather than copying all the state into an in-memory DB before execution (see
+//! build_state_from_proofs()), the state is read from the block state data as the EVM
+//! requests it. Each value is read from the data once and then held.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use archors_types::{
+    execution::{EvmStateError, StateForEvm},
+    utils::hex_encode,
+};
+use revm::{
+    db::{CacheDB, DatabaseRef, EmptyDB},
+    primitives::{
+        keccak256, AccountInfo, Address, Bytecode, HashMap as rHashMap, B256, KECCAK_EMPTY, U256,
+    },
+    Database,
+};
+
+/// A revm database that reads state from block state data (StateForEvm) as it is needed.
+///
+/// The database is read-only. Changes made during execution are held by a CacheDB
+/// that wraps this database.
+///
+/// Beacon roots read from the beacon roots contract (EIP-4788) are read from the proven
+/// storage of the contract, like any other storage.
+///
+/// ### Strict mode
+/// By default state that is not in the data is read as empty (an account that does
+/// not exist, or a zero storage value). Block state data is expected to hold all state
+/// accessed by the block, so in strict mode any state that is not in the data is an error.
+/// A trace can then not be produced from incomplete (or incorrect) data.
+#[derive(Clone)]
+pub struct LazyStateDb<S: StateForEvm> {
+    state: S,
+    /// Whether reading state that is not in the data is an error.
+    strict: bool,
+    /// Values that have been read from the state.
+    read: RefCell<ReadState>,
+}
+
+/// State that has been read from block state data.
+#[derive(Clone, Debug, Default)]
+struct ReadState {
+    /// Map of address -> account (None if the account does not exist).
+    accounts: HashMap<Address, Option<AccountInfo>>,
+    /// Map of address -> all storage key-val pairs of the account.
+    storage: HashMap<Address, rHashMap<U256, U256>>,
+    /// Map of code hash -> code.
+    code: HashMap<B256, Bytecode>,
+    /// Map of block number -> block hash. Read in full when first needed.
+    block_hashes: Option<rHashMap<U256, B256>>,
+}
+
+impl<S: StateForEvm> LazyStateDb<S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            state,
+            strict: false,
+            read: RefCell::new(ReadState::default()),
+        }
+    }
+    /// Sets whether reading state that is not in the data is an error.
+    pub fn set_strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+    /// The block state data.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+    /// Returns the block state data.
+    pub fn into_state(self) -> S {
+        self.state
+    }
+    /// Creates an in-memory DB holding the state that has been read so far.
+    ///
+    /// Re-executing the transactions that were executed with this database only reads
+    /// state that was read the first time, so the DB can be used to execute them again
+    /// without the block state data.
+    pub fn read_state_db(&self) -> Result<CacheDB<EmptyDB>, EvmStateError> {
+        let read = self.read.borrow();
+        let mut db = CacheDB::new(EmptyDB::default());
+        for (address, info) in &read.accounts {
+            if let Some(info) = info {
+                db.insert_account_info(*address, info.clone());
+            }
+        }
+        for (address, storage) in &read.storage {
+            if !matches!(read.accounts.get(address), Some(Some(_))) {
+                continue;
+            }
+            db.replace_account_storage(*address, storage.clone())
+                .map_err(|source| EvmStateError::AccountStorageInit {
+                    error: source.to_string(),
+                    address: hex_encode(address),
+                })?;
+        }
+        for (hash, code) in &read.code {
+            db.contracts.insert(*hash, code.clone());
+        }
+        db.block_hashes = read.block_hashes.clone().unwrap_or_default();
+        Ok(db)
+    }
+    /// Reads the storage of an account, holding it for later reads.
+    fn read_storage(&self, address: Address) -> Result<(), EvmStateError> {
+        if self.read.borrow().storage.contains_key(&address) {
+            return Ok(());
+        }
+        let storage = match self.state.get_account_storage(&address) {
+            Ok(storage) => storage,
+            Err(EvmStateError::NoProofForAddress(_)) => rHashMap::default(),
+            Err(e) => return Err(e),
+        };
+        self.read.borrow_mut().storage.insert(address, storage);
+        Ok(())
+    }
+}
+
+impl<S: StateForEvm> DatabaseRef for LazyStateDb<S> {
+    type Error = EvmStateError;
+
+    fn basic(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        let held = self.read.borrow().accounts.get(&address).cloned();
+        let info = match held {
+            Some(info) => info,
+            None => {
+                let info = match self.state.get_account_info(&address) {
+                    Ok(mut info) => {
+                        // The EVM treats any hash other than that of empty code as code.
+                        if info.code_hash == B256::ZERO {
+                            info.code_hash = KECCAK_EMPTY;
+                        }
+                        Some(info)
+                    }
+                    Err(EvmStateError::NoProofForAddress(_)) => None,
+                    Err(e) => return Err(e),
+                };
+                let mut read = self.read.borrow_mut();
+                if let Some(info) = &info {
+                    if let Some(code) = &info.code {
+                        read.code.insert(info.code_hash, code.clone());
+                    }
+                }
+                read.accounts.insert(address, info.clone());
+                info
+            }
+        };
+        if self.strict {
+            match &info {
+                None => return Err(EvmStateError::MissingAccount(hex_encode(address))),
+                Some(info) if info.code.is_none() && has_code(info.code_hash) => {
+                    return Err(EvmStateError::MissingCode(hex_encode(info.code_hash)))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(info)
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        let code = self.read.borrow().code.get(&code_hash).cloned();
+        match code {
+            Some(code) => Ok(code),
+            None if self.strict && has_code(code_hash) => {
+                Err(EvmStateError::MissingCode(hex_encode(code_hash)))
+            }
+            None => Ok(Bytecode::default()),
+        }
+    }
+
+    fn storage(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.read_storage(address)?;
+        let value = self
+            .read
+            .borrow()
+            .storage
+            .get(&address)
+            .and_then(|storage| storage.get(&index).copied());
+        match value {
+            Some(value) => Ok(value),
+            None if self.strict => Err(EvmStateError::MissingStorage {
+                address: hex_encode(address),
+                slot: hex_encode(B256::from(index.to_be_bytes::<32>())),
+            }),
+            None => Ok(U256::ZERO),
+        }
+    }
+
+    fn block_hash(&self, number: U256) -> Result<B256, Self::Error> {
+        if self.read.borrow().block_hashes.is_none() {
+            let hashes = self.state.get_blockhash_accesses()?;
+            self.read.borrow_mut().block_hashes = Some(hashes);
+        }
+        let hash = self
+            .read
+            .borrow()
+            .block_hashes
+            .as_ref()
+            .and_then(|hashes| hashes.get(&number).copied());
+        match hash {
+            Some(hash) => Ok(hash),
+            None if self.strict => Err(EvmStateError::MissingBlockHash(number.to_string())),
+            // Same as a missing block hash in an in-memory DB.
+            None => Ok(keccak256(number.to_be_bytes::<{ U256::BYTES }>())),
+        }
+    }
+}
+
+impl<S: StateForEvm> Database for LazyStateDb<S> {
+    type Error = EvmStateError;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        DatabaseRef::basic(self, address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        DatabaseRef::code_by_hash(self, code_hash)
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        DatabaseRef::storage(self, address, index)
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        DatabaseRef::block_hash(self, number)
+    }
+}
+
+/// Whether a code hash refers to code. Some state data gives accounts without code a zero
+/// code hash, rather than the hash of empty code.
+fn has_code(code_hash: B256) -> bool {
+    code_hash != KECCAK_EMPTY && code_hash != B256::ZERO
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, collections::HashMap as StdHashMap};
+
+    use archors_types::proof::{DisplayProof, DisplayStorageProof};
+    use revm::primitives::Account;
+
+    use super::*;
+
+    /// State that counts the number of times accounts are read.
+    #[derive(Default)]
+    struct CountingState {
+        accounts: StdHashMap<Address, AccountInfo>,
+        storage: StdHashMap<Address, rHashMap<U256, U256>>,
+        reads: Cell<usize>,
+    }
+
+    impl StateForEvm for CountingState {
+        fn get_account_info(&self, address: &Address) -> Result<AccountInfo, EvmStateError> {
+            self.reads.set(self.reads.get() + 1);
+            self.accounts
+                .get(address)
+                .cloned()
+                .ok_or_else(|| EvmStateError::NoProofForAddress(hex_encode(address)))
+        }
+        fn addresses(&self) -> Vec<Address> {
+            self.accounts.keys().copied().collect()
+        }
+        fn get_account_storage(
+            &self,
+            address: &Address,
+        ) -> Result<rHashMap<U256, U256>, EvmStateError> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(self.storage.get(address).cloned().unwrap_or_default())
+        }
+        fn get_blockhash_accesses(&self) -> Result<rHashMap<U256, B256>, EvmStateError> {
+            Ok(rHashMap::from_iter([(
+                U256::from(7),
+                B256::with_last_byte(9),
+            )]))
+        }
+        fn state_root_post_block(
+            &mut self,
+            _changes: StdHashMap<Address, Account>,
+        ) -> Result<B256, EvmStateError> {
+            Err(EvmStateError::PostRoot(
+                "test state has no proofs".to_string(),
+            ))
+        }
+        fn print_account_proof<T: AsRef<str>>(
+            &self,
+            _account_address: T,
+        ) -> Result<DisplayProof, EvmStateError> {
+            Err(EvmStateError::DisplayError(
+                "test state has no proofs".to_string(),
+            ))
+        }
+        fn print_storage_proof<T: AsRef<str>>(
+            &self,
+            _account_address: T,
+            _storage_key: T,
+        ) -> Result<DisplayStorageProof, EvmStateError> {
+            Err(EvmStateError::DisplayError(
+                "test state has no proofs".to_string(),
+            ))
+        }
+    }
+
+    fn lazy_db() -> LazyStateDb<CountingState> {
+        let address = Address::with_last_byte(1);
+        let mut state = CountingState::default();
+        state.accounts.insert(
+            address,
+            AccountInfo {
+                balance: U256::from(5),
+                ..Default::default()
+            },
+        );
+        state.storage.insert(
+            address,
+            rHashMap::from_iter([(U256::from(1), U256::from(2))]),
+        );
+        LazyStateDb::new(state)
+    }
+
+    #[test]
+    fn test_lazy_state_read_once() {
+        let db = lazy_db();
+        let address = Address::with_last_byte(1);
+        for _ in 0..3 {
+            let info = DatabaseRef::basic(&db, address).unwrap().unwrap();
+            assert_eq!(info.balance, U256::from(5));
+            let value = DatabaseRef::storage(&db, address, U256::from(1)).unwrap();
+            assert_eq!(value, U256::from(2));
+        }
+        assert_eq!(db.state().reads.get(), 2);
+        // Accounts not in the state do not exist.
+        let missing = Address::with_last_byte(2);
+        assert_eq!(DatabaseRef::basic(&db, missing).unwrap(), None);
+        assert_eq!(
+            DatabaseRef::storage(&db, missing, U256::from(1)).unwrap(),
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn test_strict_lazy_state_missing_state() {
+        let mut db = lazy_db();
+        db.set_strict(true);
+        let address = Address::with_last_byte(1);
+        let missing = Address::with_last_byte(2);
+        assert!(DatabaseRef::basic(&db, address).unwrap().is_some());
+        assert_eq!(
+            DatabaseRef::storage(&db, address, U256::from(1)).unwrap(),
+            U256::from(2)
+        );
+        assert_eq!(
+            DatabaseRef::basic(&db, missing),
+            Err(EvmStateError::MissingAccount(hex_encode(missing)))
+        );
+        assert_eq!(
+            DatabaseRef::storage(&db, address, U256::from(3)),
+            Err(EvmStateError::MissingStorage {
+                address: hex_encode(address),
+                slot: hex_encode(B256::with_last_byte(3)),
+            })
+        );
+        assert_eq!(
+            DatabaseRef::block_hash(&db, U256::from(8)),
+            Err(EvmStateError::MissingBlockHash("8".to_string()))
+        );
+        assert!(matches!(
+            DatabaseRef::code_by_hash(&db, B256::with_last_byte(4)),
+            Err(EvmStateError::MissingCode(_))
+        ));
+    }
+
+    #[test]
+    fn test_lazy_state_block_hash() {
+        let db = lazy_db();
+        assert_eq!(
+            DatabaseRef::block_hash(&db, U256::from(7)).unwrap(),
+            B256::with_last_byte(9)
+        );
+    }
+
+    #[test]
+    fn test_read_state_db_holds_read_state() {
+        let db = lazy_db();
+        let address = Address::with_last_byte(1);
+        DatabaseRef::storage(&db, address, U256::from(1)).unwrap();
+        DatabaseRef::basic(&db, address).unwrap();
+        let read_state = db.read_state_db().unwrap();
+        assert_eq!(
+            DatabaseRef::basic(&read_state, address)
+                .unwrap()
+                .unwrap()
+                .balance,
+            U256::from(5)
+        );
+        assert_eq!(
+            DatabaseRef::storage(&read_state, address, U256::from(1)).unwrap(),
+            U256::from(2)
+        );
+    }
+}
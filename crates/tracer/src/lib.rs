@@ -1,3 +1,17 @@
+pub mod access_list;
+pub mod call;
+pub mod call_tracer;
 pub mod evm;
+pub mod filter;
+pub mod lazy_state;
+pub mod parity;
+pub mod prestate;
+pub mod receipts;
+pub mod sink;
+pub mod snapshot;
 pub mod state;
+pub mod struct_log;
+#[cfg(test)]
+mod test_utils;
 pub mod trace;
+pub mod what_if;
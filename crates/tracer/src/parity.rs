@@ -0,0 +1,755 @@
+//! For producing the traces of OpenEthereum/Erigon `trace_replayTransaction` and
+//! `trace_replayBlockTransactions`.
+//!
+//! Three trace types can be requested:
+//! - trace: a flat list of calls, each with its position in the call tree (`traceAddress`).
+//! - stateDiff: the changes to each account, marked as unchanged (`=`), created (`+`),
+//!   removed (`-`) or modified (`*`).
+//! - vmTrace: the EVM steps of each call, nested by call.
+
+use std::collections::BTreeMap;
+
+use archors_types::utils::{rb160_to_eh160, ru256_to_eh256, ru256_to_eu256};
+use ethers::types::{Bytes, H160, H256, U256, U64};
+use revm::{
+    db::DatabaseRef,
+    interpreter::{opcode, CallInputs, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{
+        Account, Address, Bytes as rBytes, ExecutionResult, HashMap as rHashMap, Output, SpecId,
+        B256, U256 as rU256,
+    },
+    Database, EVMData, Inspector,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    call_tracer::{CallFrame, CallTracer, CallTracerConfig},
+    prestate::{code, is_empty, is_removed, is_unused_precompile},
+    struct_log::returns_gas,
+};
+
+/// The trace types requested, as accepted by `trace_replayBlockTransactions`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ParityTraceTypes {
+    pub trace: bool,
+    pub state_diff: bool,
+    pub vm_trace: bool,
+}
+
+impl ParityTraceTypes {
+    /// All trace types.
+    pub fn all() -> Self {
+        Self {
+            trace: true,
+            state_diff: true,
+            vm_trace: true,
+        }
+    }
+    /// Reads the trace types from names (`"trace"`, `"stateDiff"`, `"vmTrace"`).
+    pub fn from_names(names: &[&str]) -> Self {
+        Self {
+            trace: names.contains(&"trace"),
+            state_diff: names.contains(&"stateDiff"),
+            vm_trace: names.contains(&"vmTrace"),
+        }
+    }
+}
+
+/// The traces of a transaction. Trace types that were not requested are null.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParityTraceResult {
+    pub output: Bytes,
+    pub state_diff: Option<StateDiff>,
+    pub trace: Option<Vec<TransactionTrace>>,
+    pub vm_trace: Option<VmTrace>,
+    pub transaction_hash: Option<H256>,
+}
+
+/// A call, create or suicide within a transaction.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTrace {
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Absent if the call failed.
+    pub result: Option<TraceOutput>,
+    /// Number of calls made directly by this call.
+    pub subtraces: usize,
+    /// Position in the call tree, as the index of the call at each depth.
+    pub trace_address: Vec<usize>,
+    /// call, create or suicide.
+    #[serde(rename = "type")]
+    pub trace_type: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Action {
+    Call(CallAction),
+    Create(CreateAction),
+    Suicide(SuicideAction),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallAction {
+    pub from: H160,
+    /// call, callcode, delegatecall or staticcall.
+    pub call_type: String,
+    pub gas: U64,
+    pub input: Bytes,
+    pub to: H160,
+    pub value: U256,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAction {
+    pub from: H160,
+    pub gas: U64,
+    pub init: Bytes,
+    pub value: U256,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuicideAction {
+    pub address: H160,
+    pub refund_address: H160,
+    pub balance: U256,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum TraceOutput {
+    Create(CreateOutput),
+    Call(CallOutput),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallOutput {
+    pub gas_used: U64,
+    pub output: Bytes,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOutput {
+    pub address: H160,
+    pub code: Bytes,
+    pub gas_used: U64,
+}
+
+/// Mapping of account address to the changes to that account.
+pub type StateDiff = BTreeMap<H160, AccountDiff>;
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccountDiff {
+    pub balance: Delta<U256>,
+    pub code: Delta<Bytes>,
+    pub nonce: Delta<U256>,
+    pub storage: BTreeMap<H256, Delta<H256>>,
+}
+
+/// Change to a value.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Delta<T> {
+    #[serde(rename = "=")]
+    Unchanged,
+    #[serde(rename = "+")]
+    Added(T),
+    #[serde(rename = "-")]
+    Removed(T),
+    #[serde(rename = "*")]
+    Changed(Change<T>),
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+impl<T: PartialEq> Delta<T> {
+    fn between(from: T, to: T) -> Self {
+        match from == to {
+            true => Delta::Unchanged,
+            false => Delta::Changed(Change { from, to }),
+        }
+    }
+}
+
+/// The EVM steps of a call.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VmTrace {
+    pub code: Bytes,
+    pub ops: Vec<VmOperation>,
+}
+
+/// A single EVM step.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VmOperation {
+    pub cost: u64,
+    /// Absent if the step failed.
+    pub ex: Option<VmExecutedOperation>,
+    pub pc: usize,
+    /// The steps of a call or create made by the step.
+    pub sub: Option<VmTrace>,
+}
+
+/// The effects of a step.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VmExecutedOperation {
+    /// Memory written by the step.
+    pub mem: Option<MemoryDiff>,
+    /// Stack items placed by the step.
+    pub push: Vec<U256>,
+    /// Storage written by the step.
+    pub store: Option<StorageDiff>,
+    /// Gas remaining after the step.
+    pub used: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MemoryDiff {
+    pub data: Bytes,
+    pub off: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StorageDiff {
+    pub key: U256,
+    pub val: U256,
+}
+
+/// An inspector that records calls and (if requested) EVM steps for Parity style traces.
+#[derive(Clone, Debug, Default)]
+pub struct ParityTracer {
+    types: ParityTraceTypes,
+    calls: CallTracer,
+    /// Steps of the calls that are executing.
+    vm_stack: Vec<VmTrace>,
+    /// Steps of the transaction, once complete.
+    vm_root: Option<VmTrace>,
+    /// Steps that have started but not ended, one per call depth.
+    pending: Vec<PendingOp>,
+}
+
+/// A step that has been recorded, awaiting its effects.
+#[derive(Clone, Debug)]
+struct PendingOp {
+    op: u8,
+    /// Gas remaining before the step.
+    gas: u64,
+    /// Gas returned from a call or create made by the step.
+    returned_gas: u64,
+    /// Memory region (offset, length) that the step writes.
+    mem: Option<(usize, usize)>,
+    store: Option<StorageDiff>,
+}
+
+impl ParityTracer {
+    pub fn new(types: ParityTraceTypes) -> Self {
+        Self {
+            types,
+            calls: CallTracer::new(CallTracerConfig::default()),
+            ..Default::default()
+        }
+    }
+    /// Combines the recorded calls and steps with the result of the transaction.
+    pub fn into_trace(
+        self,
+        result: &ExecutionResult,
+        state_diff: Option<StateDiff>,
+        transaction_hash: Option<H256>,
+    ) -> ParityTraceResult {
+        let output = match result {
+            ExecutionResult::Success { output, .. } => match output {
+                Output::Call(bytes) | Output::Create(bytes, _) => bytes.to_vec(),
+            },
+            ExecutionResult::Revert { output, .. } => output.to_vec(),
+            ExecutionResult::Halt { .. } => vec![],
+        };
+        let trace = match (self.types.trace, self.calls.into_execution_frame()) {
+            (true, Some((root, gas_limit))) => {
+                let mut traces = vec![];
+                flatten(&root, Some(gas_limit), vec![], &mut traces);
+                Some(traces)
+            }
+            (true, None) => Some(vec![]),
+            (false, _) => None,
+        };
+        ParityTraceResult {
+            output: Bytes::from(output),
+            state_diff,
+            trace,
+            vm_trace: self.vm_root.filter(|_| self.types.vm_trace),
+            transaction_hash,
+        }
+    }
+    /// Starts recording the steps of a new call.
+    fn enter_vm(&mut self) {
+        if self.types.vm_trace {
+            self.vm_stack.push(VmTrace::default());
+        }
+    }
+    /// Completes the steps of a call, adding them to the step that made the call.
+    fn exit_vm(&mut self, result: InstructionResult, remaining: &Gas) {
+        if !self.types.vm_trace {
+            return;
+        }
+        if let (true, Some(op)) = (returns_gas(result), self.pending.last_mut()) {
+            op.returned_gas += remaining.remaining();
+        }
+        let Some(trace) = self.vm_stack.pop() else {
+            return;
+        };
+        match self
+            .vm_stack
+            .last_mut()
+            .and_then(|parent| parent.ops.last_mut())
+        {
+            Some(caller) => caller.sub = Some(trace),
+            None => self.vm_root = Some(trace),
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for ParityTracer {
+    fn step(&mut self, interp: &mut Interpreter, data: &mut EVMData<'_, DB>) -> InstructionResult {
+        self.calls.step(interp, data);
+        if !self.types.vm_trace {
+            return InstructionResult::Continue;
+        }
+        let Some(trace) = self.vm_stack.last_mut() else {
+            return InstructionResult::Continue;
+        };
+        if trace.ops.is_empty() {
+            trace.code = Bytes::from(interp.contract.bytecode.original_bytecode_slice().to_vec());
+        }
+        let op = interp.current_opcode();
+        let stack = interp.stack.data();
+        trace.ops.push(VmOperation {
+            cost: 0,
+            ex: None,
+            pc: interp.program_counter(),
+            sub: None,
+        });
+        let store = match (op, stack.as_slice()) {
+            (opcode::SSTORE, [.., val, key]) => Some(StorageDiff {
+                key: ru256_to_eu256(*key),
+                val: ru256_to_eu256(*val),
+            }),
+            _ => None,
+        };
+        let depth = data.journaled_state.depth() as usize;
+        self.pending.truncate(depth.saturating_sub(1));
+        self.pending.push(PendingOp {
+            op,
+            gas: interp.gas.remaining(),
+            returned_gas: 0,
+            mem: memory_written(op, stack),
+            store,
+        });
+        InstructionResult::Continue
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, DB>,
+        eval: InstructionResult,
+    ) -> InstructionResult {
+        self.calls.step_end(interp, data, eval);
+        if !self.types.vm_trace {
+            return InstructionResult::Continue;
+        }
+        let (Some(pending), Some(trace)) = (self.pending.pop(), self.vm_stack.last_mut()) else {
+            return InstructionResult::Continue;
+        };
+        let Some(operation) = trace.ops.last_mut() else {
+            return InstructionResult::Continue;
+        };
+        let used = interp.gas.remaining();
+        operation.cost = (pending.gas + pending.returned_gas).saturating_sub(used);
+        let succeeded = matches!(
+            eval,
+            InstructionResult::Continue
+                | InstructionResult::Stop
+                | InstructionResult::Return
+                | InstructionResult::SelfDestruct
+        );
+        if !succeeded {
+            return InstructionResult::Continue;
+        }
+        let stack = interp.stack.data();
+        let pushed = stack_outputs(pending.op).min(stack.len());
+        let mem = pending.mem.and_then(|(off, len)| {
+            let data = interp.memory.data().get(off..off.checked_add(len)?)?;
+            Some(MemoryDiff {
+                data: Bytes::from(data.to_vec()),
+                off,
+            })
+        });
+        operation.ex = Some(VmExecutedOperation {
+            mem,
+            push: stack[stack.len() - pushed..]
+                .iter()
+                .map(|item| ru256_to_eu256(*item))
+                .collect(),
+            store: pending.store,
+            used,
+        });
+        InstructionResult::Continue
+    }
+
+    fn log(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        address: &Address,
+        topics: &[B256],
+        bytes: &rBytes,
+    ) {
+        self.calls.log(data, address, topics, bytes);
+    }
+
+    fn call(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, rBytes) {
+        self.enter_vm();
+        self.calls.call(data, inputs)
+    }
+
+    fn call_end(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: rBytes,
+    ) -> (InstructionResult, Gas, rBytes) {
+        self.exit_vm(ret, &remaining_gas);
+        self.calls.call_end(data, inputs, remaining_gas, ret, out)
+    }
+
+    fn create(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<Address>, Gas, rBytes) {
+        self.enter_vm();
+        self.calls.create(data, inputs)
+    }
+
+    fn create_end(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: rBytes,
+    ) -> (InstructionResult, Option<Address>, Gas, rBytes) {
+        self.exit_vm(ret, &remaining_gas);
+        self.calls
+            .create_end(data, inputs, ret, address, remaining_gas, out)
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: rU256) {
+        Inspector::<DB>::selfdestruct(&mut self.calls, contract, target, value);
+    }
+}
+
+/// Adds a call and the calls it made to a flat list of traces.
+///
+/// The gas available for execution is provided for the transaction call, as call
+/// frames give the transaction gas limit.
+fn flatten(
+    frame: &CallFrame,
+    execution_gas: Option<u64>,
+    trace_address: Vec<usize>,
+    traces: &mut Vec<TransactionTrace>,
+) {
+    let gas = execution_gas.map(U64::from).unwrap_or(frame.gas);
+    let to = frame.to.unwrap_or_default();
+    let value = frame.value.unwrap_or_default();
+    let (trace_type, action, result) = match frame.call_type.as_str() {
+        "CREATE" | "CREATE2" => (
+            "create",
+            Action::Create(CreateAction {
+                from: frame.from,
+                gas,
+                init: frame.input.clone(),
+                value,
+            }),
+            TraceOutput::Create(CreateOutput {
+                address: to,
+                code: frame.output.clone().unwrap_or_default(),
+                gas_used: frame.gas_used,
+            }),
+        ),
+        "SELFDESTRUCT" => {
+            traces.push(TransactionTrace {
+                action: Action::Suicide(SuicideAction {
+                    address: frame.from,
+                    refund_address: to,
+                    balance: value,
+                }),
+                error: None,
+                result: None,
+                subtraces: 0,
+                trace_address,
+                trace_type: "suicide".to_string(),
+            });
+            return;
+        }
+        call_type => (
+            "call",
+            Action::Call(CallAction {
+                from: frame.from,
+                call_type: call_type.to_lowercase(),
+                gas,
+                input: frame.input.clone(),
+                to,
+                value,
+            }),
+            TraceOutput::Call(CallOutput {
+                gas_used: frame.gas_used,
+                output: frame.output.clone().unwrap_or_default(),
+            }),
+        ),
+    };
+    let error = frame.error.as_deref().map(parity_error);
+    traces.push(TransactionTrace {
+        action,
+        result: error.is_none().then_some(result),
+        error,
+        subtraces: frame.calls.len(),
+        trace_address: trace_address.clone(),
+        trace_type: trace_type.to_string(),
+    });
+    for (index, call) in frame.calls.iter().enumerate() {
+        let mut address = trace_address.clone();
+        address.push(index);
+        flatten(call, None, address, traces);
+    }
+}
+
+/// Rewords a geth error as Parity does.
+fn parity_error(error: &str) -> String {
+    let reworded = match error {
+        "execution reverted" => "Reverted",
+        "out of gas" => "Out of gas",
+        "invalid jump destination" => "Bad jump destination",
+        "stack underflow" => "Stack underflow",
+        "stack limit reached 1024" => "Out of stack",
+        "write protection" => "Mutable Call In Static Context",
+        e if e.starts_with("invalid opcode") => "Bad instruction",
+        e => e,
+    };
+    reworded.to_string()
+}
+
+/// Creates the state diff of a transaction from the database (before the changes of the
+/// transaction are committed) and the changes.
+pub fn state_diff<D: DatabaseRef>(
+    db: &D,
+    changes: &rHashMap<Address, Account>,
+    spec: SpecId,
+) -> Result<StateDiff, D::Error> {
+    let mut diff = StateDiff::new();
+    for (address, account) in changes {
+        if is_unused_precompile(address, account, spec) || !account.is_touched() {
+            continue;
+        }
+        let pre = db.basic(*address)?.filter(|info| !is_empty(info));
+        let post = (!is_removed(account, spec)).then_some(&account.info);
+        let account_diff = match (pre, post) {
+            (None, None) => continue,
+            (None, Some(post)) => AccountDiff {
+                balance: Delta::Added(ru256_to_eu256(post.balance)),
+                code: Delta::Added(code(db, post)?.unwrap_or_default()),
+                nonce: Delta::Added(U256::from(post.nonce)),
+                storage: storage_diff(account, |_, present| {
+                    (!present.is_zero()).then_some(Delta::Added(present))
+                }),
+            },
+            (Some(pre), None) => AccountDiff {
+                balance: Delta::Removed(ru256_to_eu256(pre.balance)),
+                code: Delta::Removed(code(db, &pre)?.unwrap_or_default()),
+                nonce: Delta::Removed(U256::from(pre.nonce)),
+                storage: storage_diff(account, |original, _| {
+                    (!original.is_zero()).then_some(Delta::Removed(original))
+                }),
+            },
+            (Some(pre), Some(post)) => AccountDiff {
+                balance: Delta::between(ru256_to_eu256(pre.balance), ru256_to_eu256(post.balance)),
+                code: match pre.code_hash == post.code_hash {
+                    true => Delta::Unchanged,
+                    false => Delta::Changed(Change {
+                        from: code(db, &pre)?.unwrap_or_default(),
+                        to: code(db, post)?.unwrap_or_default(),
+                    }),
+                },
+                nonce: Delta::between(U256::from(pre.nonce), U256::from(post.nonce)),
+                storage: storage_diff(account, |original, present| {
+                    (original != present).then(|| Delta::between(original, present))
+                }),
+            },
+        };
+        let unchanged = matches!(account_diff.balance, Delta::Unchanged)
+            && matches!(account_diff.code, Delta::Unchanged)
+            && matches!(account_diff.nonce, Delta::Unchanged)
+            && account_diff.storage.is_empty();
+        if !unchanged {
+            diff.insert(rb160_to_eh160(address), account_diff);
+        }
+    }
+    Ok(diff)
+}
+
+/// Storage slot changes of an account, from the original and present value of each slot.
+fn storage_diff<F>(account: &Account, delta: F) -> BTreeMap<H256, Delta<H256>>
+where
+    F: Fn(H256, H256) -> Option<Delta<H256>>,
+{
+    account
+        .storage
+        .iter()
+        .filter_map(|(key, slot)| {
+            let change = delta(
+                ru256_to_eh256(slot.original_value()),
+                ru256_to_eh256(slot.present_value),
+            )?;
+            Some((ru256_to_eh256(*key), change))
+        })
+        .collect()
+}
+
+/// Number of stack items that a step places. For DUP and SWAP, this includes every
+/// item that the step moves.
+fn stack_outputs(op: u8) -> usize {
+    match op {
+        opcode::DUP1..=opcode::DUP16 => (op - opcode::DUP1) as usize + 2,
+        opcode::SWAP1..=opcode::SWAP16 => (op - opcode::SWAP1) as usize + 2,
+        opcode::STOP
+        | opcode::POP
+        | opcode::MSTORE
+        | opcode::MSTORE8
+        | opcode::SSTORE
+        | opcode::JUMP
+        | opcode::JUMPI
+        | opcode::JUMPDEST
+        | opcode::LOG0..=opcode::LOG4
+        | opcode::CALLDATACOPY
+        | opcode::CODECOPY
+        | opcode::EXTCODECOPY
+        | opcode::RETURNDATACOPY
+        | opcode::RETURN
+        | opcode::REVERT
+        | opcode::INVALID
+        | opcode::SELFDESTRUCT => 0,
+        _ if op_exists(op) => 1,
+        _ => 0,
+    }
+}
+
+fn op_exists(op: u8) -> bool {
+    opcode::OPCODE_JUMPMAP[op as usize].is_some()
+}
+
+/// Memory region (offset, length) written by a step, read from the stack before the step.
+fn memory_written(op: u8, stack: &[rU256]) -> Option<(usize, usize)> {
+    // Stack items counted from the top (0).
+    let item = |position: usize| -> Option<usize> {
+        let value = stack.len().checked_sub(position + 1).map(|i| stack[i])?;
+        usize::try_from(value).ok()
+    };
+    let (offset, length) = match op {
+        opcode::MSTORE => (item(0)?, 32),
+        opcode::MSTORE8 => (item(0)?, 1),
+        opcode::CALLDATACOPY | opcode::CODECOPY | opcode::RETURNDATACOPY => (item(0)?, item(2)?),
+        opcode::EXTCODECOPY => (item(1)?, item(3)?),
+        opcode::CALL | opcode::CALLCODE => (item(5)?, item(6)?),
+        opcode::DELEGATECALL | opcode::STATICCALL => (item(4)?, item(5)?),
+        _ => return None,
+    };
+    (length > 0).then_some((offset, length))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delta_serialization() {
+        let unchanged: Delta<U256> = Delta::Unchanged;
+        assert_eq!(serde_json::to_string(&unchanged).unwrap(), r#""=""#);
+        assert_eq!(
+            serde_json::to_string(&Delta::Added(U256::from(1))).unwrap(),
+            r#"{"+":"0x1"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Delta::between(U256::from(1), U256::from(2))).unwrap(),
+            r#"{"*":{"from":"0x1","to":"0x2"}}"#
+        );
+    }
+
+    #[test]
+    fn test_flatten_trace_addresses() {
+        let leaf = CallFrame {
+            call_type: "STATICCALL".to_string(),
+            ..Default::default()
+        };
+        let root = CallFrame {
+            call_type: "CALL".to_string(),
+            gas: U64::from(100_000),
+            calls: vec![
+                CallFrame {
+                    call_type: "CALL".to_string(),
+                    calls: vec![leaf.clone()],
+                    error: Some("execution reverted".to_string()),
+                    ..Default::default()
+                },
+                leaf,
+            ],
+            ..Default::default()
+        };
+        let mut traces = vec![];
+        flatten(&root, Some(79_000), vec![], &mut traces);
+        let addresses: Vec<Vec<usize>> = traces.iter().map(|t| t.trace_address.clone()).collect();
+        assert_eq!(addresses, vec![vec![], vec![0], vec![0, 0], vec![1]]);
+        assert_eq!(traces[0].subtraces, 2);
+        let Action::Call(action) = &traces[0].action else {
+            panic!("Expected call action")
+        };
+        assert_eq!(action.gas, U64::from(79_000));
+        assert_eq!(traces[1].error.as_deref(), Some("Reverted"));
+        assert_eq!(traces[1].result, None);
+        let Action::Call(action) = &traces[2].action else {
+            panic!("Expected call action")
+        };
+        assert_eq!(action.call_type, "staticcall");
+    }
+
+    #[test]
+    fn test_stack_outputs() {
+        assert_eq!(stack_outputs(opcode::ADD), 1);
+        assert_eq!(stack_outputs(opcode::DUP1), 2);
+        assert_eq!(stack_outputs(opcode::SWAP2), 3);
+        assert_eq!(stack_outputs(opcode::SSTORE), 0);
+    }
+
+    #[test]
+    fn test_memory_written_by_mstore() {
+        let stack = vec![rU256::from(5), rU256::from(64)];
+        assert_eq!(memory_written(opcode::MSTORE, &stack), Some((64, 32)));
+        assert_eq!(memory_written(opcode::ADD, &stack), None);
+    }
+}
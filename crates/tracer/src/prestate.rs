@@ -0,0 +1,355 @@
+//! For producing the trace of geth `debug_traceTransaction` with the `prestateTracer`.
+//!
+//! The prestate is the state of every account accessed by a transaction, as it was before
+//! the transaction. In diff mode, the state before and after the transaction is given for
+//! accounts that the transaction modified.
+//!
+//! The output has the same form as that of a node, and so can be used in place of
+//! a remote prestate trace (E.g., by archors_inventory).
+
+use std::collections::BTreeMap;
+
+use archors_types::utils::{rb160_to_eh160, ru256_to_eh256, ru256_to_eu256};
+use ethers::types::{Bytes, H160, H256, U256};
+use revm::{
+    db::DatabaseRef,
+    primitives::{Account, AccountInfo, Address, HashMap as rHashMap, SpecId, KECCAK_EMPTY},
+};
+use serde::{Deserialize, Serialize};
+
+/// Options for the prestate tracer, as accepted by geth `debug_traceTransaction`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PrestateTracerConfig {
+    /// State before and after the transaction is given for modified accounts.
+    pub diff_mode: bool,
+    pub disable_code: bool,
+    pub disable_storage: bool,
+}
+
+/// State of an account. Absent fields are either empty or (in diff mode) unchanged.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PrestateAccount {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage: Option<BTreeMap<H256, H256>>,
+}
+
+/// Mapping of account address to account state.
+pub type PrestateAccounts = BTreeMap<H160, PrestateAccount>;
+
+/// The state of modified accounts before and after a transaction.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PrestateDiff {
+    pub pre: PrestateAccounts,
+    pub post: PrestateAccounts,
+}
+
+/// The trace of a transaction in the geth prestate tracer format.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum PrestateTrace {
+    Diff(PrestateDiff),
+    Prestate(PrestateAccounts),
+}
+
+/// Creates the prestate trace of a transaction from the database (before the changes of
+/// the transaction are committed) and the changes.
+pub fn prestate_trace<D: DatabaseRef>(
+    config: PrestateTracerConfig,
+    db: &D,
+    changes: &rHashMap<Address, Account>,
+    spec: SpecId,
+) -> Result<PrestateTrace, D::Error> {
+    let mut pre = PrestateAccounts::new();
+    let mut post = PrestateAccounts::new();
+    for (address, account) in changes {
+        if is_unused_precompile(address, account, spec) {
+            continue;
+        }
+        let info = db.basic(*address)?;
+        let existed = info.as_ref().map(|i| !is_empty(i)).unwrap_or_default();
+        let info = info.unwrap_or_default();
+        let mut pre_account = PrestateAccount {
+            balance: Some(ru256_to_eu256(info.balance)),
+            nonce: (info.nonce > 0).then_some(info.nonce),
+            code: match config.disable_code {
+                true => None,
+                false => code(db, &info)?,
+            },
+            storage: None,
+        };
+        let mut pre_storage = BTreeMap::new();
+        let mut post_storage = BTreeMap::new();
+        if !config.disable_storage {
+            for (key, slot) in &account.storage {
+                let key = ru256_to_eh256(*key);
+                let original = ru256_to_eh256(slot.original_value());
+                let present = ru256_to_eh256(slot.present_value);
+                if config.diff_mode {
+                    if original == present {
+                        continue;
+                    }
+                    if !present.is_zero() {
+                        post_storage.insert(key, present);
+                    }
+                    if original.is_zero() {
+                        continue;
+                    }
+                }
+                pre_storage.insert(key, original);
+            }
+        }
+        pre_account.storage = (!pre_storage.is_empty()).then_some(pre_storage);
+        let address = rb160_to_eh160(address);
+        if !config.diff_mode {
+            pre.insert(address, pre_account);
+            continue;
+        }
+        if is_removed(account, spec) {
+            pre.insert(address, pre_account);
+            continue;
+        }
+        let post_account = PrestateAccount {
+            balance: (account.info.balance != info.balance)
+                .then(|| ru256_to_eu256(account.info.balance)),
+            nonce: (account.info.nonce != info.nonce).then_some(account.info.nonce),
+            code: match !config.disable_code && account.info.code_hash != info.code_hash {
+                true => code(db, &account.info)?,
+                false => None,
+            },
+            storage: (!post_storage.is_empty()).then_some(post_storage),
+        };
+        let modified = post_account != PrestateAccount::default() || pre_account.storage.is_some();
+        if !modified {
+            continue;
+        }
+        post.insert(address, post_account);
+        // Accounts created by the transaction had no prior state.
+        let created = account.is_created() && !existed;
+        if !created {
+            pre.insert(address, pre_account);
+        }
+    }
+    match config.diff_mode {
+        true => Ok(PrestateTrace::Diff(PrestateDiff { pre, post })),
+        false => Ok(PrestateTrace::Prestate(pre)),
+    }
+}
+
+/// Code of an account, if it has code.
+pub(crate) fn code<D: DatabaseRef>(db: &D, info: &AccountInfo) -> Result<Option<Bytes>, D::Error> {
+    if info.code_hash == KECCAK_EMPTY {
+        return Ok(None);
+    }
+    let code = match &info.code {
+        Some(code) => code.clone(),
+        None => db.code_by_hash(info.code_hash)?,
+    };
+    let bytes = code.original_bytes();
+    Ok((!bytes.is_empty()).then(|| Bytes::from(bytes.to_vec())))
+}
+
+/// An account is empty if it has no code, zero nonce and zero balance.
+pub(crate) fn is_empty(info: &AccountInfo) -> bool {
+    info.code_hash == KECCAK_EMPTY && info.nonce == 0 && info.balance.is_zero()
+}
+
+/// Returns true if the changes from a transaction remove the account from state.
+///
+/// The EVM applies EIP-6780, only marking an account as self destructed if it was created
+/// in the same transaction. Empty accounts that are touched are removed (EIP-161).
+pub(crate) fn is_removed(account: &Account, spec: SpecId) -> bool {
+    account.is_selfdestructed()
+        || (SpecId::enabled(spec, SpecId::SPURIOUS_DRAGON)
+            && account.is_touched()
+            && is_empty(&account.info))
+}
+
+/// Precompiles are always held by the EVM, but are only considered accessed if called.
+pub(crate) fn is_unused_precompile(address: &Address, account: &Account, spec: SpecId) -> bool {
+    let bytes = address.as_slice();
+    let is_precompile =
+        bytes[..19].iter().all(|b| *b == 0) && (1..=last_precompile(spec)).contains(&bytes[19]);
+    is_precompile && !account.is_touched()
+}
+
+/// Highest address of a precompile for a fork. The EVM holds precompiles as accessed
+/// from the start of a transaction, they are only included in a trace if used.
+fn last_precompile(spec: SpecId) -> u8 {
+    if SpecId::enabled(spec, SpecId::CANCUN) {
+        // Point evaluation (EIP-4844)
+        0x0a
+    } else if SpecId::enabled(spec, SpecId::ISTANBUL) {
+        // Blake2f (EIP-152)
+        0x09
+    } else if SpecId::enabled(spec, SpecId::BYZANTIUM) {
+        // Modexp and alt_bn128 (EIP-198, EIP-196, EIP-197)
+        0x08
+    } else {
+        0x04
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use revm::{
+        db::{CacheDB, EmptyDB},
+        primitives::{AccountStatus, StorageSlot, U256 as rU256},
+    };
+
+    use super::*;
+
+    fn account(info: AccountInfo, storage: Vec<(u64, u64, u64)>) -> Account {
+        Account {
+            info,
+            storage: storage
+                .into_iter()
+                .map(|(key, original, present)| {
+                    (
+                        rU256::from(key),
+                        StorageSlot::new_changed(rU256::from(original), rU256::from(present)),
+                    )
+                })
+                .collect(),
+            status: AccountStatus::Touched,
+        }
+    }
+
+    fn setup() -> (CacheDB<EmptyDB>, rHashMap<Address, Account>) {
+        let mut db = CacheDB::new(EmptyDB::default());
+        let sender = Address::with_last_byte(0xaa);
+        let contract = Address::with_last_byte(0xbb);
+        db.insert_account_info(
+            sender,
+            AccountInfo {
+                balance: rU256::from(100),
+                nonce: 1,
+                ..Default::default()
+            },
+        );
+        db.insert_account_info(
+            contract,
+            AccountInfo {
+                nonce: 1,
+                ..Default::default()
+            },
+        );
+        let changes = rHashMap::from_iter([
+            (
+                sender,
+                account(
+                    AccountInfo {
+                        balance: rU256::from(90),
+                        nonce: 2,
+                        ..Default::default()
+                    },
+                    vec![],
+                ),
+            ),
+            (
+                contract,
+                account(
+                    AccountInfo {
+                        nonce: 1,
+                        ..Default::default()
+                    },
+                    vec![(1, 5, 5), (2, 0, 7)],
+                ),
+            ),
+            (
+                Address::with_last_byte(1),
+                Account {
+                    status: AccountStatus::Loaded,
+                    ..account(AccountInfo::default(), vec![])
+                },
+            ),
+        ]);
+        (db, changes)
+    }
+
+    #[test]
+    fn test_prestate() {
+        let (db, changes) = setup();
+        let trace = prestate_trace(
+            PrestateTracerConfig::default(),
+            &db,
+            &changes,
+            SpecId::SHANGHAI,
+        )
+        .unwrap();
+        let json = serde_json::to_value(&trace).unwrap();
+        let sender = &json["0x00000000000000000000000000000000000000aa"];
+        assert_eq!(sender["balance"], "0x64");
+        assert_eq!(sender["nonce"], 1);
+        let contract = &json["0x00000000000000000000000000000000000000bb"];
+        assert_eq!(contract["balance"], "0x0");
+        assert_eq!(contract["nonce"], 1);
+        assert!(contract.get("code").is_none());
+        assert_eq!(contract["storage"].as_object().unwrap().len(), 2);
+        // Unused precompile excluded.
+        assert_eq!(json.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_prestate_diff_mode() {
+        let (db, changes) = setup();
+        let config = PrestateTracerConfig {
+            diff_mode: true,
+            ..Default::default()
+        };
+        let PrestateTrace::Diff(diff) =
+            prestate_trace(config, &db, &changes, SpecId::SHANGHAI).unwrap()
+        else {
+            panic!("Expected diff mode trace")
+        };
+        let sender = H160::from_low_u64_be(0xaa);
+        let contract = H160::from_low_u64_be(0xbb);
+        assert_eq!(diff.pre[&sender].balance, Some(U256::from(100)));
+        assert_eq!(diff.post[&sender].balance, Some(U256::from(90)));
+        assert_eq!(diff.post[&sender].nonce, Some(2));
+        // Unchanged slot 1 is omitted, slot 2 was empty before.
+        let slot_2 = H256::from_low_u64_be(2);
+        assert_eq!(diff.post[&contract].storage.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            diff.post[&contract].storage.as_ref().unwrap()[&slot_2],
+            H256::from_low_u64_be(7)
+        );
+        assert_eq!(diff.pre[&contract].storage, None);
+        assert_eq!(diff.post[&contract].balance, None);
+    }
+
+    #[test]
+    fn test_unused_precompiles_by_fork() {
+        let loaded = Account {
+            status: AccountStatus::Loaded,
+            ..account(AccountInfo::default(), vec![])
+        };
+        let point_evaluation = Address::with_last_byte(0x0a);
+        assert!(is_unused_precompile(
+            &point_evaluation,
+            &loaded,
+            SpecId::CANCUN
+        ));
+        assert!(!is_unused_precompile(
+            &point_evaluation,
+            &loaded,
+            SpecId::SHANGHAI
+        ));
+        let blake2f = Address::with_last_byte(0x09);
+        assert!(is_unused_precompile(&blake2f, &loaded, SpecId::ISTANBUL));
+        assert!(!is_unused_precompile(&blake2f, &loaded, SpecId::BYZANTIUM));
+        // Called precompiles are included.
+        let called = account(AccountInfo::default(), vec![]);
+        assert!(!is_unused_precompile(
+            &point_evaluation,
+            &called,
+            SpecId::CANCUN
+        ));
+    }
+}
@@ -0,0 +1,311 @@
+//! For producing transaction receipts from the results of block execution.
+//!
+//! Receipts are committed to by the block header (receipts root and logs bloom) and so
+//! provide a check on execution that is independent of the state root.
+
+use archors_types::utils::{rb160_to_eh160, rb256_to_eh256};
+use archors_verify::path::{
+    byte_to_nibbles, nibbles_to_prefixed_bytes, PathError, TargetNodeEncoding,
+};
+use ethers::{
+    types::{Bloom, Bytes, Log, Transaction, TransactionReceipt, H256, U256, U64},
+    utils::{
+        get_contract_address, keccak256,
+        rlp::{self, RlpStream},
+    },
+};
+use revm::primitives::{ExecutionResult, Log as RevmLog};
+
+/// Creates the receipt for a transaction from the result of executing it. The gas used by
+/// prior transactions in the block is added to get the cumulative gas used.
+///
+/// Receipts before Byzantium (EIP-658) have an intermediate state root instead of a status.
+/// Intermediate state roots are not computed, so these receipts have neither.
+pub fn build_receipt(
+    tx: &Transaction,
+    result: &ExecutionResult,
+    prior_gas_used: u64,
+    first_log_index: usize,
+    has_status: bool,
+) -> TransactionReceipt {
+    let (success, gas_used, logs) = match result {
+        ExecutionResult::Success { gas_used, logs, .. } => (true, *gas_used, logs.as_slice()),
+        ExecutionResult::Revert { gas_used, .. } | ExecutionResult::Halt { gas_used, .. } => {
+            (false, *gas_used, &[][..])
+        }
+    };
+    let logs: Vec<Log> = logs
+        .iter()
+        .enumerate()
+        .map(|(index, log)| convert_log(tx, log, first_log_index + index, index))
+        .collect();
+    let contract_address = match tx.to {
+        Some(_) => None,
+        None => Some(get_contract_address(tx.from, tx.nonce)),
+    };
+    TransactionReceipt {
+        transaction_hash: tx.hash,
+        transaction_index: tx.transaction_index.unwrap_or_default(),
+        block_hash: tx.block_hash,
+        block_number: tx.block_number,
+        from: tx.from,
+        to: tx.to,
+        cumulative_gas_used: U256::from(prior_gas_used + gas_used),
+        gas_used: Some(U256::from(gas_used)),
+        contract_address,
+        logs_bloom: logs_bloom(&logs),
+        logs,
+        status: has_status.then_some(U64::from(success as u64)),
+        transaction_type: tx.transaction_type,
+        effective_gas_price: tx.gas_price,
+        ..Default::default()
+    }
+}
+
+/// Converts a log emitted by the EVM to a log with block and transaction context.
+fn convert_log(tx: &Transaction, log: &RevmLog, log_index: usize, tx_log_index: usize) -> Log {
+    Log {
+        address: rb160_to_eh160(&log.address),
+        topics: log.topics.iter().map(|t| rb256_to_eh256(*t)).collect(),
+        data: Bytes::from(log.data.to_vec()),
+        block_hash: tx.block_hash,
+        block_number: tx.block_number,
+        transaction_hash: Some(tx.hash),
+        transaction_index: tx.transaction_index,
+        log_index: Some(U256::from(log_index)),
+        transaction_log_index: Some(U256::from(tx_log_index)),
+        log_type: None,
+        removed: Some(false),
+    }
+}
+
+/// Creates the 2048 bit bloom filter for a collection of logs.
+///
+/// The address and every topic of each log are added.
+pub fn logs_bloom(logs: &[Log]) -> Bloom {
+    let mut bloom = Bloom::zero();
+    for log in logs {
+        accrue(&mut bloom, log.address.as_bytes());
+        for topic in &log.topics {
+            accrue(&mut bloom, topic.as_bytes());
+        }
+    }
+    bloom
+}
+
+/// Sets three bits in the bloom, each selected by 11 bits of the hash of the input.
+fn accrue(bloom: &mut Bloom, input: &[u8]) {
+    let hash = keccak256(input);
+    for pair in hash[..6].chunks(2) {
+        let bit = (((pair[0] as usize) << 8) | pair[1] as usize) & 2047;
+        bloom.0[255 - bit / 8] |= 1 << (bit % 8);
+    }
+}
+
+/// Encodes a receipt as it is stored in the receipts trie.
+///
+/// Typed receipts (EIP-2718) are prefixed with the transaction type.
+pub fn encode_receipt(receipt: &TransactionReceipt) -> Vec<u8> {
+    let encoded = rlp::encode(receipt).to_vec();
+    match receipt.transaction_type.map(|t| t.as_u64()) {
+        None | Some(0) => encoded,
+        Some(tx_type) => [vec![tx_type as u8], encoded].concat(),
+    }
+}
+
+/// Computes the receipts root for the receipts of a block.
+pub fn receipts_root(receipts: &[TransactionReceipt]) -> Result<H256, PathError> {
+    ordered_trie_root(receipts.iter().map(encode_receipt).collect())
+}
+
+/// Computes the root of a trie where each item is keyed by the RLP encoding of its index.
+///
+/// This is the structure of the transactions and receipts tries.
+pub fn ordered_trie_root(items: Vec<Vec<u8>>) -> Result<H256, PathError> {
+    let mut leaves: Vec<(Vec<u8>, Vec<u8>)> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let key = rlp::encode(&(index as u64));
+            (key.iter().flat_map(byte_to_nibbles).collect(), item)
+        })
+        .collect();
+    leaves.sort();
+    Ok(H256::from(keccak256(encode_node(&leaves, 0)?)))
+}
+
+/// RLP encodes the node that holds the given leaves, where the keys (as nibbles) of
+/// the leaves share their first `depth` nibbles.
+fn encode_node(leaves: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Result<Vec<u8>, PathError> {
+    let node = match leaves {
+        [] => rlp::NULL_RLP.to_vec(),
+        [(key, value)] => {
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&nibbles_to_prefixed_bytes(
+                &key[depth..],
+                TargetNodeEncoding::Leaf,
+            )?);
+            stream.append(value);
+            stream.out().to_vec()
+        }
+        _ => {
+            let shared = shared_nibbles(leaves, depth);
+            if shared > 0 {
+                let mut stream = RlpStream::new_list(2);
+                stream.append(&nibbles_to_prefixed_bytes(
+                    &leaves[0].0[depth..depth + shared],
+                    TargetNodeEncoding::Extension,
+                )?);
+                append_child(&mut stream, encode_node(leaves, depth + shared)?);
+                return Ok(stream.out().to_vec());
+            }
+            let mut stream = RlpStream::new_list(17);
+            for nibble in 0..16u8 {
+                let children: Vec<(Vec<u8>, Vec<u8>)> = leaves
+                    .iter()
+                    .filter(|(key, _)| key.len() > depth && key[depth] == nibble)
+                    .cloned()
+                    .collect();
+                if children.is_empty() {
+                    stream.append_empty_data();
+                } else {
+                    append_child(&mut stream, encode_node(&children, depth + 1)?);
+                }
+            }
+            match leaves.iter().find(|(key, _)| key.len() == depth) {
+                Some((_, value)) => stream.append(value),
+                None => stream.append_empty_data(),
+            };
+            stream.out().to_vec()
+        }
+    };
+    Ok(node)
+}
+
+/// Adds a reference to a child node. Nodes shorter than 32 bytes are embedded, others
+/// are referred to by hash.
+fn append_child(stream: &mut RlpStream, node: Vec<u8>) {
+    if node.len() < 32 {
+        stream.append_raw(&node, 1);
+    } else {
+        stream.append(&keccak256(&node).to_vec());
+    }
+}
+
+/// Number of nibbles after `depth` that all leaf keys have in common.
+fn shared_nibbles(leaves: &[(Vec<u8>, Vec<u8>)], depth: usize) -> usize {
+    let first = &leaves[0].0;
+    let mut shared = first.len().saturating_sub(depth);
+    for (key, _) in &leaves[1..] {
+        shared = first[depth..]
+            .iter()
+            .zip(key.get(depth..).unwrap_or_default())
+            .take(shared)
+            .take_while(|(a, b)| a == b)
+            .count();
+    }
+    shared
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ethers::types::H160;
+
+    use super::*;
+
+    #[test]
+    fn test_empty_receipts_root() {
+        let expected =
+            H256::from_str("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+                .unwrap();
+        assert_eq!(receipts_root(&[]).unwrap(), expected);
+    }
+
+    /// Receipts of 20 transactions: legacy (untyped and type 0), access list and dynamic
+    /// fee, some failed and some with logs.
+    fn test_receipts() -> Vec<TransactionReceipt> {
+        (0..20u64)
+            .map(|index| {
+                let logs: Vec<Log> = (0..index % 3)
+                    .map(|log_index| Log {
+                        address: H160::from_low_u64_be(index + 1),
+                        topics: vec![H256::from_low_u64_be(log_index)],
+                        data: Bytes::from(vec![index as u8; log_index as usize * 40]),
+                        ..Default::default()
+                    })
+                    .collect();
+                TransactionReceipt {
+                    transaction_type: match index % 4 {
+                        0 => None,
+                        tx_type => Some(U64::from(tx_type - 1)),
+                    },
+                    status: Some(U64::from((index % 5 != 0) as u64)),
+                    cumulative_gas_used: U256::from(21_000 * (index + 1)),
+                    logs_bloom: logs_bloom(&logs),
+                    logs,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_receipts_root_with_branch_and_extension_nodes() {
+        // Independently computed with an insertion-based trie.
+        let expected =
+            H256::from_str("0xbdd964f81cbeeb1a9f95a0cb06e464b81795ae08a1c08189dbb5a6e2c20e9632")
+                .unwrap();
+        assert_eq!(receipts_root(&test_receipts()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_trie_root_with_value_in_branch() {
+        // "puppy" test from ethereum/tests trieanyorder.json. The key "do" ends at a branch.
+        let mut leaves: Vec<(Vec<u8>, Vec<u8>)> = [
+            ("do", "verb"),
+            ("horse", "stallion"),
+            ("doge", "coin"),
+            ("dog", "puppy"),
+        ]
+        .iter()
+        .map(|(key, value)| {
+            let nibbles = key.as_bytes().iter().flat_map(byte_to_nibbles).collect();
+            (nibbles, value.as_bytes().to_vec())
+        })
+        .collect();
+        leaves.sort();
+        let expected =
+            H256::from_str("0x5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84")
+                .unwrap();
+        let root = keccak256(encode_node(&leaves, 0).unwrap());
+        assert_eq!(H256::from(root), expected);
+    }
+
+    #[test]
+    fn test_logs_bloom_contains_address_and_topics() {
+        let log = Log {
+            address: H160::from_low_u64_be(1),
+            topics: vec![H256::from_low_u64_be(2)],
+            ..Default::default()
+        };
+        let bloom = logs_bloom(std::slice::from_ref(&log));
+        assert!(bloom.0.iter().map(|b| b.count_ones()).sum::<u32>() <= 6);
+        let mut address_only = Bloom::zero();
+        accrue(&mut address_only, log.address.as_bytes());
+        assert!(bloom.contains_bloom(&address_only));
+    }
+
+    #[test]
+    fn test_typed_receipt_prefix() {
+        let receipt = TransactionReceipt {
+            transaction_type: Some(U64::from(2)),
+            status: Some(U64::from(1)),
+            ..Default::default()
+        };
+        let encoded = encode_receipt(&receipt);
+        assert_eq!(encoded[0], 0x02);
+        assert_eq!(&encoded[1..], rlp::encode(&receipt).as_ref());
+    }
+}
@@ -0,0 +1,213 @@
+//! For directing the trace of each transaction to a destination.
+//!
+//! A writer is requested from the sink for every transaction that is traced, so that the
+//! traces of different transactions are kept separate.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, stdout, BufWriter, Write},
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+/// A destination for the trace output of each transaction, in any of the trace formats
+/// (see `TraceFormat`).
+///
+/// Implemented for closures that take a transaction index and return a writer.
+pub trait TraceSink {
+    /// Returns the writer for the trace of the transaction at the given index in the block.
+    fn writer(&mut self, tx_index: usize) -> io::Result<Box<dyn Write>>;
+}
+
+impl<F> TraceSink for F
+where
+    F: FnMut(usize) -> io::Result<Box<dyn Write>>,
+{
+    fn writer(&mut self, tx_index: usize) -> io::Result<Box<dyn Write>> {
+        self(tx_index)
+    }
+}
+
+/// Writes every trace to stdout.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdoutSink;
+
+impl TraceSink for StdoutSink {
+    fn writer(&mut self, _tx_index: usize) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(stdout()))
+    }
+}
+
+/// Writes the trace of each transaction to a separate file in a directory.
+///
+/// Files are named by transaction index (e.g., `tx_7.jsonl`).
+#[derive(Clone, Debug)]
+pub struct FileSink {
+    dir: PathBuf,
+}
+
+impl FileSink {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+    /// Path of the file that holds the trace of a transaction.
+    pub fn path(&self, tx_index: usize) -> PathBuf {
+        self.dir.join(format!("tx_{tx_index}.jsonl"))
+    }
+}
+
+impl TraceSink for FileSink {
+    fn writer(&mut self, tx_index: usize) -> io::Result<Box<dyn Write>> {
+        fs::create_dir_all(&self.dir)?;
+        let file = File::create(self.path(tx_index))?;
+        Ok(Box::new(BufWriter::new(file)))
+    }
+}
+
+/// Keeps the trace of each transaction in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySink {
+    traces: BTreeMap<usize, SharedBuffer>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The trace of a transaction, if it was traced.
+    pub fn trace(&self, tx_index: usize) -> Option<Vec<u8>> {
+        self.traces.get(&tx_index).map(SharedBuffer::contents)
+    }
+    /// The traces of all transactions traced, keyed by transaction index.
+    pub fn traces(&self) -> BTreeMap<usize, Vec<u8>> {
+        self.traces
+            .iter()
+            .map(|(index, buffer)| (*index, buffer.contents()))
+            .collect()
+    }
+}
+
+impl TraceSink for MemorySink {
+    fn writer(&mut self, tx_index: usize) -> io::Result<Box<dyn Write>> {
+        let buffer = self.traces.entry(tx_index).or_default();
+        Ok(Box::new(buffer.clone()))
+    }
+}
+
+/// A buffer that a tracer can write to while it is also held elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// A copy of the bytes written so far.
+    pub fn contents(&self) -> Vec<u8> {
+        match self.0.lock() {
+            Ok(bytes) => bytes.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = self
+            .0
+            .lock()
+            .map_err(|_| io::Error::other("Trace buffer lock poisoned"))?;
+        bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends the trace of each transaction through a channel once the transaction has
+/// been traced. Messages are (transaction index, trace).
+#[derive(Clone, Debug)]
+pub struct ChannelSink {
+    sender: Sender<(usize, Vec<u8>)>,
+}
+
+impl ChannelSink {
+    pub fn new(sender: Sender<(usize, Vec<u8>)>) -> Self {
+        Self { sender }
+    }
+}
+
+impl TraceSink for ChannelSink {
+    fn writer(&mut self, tx_index: usize) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(ChannelWriter {
+            tx_index,
+            buffer: vec![],
+            sender: self.sender.clone(),
+        }))
+    }
+}
+
+/// Collects the trace of one transaction and sends it when dropped.
+struct ChannelWriter {
+    tx_index: usize,
+    buffer: Vec<u8>,
+    sender: Sender<(usize, Vec<u8>)>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ChannelWriter {
+    fn drop(&mut self) {
+        // The receiver may have been dropped, in which case the trace is not wanted.
+        let _ = self
+            .sender
+            .send((self.tx_index, std::mem::take(&mut self.buffer)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_memory_sink_keeps_traces_separate() {
+        let mut sink = MemorySink::new();
+        sink.writer(1).unwrap().write_all(b"first").unwrap();
+        sink.writer(3).unwrap().write_all(b"second").unwrap();
+        assert_eq!(sink.trace(1).unwrap(), b"first");
+        assert_eq!(sink.trace(3).unwrap(), b"second");
+        assert_eq!(sink.trace(2), None);
+        assert_eq!(sink.traces().len(), 2);
+    }
+
+    #[test]
+    fn test_channel_sink_sends_on_completion() {
+        let (sender, receiver) = channel();
+        let mut sink = ChannelSink::new(sender);
+        {
+            let mut writer = sink.writer(4).unwrap();
+            writer.write_all(b"trace").unwrap();
+            assert!(receiver.try_recv().is_err());
+        }
+        assert_eq!(receiver.recv().unwrap(), (4, b"trace".to_vec()));
+    }
+
+    #[test]
+    fn test_closure_sink() {
+        let buffer = SharedBuffer::default();
+        let shared = buffer.clone();
+        let mut sink =
+            move |_tx_index: usize| -> io::Result<Box<dyn Write>> { Ok(Box::new(shared.clone())) };
+        sink.writer(0).unwrap().write_all(b"trace").unwrap();
+        assert_eq!(buffer.contents(), b"trace");
+    }
+}
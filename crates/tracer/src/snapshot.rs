@@ -0,0 +1,273 @@
+//! For re-executing transactions of a block that has already been executed.
+//!
+//! The block is executed once and the state changes of each transaction are kept. The
+//! state before any transaction can then be restored without executing the block again or
+//! reading the block state data, so a transaction can be traced repeatedly with different
+//! inspectors.
+//!
+//! The changes of each transaction are also available as a state diff, with the values
+//! before and after the transaction.
+
+use ethers::types::{Transaction, H256};
+use revm::{
+    db::{CacheDB, DatabaseCommit, EmptyDB},
+    primitives::{Account, Address, HashMap as rHashMap, ResultAndState},
+    Inspector,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    evm::{BlockEvm, EvmError, TraceFormat},
+    parity::{state_diff, StateDiff},
+    sink::TraceSink,
+    trace::{sink_writer, TraceError},
+};
+
+/// The state changed by a transaction in a block.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxStateDiff {
+    pub tx_hash: H256,
+    /// Balance, nonce, code and storage of each account changed, before and after the
+    /// transaction.
+    pub state_diff: StateDiff,
+}
+
+/// Number of transactions between the copies of the state kept by `BlockSnapshots`.
+const CHECKPOINT_INTERVAL: usize = 32;
+
+/// The state before each transaction in an executed block.
+///
+/// Holds the EVM environment of the block and the state changes of each transaction. The
+/// state before a transaction is the initial state (after any pre-block changes) with the
+/// changes of every prior transaction committed.
+///
+/// A full copy of the state is only kept every `CHECKPOINT_INTERVAL` transactions, as each
+/// copy holds all the state accessed so far. The state before a transaction is restored from
+/// the closest prior copy by committing the changes of the transactions since.
+#[derive(Clone)]
+pub struct BlockSnapshots {
+    /// EVM with the block environment, without a database.
+    base: BlockEvm,
+    /// Transactions of the block, in order.
+    transactions: Vec<Transaction>,
+    /// State changes made by each transaction, in order.
+    tx_changes: Vec<rHashMap<Address, Account>>,
+    /// State before the first transaction and every `CHECKPOINT_INTERVAL` transactions after.
+    checkpoints: Vec<CacheDB<EmptyDB>>,
+}
+
+impl BlockSnapshots {
+    /// Starts recording from an EVM that is ready for the first transaction of a block.
+    pub(crate) fn new(mut base: BlockEvm) -> Self {
+        let checkpoints = base.evm.db.take().into_iter().collect();
+        Self {
+            base,
+            transactions: vec![],
+            tx_changes: vec![],
+            checkpoints,
+        }
+    }
+    /// Records a transaction and the state changes it made.
+    pub(crate) fn record(&mut self, tx: Transaction, changes: rHashMap<Address, Account>) {
+        self.transactions.push(tx);
+        self.tx_changes.push(changes);
+        let count = self.transactions.len();
+        if count == self.checkpoints.len() * CHECKPOINT_INTERVAL {
+            if let Some(state) = self.state_before(count) {
+                self.checkpoints.push(state);
+            }
+        }
+    }
+    /// The state before the transaction at the given index, from the closest prior
+    /// checkpoint. An index equal to the number of transactions gives the state after the
+    /// last transaction.
+    fn state_before(&self, index: usize) -> Option<CacheDB<EmptyDB>> {
+        let last = self.checkpoints.len().checked_sub(1)?;
+        let checkpoint = (index / CHECKPOINT_INTERVAL).min(last);
+        let mut state = self.checkpoints[checkpoint].clone();
+        for changes in &self.tx_changes[checkpoint * CHECKPOINT_INTERVAL..index] {
+            state.commit(changes.clone());
+        }
+        Some(state)
+    }
+    /// Number of transactions recorded.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+    /// The transaction at the given index in the block.
+    pub fn transaction(&self, index: usize) -> Option<&Transaction> {
+        self.transactions.get(index)
+    }
+    /// An EVM with the state before the transaction at the given index, with that
+    /// transaction loaded and ready to be executed.
+    pub fn evm_at(&self, index: usize) -> Result<BlockEvm, TraceError> {
+        let tx = self
+            .transactions
+            .get(index)
+            .ok_or(TraceError::TxIndexOutOfRange {
+                index,
+                count: self.len(),
+            })?;
+        let state = self.state_before(index).ok_or(EvmError::NoDatabase)?;
+        let mut block_evm = self.base.clone();
+        block_evm.evm.database(state);
+        block_evm
+            .add_transaction_environment(tx.clone())
+            .map_err(|source| TraceError::TxEnvError { source, index })?;
+        Ok(block_evm)
+    }
+    /// The state changed by each transaction, in order.
+    ///
+    /// Values before a transaction are those of the block state data with the changes of
+    /// prior transactions applied. The diffs have the form of the OpenEthereum/Erigon
+    /// `stateDiff`.
+    pub fn state_diffs(&self) -> Result<Vec<TxStateDiff>, TraceError> {
+        let spec = self.base.evm.env.cfg.spec_id;
+        let mut db = self
+            .checkpoints
+            .first()
+            .ok_or(EvmError::NoDatabase)?
+            .clone();
+        let mut diffs = vec![];
+        for (tx, changes) in self.transactions.iter().zip(&self.tx_changes) {
+            let state_diff = state_diff(&db, changes, spec).map_err(EvmError::from)?;
+            db.commit(changes.clone());
+            diffs.push(TxStateDiff {
+                tx_hash: tx.hash,
+                state_diff,
+            });
+        }
+        Ok(diffs)
+    }
+    /// Executes the transaction at the given index with an inspector.
+    ///
+    /// The snapshots are unchanged, so the same transaction can be inspected again.
+    pub fn inspect_transaction<I>(
+        &self,
+        index: usize,
+        inspector: I,
+    ) -> Result<ResultAndState, TraceError>
+    where
+        I: Inspector<CacheDB<EmptyDB>>,
+    {
+        self.evm_at(index)?
+            .execute_with_inspector(inspector)
+            .map_err(|source| TraceError::TxExecutionError { source, index })
+    }
+    /// Traces the transaction at the given index in the given format, sending the trace
+    /// to the sink.
+    pub fn trace_transaction<S: TraceSink>(
+        &self,
+        index: usize,
+        format: TraceFormat,
+        sink: &mut S,
+    ) -> Result<ResultAndState, TraceError> {
+        let writer = sink_writer(sink, index)?;
+        self.evm_at(index)?
+            .execute_with_trace(format, writer)
+            .map_err(|source| TraceError::TxExecutionError { source, index })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::U256 as EU256;
+    use revm::{inspectors::NoOpInspector, primitives::U256};
+
+    use super::*;
+    use crate::{
+        parity::{Change, Delta},
+        sink::MemorySink,
+        test_utils::{recipient, sender, transfer, transfer_block},
+        trace::{BlockExecutor, PostExecutionProof},
+    };
+
+    /// Executes a block with two transfers from the same sender, recording snapshots.
+    fn snapshots() -> BlockSnapshots {
+        let (block, state) = transfer_block();
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        executor.execute_with_snapshots().unwrap().1
+    }
+
+    #[test]
+    fn test_snapshot_state_before_transaction() {
+        let snapshots = snapshots();
+        assert_eq!(snapshots.len(), 2);
+        let mut block_evm = snapshots.evm_at(1).unwrap();
+        let address = Address::from(sender().0);
+        let sender = block_evm.evm.db().unwrap().accounts[&address].info.clone();
+        assert_eq!(sender.nonce, 1);
+        assert_eq!(sender.balance, U256::from(8));
+    }
+
+    #[test]
+    fn test_snapshot_state_after_checkpoint() {
+        let (mut block, state) = transfer_block();
+        let count = CHECKPOINT_INTERVAL as u64 + 8;
+        block.transactions = (0..count)
+            .map(|nonce| transfer(nonce + 1, nonce, 0))
+            .collect();
+        block.gas_used = (21_000 * count).into();
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        let snapshots = executor.execute_with_snapshots().unwrap().1;
+        assert_eq!(snapshots.checkpoints.len(), 2);
+        let index = CHECKPOINT_INTERVAL + 3;
+        let mut block_evm = snapshots.evm_at(index).unwrap();
+        let address = Address::from(sender().0);
+        let sender = &block_evm.evm.db().unwrap().accounts[&address].info;
+        assert_eq!(sender.nonce, index as u64);
+    }
+
+    #[test]
+    fn test_snapshot_transaction_repeatable() {
+        let snapshots = snapshots();
+        for _ in 0..2 {
+            let outcome = snapshots.inspect_transaction(1, NoOpInspector {}).unwrap();
+            assert!(outcome.result.is_success());
+        }
+        let mut sink = MemorySink::new();
+        snapshots
+            .trace_transaction(0, TraceFormat::default(), &mut sink)
+            .unwrap();
+        assert!(sink.trace(0).is_some());
+        assert_eq!(
+            snapshots.evm_at(2).err(),
+            Some(TraceError::TxIndexOutOfRange { index: 2, count: 2 })
+        );
+    }
+
+    #[test]
+    fn test_snapshot_state_diffs() {
+        let diffs = snapshots().state_diffs().unwrap();
+        assert_eq!(diffs.len(), 2);
+        let first = &diffs[0].state_diff;
+        assert_eq!(
+            first[&sender()].balance,
+            Delta::Changed(Change {
+                from: EU256::from(9),
+                to: EU256::from(8)
+            })
+        );
+        assert_eq!(first[&recipient()].balance, Delta::Added(EU256::from(1)));
+        // Values before the second transaction include the changes of the first.
+        let second = &diffs[1].state_diff;
+        assert_eq!(
+            second[&sender()].nonce,
+            Delta::Changed(Change {
+                from: EU256::from(1),
+                to: EU256::from(2)
+            })
+        );
+        assert_eq!(
+            second[&recipient()].balance,
+            Delta::Changed(Change {
+                from: EU256::from(1),
+                to: EU256::from(3)
+            })
+        );
+    }
+}
@@ -4,12 +4,12 @@ use std::collections::HashMap;
 
 use archors_types::{
     execution::{EvmStateError, StateForEvm},
-    utils::{eh256_to_ru256, eu256_to_ru256, eu64_to_ru256, hex_encode}, proof::{DisplayProof, DisplayStorageProof},
+    utils::{eu256_to_ru256, eu64_to_ru256, hex_encode}, proof::{DisplayProof, DisplayStorageProof},
 };
 use ethers::types::{EIP1186ProofResponse, H160, H256, U64};
 use revm::{
     db::{CacheDB, EmptyDB},
-    primitives::{Account, AccountInfo, Bytecode, Bytes, HashMap as rHashMap, B160, B256, U256},
+    primitives::{Account, AccountInfo, Address, Bytecode, Bytes, HashMap as rHashMap, B256, U256},
 };
 
 /// A basic map of accounts to proofs. Includes all state required to trace a block.
@@ -33,10 +33,10 @@ pub struct BlockProofsBasic {
 }
 
 impl StateForEvm for BlockProofsBasic {
-    fn get_account_info(&self, address: &B160) -> Result<AccountInfo, EvmStateError> {
+    fn get_account_info(&self, address: &Address) -> Result<AccountInfo, EvmStateError> {
         let account = self
             .proofs
-            .get(&address.0.into())
+            .get(&H160(address.0 .0))
             .ok_or_else(|| EvmStateError::NoProofForAddress(hex_encode(address)))?;
 
         let code: Option<Bytecode> = self.code.get(&account.code_hash).map(|data| {
@@ -51,10 +51,13 @@ impl StateForEvm for BlockProofsBasic {
         };
         Ok(info)
     }
-    fn get_account_storage(&self, address: &B160) -> Result<rHashMap<U256, U256>, EvmStateError> {
+    fn get_account_storage(
+        &self,
+        address: &Address,
+    ) -> Result<rHashMap<U256, U256>, EvmStateError> {
         let account = self
             .proofs
-            .get(&address.0.into())
+            .get(&H160(address.0 .0))
             .ok_or_else(|| EvmStateError::NoProofForAddress(hex_encode(address)))?;
 
         // Storage key-val pairs for the account.
@@ -62,7 +65,7 @@ impl StateForEvm for BlockProofsBasic {
 
         for storage_data in &account.storage_proof {
             // U256 ethers -> U256 revm
-            let key = eh256_to_ru256(storage_data.key);
+            let key = eu256_to_ru256(storage_data.key)?;
             let value = eu256_to_ru256(storage_data.value)?;
 
             storage.insert(key, value);
@@ -70,10 +73,10 @@ impl StateForEvm for BlockProofsBasic {
 
         Ok(storage)
     }
-    fn addresses(&self) -> Vec<B160> {
+    fn addresses(&self) -> Vec<Address> {
         self.proofs
             .keys()
-            .map(|address| B160::from(address.0))
+            .map(|address| Address::from(address.0))
             .collect()
     }
 
@@ -89,7 +92,7 @@ impl StateForEvm for BlockProofsBasic {
 
     fn state_root_post_block(
         &mut self,
-        _changes: HashMap<B160, Account>,
+        _changes: HashMap<Address, Account>,
     ) -> Result<B256, EvmStateError> {
         unimplemented!("Post execution root check is not implemented for basic proof data format.")
     }
@@ -157,7 +160,7 @@ mod test {
         let expected_account = AccountInfo {
             balance: U256::from_str(balance).unwrap(),
             nonce,
-            code_hash: B256::zero(),
+            code_hash: B256::ZERO,
             code: None,
         };
         assert_eq!(retreived_account, expected_account);
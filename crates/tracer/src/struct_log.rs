@@ -0,0 +1,380 @@
+//! For producing the default (struct logger) trace of geth `debug_traceTransaction`.
+//!
+//! The trace for a transaction has the form `{gas, failed, returnValue, structLogs}`, where
+//! each struct log describes one EVM step.
+
+use std::collections::{BTreeMap, HashMap};
+
+use revm::{
+    interpreter::{opcode, CallInputs, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{Address, Bytes, ExecutionResult, Output, U256},
+    Database, EVMData, Inspector,
+};
+use serde::{Deserialize, Serialize};
+
+/// Options for the struct logger, as accepted by geth `debug_traceTransaction`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StructLoggerConfig {
+    pub disable_stack: bool,
+    pub disable_memory: bool,
+    pub disable_storage: bool,
+    pub enable_return_data: bool,
+}
+
+/// The trace of a transaction in the geth struct logger format.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLogTrace {
+    /// Gas used by the transaction.
+    pub gas: u64,
+    pub failed: bool,
+    /// Return (or revert) data, hex encoded without prefix.
+    pub return_value: String,
+    pub struct_logs: Vec<StructLog>,
+}
+
+/// A single EVM step.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    pub pc: u64,
+    pub op: String,
+    /// Gas remaining before the step.
+    pub gas: u64,
+    pub gas_cost: u64,
+    /// Call depth, starting at 1.
+    pub depth: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Stack items (bottom first), as 0x-prefixed quantities.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<String>>,
+    /// Return data of the most recent call in the current context (0x-prefixed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub return_data: Option<String>,
+    /// Memory as 32 byte words, hex encoded without prefix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Vec<String>>,
+    /// Storage of the current contract that has been accessed so far. Only present for
+    /// SLOAD and SSTORE.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage: Option<BTreeMap<String, String>>,
+    /// Gas refund counter.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub refund: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// An inspector that records each EVM step in the geth struct logger format.
+#[derive(Clone, Debug, Default)]
+pub struct StructLogger {
+    config: StructLoggerConfig,
+    logs: Vec<StructLog>,
+    /// Steps that have started but not ended, one per call depth.
+    pending: Vec<PendingStep>,
+    /// Storage accessed so far, by contract.
+    storage: HashMap<Address, BTreeMap<String, String>>,
+    /// Refunds accrued in each active call frame.
+    refunds: Vec<i64>,
+}
+
+/// A step that has been logged, awaiting its gas cost.
+#[derive(Clone, Debug)]
+struct PendingStep {
+    /// Position in the logs.
+    index: usize,
+    /// Gas remaining before the step.
+    gas: u64,
+    /// Gas returned from a call or create made by the step.
+    returned_gas: u64,
+    /// Key read by an SLOAD step.
+    sload_key: Option<U256>,
+    address: Address,
+}
+
+impl StructLogger {
+    pub fn new(config: StructLoggerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+    /// The steps recorded so far.
+    pub fn logs(&self) -> &[StructLog] {
+        &self.logs
+    }
+    /// Combines the recorded steps with the result of the transaction.
+    pub fn into_trace(self, result: &ExecutionResult) -> StructLogTrace {
+        let (failed, output) = match result {
+            ExecutionResult::Success { output, .. } => match output {
+                Output::Call(bytes) | Output::Create(bytes, _) => (false, bytes.to_vec()),
+            },
+            ExecutionResult::Revert { output, .. } => (true, output.to_vec()),
+            ExecutionResult::Halt { .. } => (true, vec![]),
+        };
+        let gas = match result {
+            ExecutionResult::Success { gas_used, .. }
+            | ExecutionResult::Revert { gas_used, .. }
+            | ExecutionResult::Halt { gas_used, .. } => *gas_used,
+        };
+        StructLogTrace {
+            gas,
+            failed,
+            return_value: hex::encode(output),
+            struct_logs: self.logs,
+        }
+    }
+    /// Total refund counter, given the refund of the current frame.
+    fn refund(&mut self, depth: usize, current: i64) -> u64 {
+        self.refunds.resize(depth, 0);
+        if let Some(last) = self.refunds.last_mut() {
+            *last = current;
+        }
+        self.refunds.iter().sum::<i64>().max(0) as u64
+    }
+    /// Records a storage access and returns the storage accessed so far for the contract.
+    fn access_storage(
+        &mut self,
+        address: Address,
+        key: U256,
+        value: U256,
+    ) -> BTreeMap<String, String> {
+        let storage = self.storage.entry(address).or_default();
+        storage.insert(storage_word(key), storage_word(value));
+        storage.clone()
+    }
+    /// Adds gas returned by a completed call or create to the step that made it.
+    fn return_gas(&mut self, result: InstructionResult, remaining: &Gas) {
+        if let Some(step) = self.pending.last_mut() {
+            step.returned_gas += returned_gas(result, remaining);
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for StructLogger {
+    fn step(&mut self, interp: &mut Interpreter, data: &mut EVMData<'_, DB>) -> InstructionResult {
+        let depth = data.journaled_state.depth();
+        let op = interp.current_opcode();
+        let gas = interp.gas.remaining();
+        let address = interp.contract.address;
+        let stack = interp.stack.data();
+
+        let mut log = StructLog {
+            pc: interp.program_counter() as u64,
+            op: op_name(op),
+            gas,
+            depth,
+            refund: self.refund(depth as usize, interp.gas.refunded()),
+            ..Default::default()
+        };
+        if !self.config.disable_stack {
+            log.stack = Some(stack.iter().map(|item| format!("0x{item:x}")).collect());
+        }
+        if self.config.enable_return_data && !interp.return_data_buffer.is_empty() {
+            log.return_data = Some(format!("0x{}", hex::encode(&interp.return_data_buffer)));
+        }
+        if !self.config.disable_memory {
+            log.memory = Some(interp.memory.data().chunks(32).map(hex::encode).collect());
+        }
+        let mut sload_key = None;
+        if !self.config.disable_storage {
+            match (op, stack.as_slice()) {
+                (opcode::SLOAD, [.., key]) => sload_key = Some(*key),
+                (opcode::SSTORE, [.., value, key]) => {
+                    log.storage = Some(self.access_storage(address, *key, *value))
+                }
+                _ => {}
+            }
+        }
+        self.pending.truncate(depth as usize - 1);
+        self.pending.push(PendingStep {
+            index: self.logs.len(),
+            gas,
+            returned_gas: 0,
+            sload_key,
+            address,
+        });
+        self.logs.push(log);
+        InstructionResult::Continue
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        _data: &mut EVMData<'_, DB>,
+        eval: InstructionResult,
+    ) -> InstructionResult {
+        let Some(step) = self.pending.pop() else {
+            return InstructionResult::Continue;
+        };
+        let storage = match (step.sload_key, interp.stack.data().last()) {
+            (Some(key), Some(value)) => Some(self.access_storage(step.address, key, *value)),
+            _ => None,
+        };
+        let log = &mut self.logs[step.index];
+        log.gas_cost = (step.gas + step.returned_gas).saturating_sub(interp.gas.remaining());
+        log.error = step_error(eval, &log.op);
+        if storage.is_some() {
+            log.storage = storage;
+        }
+        InstructionResult::Continue
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.return_gas(ret, &remaining_gas);
+        (ret, remaining_gas, out)
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: Bytes,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        self.return_gas(ret, &remaining_gas);
+        (ret, address, remaining_gas, out)
+    }
+}
+
+/// Name of an opcode, as used by geth.
+pub(crate) fn op_name(op: u8) -> String {
+    match opcode::OPCODE_JUMPMAP[op as usize] {
+        Some(name) => name.to_string(),
+        None => format!("opcode {op:#x} not defined"),
+    }
+}
+
+/// Gas that a completed call or create returns to the caller.
+///
+/// ### Gas cost of calls
+/// The gas cost reported for CALL, CREATE (and variants) includes the gas given to the
+/// callee, as geth does. Gas that the callee returns is not deducted.
+pub(crate) fn returned_gas(result: InstructionResult, remaining: &Gas) -> u64 {
+    match returns_gas(result) {
+        true => remaining.remaining(),
+        false => 0,
+    }
+}
+
+/// Whether the unused gas of a frame is returned to the caller. Gas is not returned
+/// if the frame halted exceptionally.
+pub(crate) fn returns_gas(result: InstructionResult) -> bool {
+    matches!(
+        result,
+        InstructionResult::Continue
+            | InstructionResult::Stop
+            | InstructionResult::Return
+            | InstructionResult::SelfDestruct
+            | InstructionResult::Revert
+            | InstructionResult::CallTooDeep
+            | InstructionResult::OutOfFund
+    )
+}
+
+/// 32 byte storage key or value, hex encoded without prefix.
+fn storage_word(word: U256) -> String {
+    let bytes: [u8; 32] = word.to_be_bytes();
+    hex::encode(bytes)
+}
+
+/// The error (as worded by geth) for a step that failed.
+pub(crate) fn step_error(result: InstructionResult, op_name: &str) -> Option<String> {
+    use InstructionResult::*;
+    let error = match result {
+        OutOfGas | MemoryOOG | MemoryLimitOOG | PrecompileOOG | InvalidOperandOOG => {
+            "out of gas".to_string()
+        }
+        InvalidJump => "invalid jump destination".to_string(),
+        OpcodeNotFound | InvalidFEOpcode => format!("invalid opcode: {op_name}"),
+        StackUnderflow => "stack underflow".to_string(),
+        StackOverflow => "stack limit reached 1024".to_string(),
+        CallNotAllowedInsideStatic | StateChangeDuringStaticCall => "write protection".to_string(),
+        OutOfOffset => "return data out of bounds".to_string(),
+        _ => return None,
+    };
+    Some(error)
+}
+
+#[cfg(test)]
+mod test {
+    use revm::primitives::{Eval, Halt};
+
+    use super::*;
+
+    #[test]
+    fn test_struct_log_serialization_omits_disabled_fields() {
+        let log = StructLog {
+            pc: 2,
+            op: "PUSH1".to_string(),
+            gas: 100,
+            gas_cost: 3,
+            depth: 1,
+            stack: Some(vec!["0x60".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&log).unwrap(),
+            r#"{"pc":2,"op":"PUSH1","gas":100,"gasCost":3,"depth":1,"stack":["0x60"]}"#
+        );
+    }
+
+    #[test]
+    fn test_config_from_geth_options() {
+        let config: StructLoggerConfig =
+            serde_json::from_str(r#"{"disableStorage":true,"enableReturnData":true}"#).unwrap();
+        assert_eq!(
+            config,
+            StructLoggerConfig {
+                disable_storage: true,
+                enable_return_data: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_trace_from_result() {
+        let logger = StructLogger::new(StructLoggerConfig::default());
+        let success = ExecutionResult::Success {
+            reason: Eval::Return,
+            gas_used: 21_000,
+            gas_refunded: 0,
+            logs: vec![],
+            output: Output::Call(Bytes::from_static(&[0xab])),
+        };
+        let trace = logger.clone().into_trace(&success);
+        assert_eq!(
+            serde_json::to_string(&trace).unwrap(),
+            r#"{"gas":21000,"failed":false,"returnValue":"ab","structLogs":[]}"#
+        );
+        let halt = ExecutionResult::Halt {
+            reason: Halt::OpcodeNotFound,
+            gas_used: 50_000,
+        };
+        let trace = logger.into_trace(&halt);
+        assert!(trace.failed);
+        assert!(trace.return_value.is_empty());
+    }
+
+    #[test]
+    fn test_storage_word_is_padded() {
+        assert_eq!(
+            storage_word(U256::from(1)),
+            "0000000000000000000000000000000000000000000000000000000000000001"
+        );
+    }
+}
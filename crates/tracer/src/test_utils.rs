@@ -0,0 +1,117 @@
+//! Blocks, block state data and EVMs shared by the tests of the crate.
+
+use std::{collections::HashMap, str::FromStr};
+
+use ethers::types::{Block, EIP1186ProofResponse, Transaction, H160, H256};
+use revm::{
+    db::{CacheDB, EmptyDB},
+    primitives::{AccountInfo, Address, Bytecode, TransactTo},
+    EVM,
+};
+
+use crate::state::BlockProofsBasic;
+
+/// Account that sends the transactions of the test blocks, with a balance of 9.
+pub(crate) fn sender() -> H160 {
+    H160::from_str("0x0300000000000000000000000000000000000000").unwrap()
+}
+
+/// Account that receives the transactions of the test blocks.
+pub(crate) fn recipient() -> H160 {
+    H160::from_str("0x0200000000000000000000000000000000000000").unwrap()
+}
+
+/// A legacy transaction from the sender to the recipient, without a gas price.
+pub(crate) fn transfer(hash: u64, nonce: u64, value: u64) -> Transaction {
+    Transaction {
+        hash: H256::from_low_u64_be(hash),
+        from: sender(),
+        to: Some(recipient()),
+        gas: 21_000.into(),
+        gas_price: Some(0.into()),
+        value: value.into(),
+        nonce: nonce.into(),
+        transaction_index: Some(nonce.into()),
+        v: 27.into(),
+        ..Default::default()
+    }
+}
+
+/// A block with the given number and transactions, authored by the zero address.
+pub(crate) fn block(number: u64, transactions: Vec<Transaction>) -> Block<Transaction> {
+    Block {
+        author: Some(H160::zero()),
+        number: Some(number.into()),
+        gas_limit: 30_000_000.into(),
+        transactions,
+        ..Default::default()
+    }
+}
+
+/// State data with the sender and the block author (which receives the fees).
+pub(crate) fn sender_state() -> BlockProofsBasic {
+    let mut state = BlockProofsBasic {
+        proofs: HashMap::default(),
+        code: HashMap::default(),
+        block_hashes: HashMap::default(),
+    };
+    state.proofs.insert(
+        sender(),
+        EIP1186ProofResponse {
+            address: sender(),
+            balance: 9.into(),
+            ..Default::default()
+        },
+    );
+    state
+        .proofs
+        .insert(H160::zero(), EIP1186ProofResponse::default());
+    state
+}
+
+/// A pre-Byzantium block (receipts root not checked) with two transfers, and the state
+/// for the block.
+pub(crate) fn transfer_block() -> (Block<Transaction>, BlockProofsBasic) {
+    let mut state = sender_state();
+    state
+        .proofs
+        .insert(recipient(), EIP1186ProofResponse::default());
+    let block = Block {
+        gas_used: 42_000.into(),
+        ..block(2_000_000, vec![transfer(1, 0, 1), transfer(2, 1, 2)])
+    };
+    (block, state)
+}
+
+/// Account that holds the code called by the test EVM.
+pub(crate) fn contract() -> Address {
+    Address::with_last_byte(0xbb)
+}
+
+/// Account that calls the contract in the test EVM.
+pub(crate) fn caller() -> Address {
+    Address::with_last_byte(0xcc)
+}
+
+/// A database with the given code at an address.
+pub(crate) fn db_with_code(address: Address, code: &[u8]) -> CacheDB<EmptyDB> {
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.insert_account_info(
+        address,
+        AccountInfo {
+            code: Some(Bytecode::new_raw(code.to_vec().into())),
+            ..Default::default()
+        },
+    );
+    db
+}
+
+/// An EVM with a transaction from the caller to the contract, which has the given code.
+pub(crate) fn evm_calling(code: &[u8]) -> EVM<CacheDB<EmptyDB>> {
+    let mut evm = EVM::new();
+    evm.database(db_with_code(contract(), code));
+    evm.env.tx.caller = caller();
+    evm.env.tx.transact_to = TransactTo::Call(contract());
+    evm.env.tx.gas_limit = 100_000;
+    evm
+}
@@ -1,19 +1,38 @@
 //! For executing a block using state.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, io::Write};
 
 use archors_types::{
+    chain::ChainConfig,
     execution::{EvmStateError, StateForEvm},
-    utils::hex_encode,
+    utils::{eu256_to_u64, hex_encode},
+};
+use ethers::types::{
+    transaction::eip2930::AccessList, Block, Bloom, Transaction, TransactionReceipt, H256,
 };
-use ethers::types::{Block, Transaction, H256};
 use log::{info, warn};
-use revm::primitives::{Account, HashMap as rHashMap, B160, B256, U256};
+use revm::{
+    db::{CacheDB, DatabaseCommit, EmptyDB},
+    primitives::{
+        Account, AccountInfo, AccountStatus, Address, HashMap as rHashMap, ResultAndState, SpecId,
+        B256, U256,
+    },
+    Inspector,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 
 use crate::{
-    evm::{BlockEvm, EvmError},
-    state::build_state_from_proofs,
+    access_list::AccessListInspector,
+    evm::{BlockEvm, EvmError, TraceFormat},
+    lazy_state::LazyStateDb,
+    parity::{ParityTraceResult, ParityTraceTypes},
+    prestate::is_removed,
+    receipts::{build_receipt, receipts_root},
+    sink::{MemorySink, TraceSink},
+    snapshot::{BlockSnapshots, TxStateDiff},
+    what_if::{tx_outcomes, TxOutcome, WhatIfReport},
 };
 
 /// An error with tracing a block
@@ -23,6 +42,8 @@ pub enum TraceError {
     StateError(#[from] EvmStateError),
     #[error("UtilsError {0}")]
     UtilsError(#[from] archors_types::utils::UtilsError),
+    #[error("PathError {0}")]
+    PathError(#[from] archors_verify::path::PathError),
     #[error("EvmError {0}")]
     EvmError(#[from] EvmError),
     #[error("Computed state root {computed_root} does not match header state root {header_root}")]
@@ -36,6 +57,42 @@ pub enum TraceError {
     TxExecutionError { source: EvmError, index: usize },
     #[error("Transaction does not have an index")]
     TxWithoutIndex,
+    #[error("Block has {expected} uncle(s), but {provided} uncle header(s) were provided")]
+    UncleCount { expected: usize, provided: usize },
+    #[error("Uncle header {provided} does not match uncle hash {expected} in block")]
+    UncleMismatch { expected: String, provided: String },
+    #[error("Expected a block author (coinbase) to apply block rewards, found none")]
+    NoBlockAuthor,
+    #[error("Expected uncle header to have a number and author (coinbase), found none")]
+    IncompleteUncle,
+    #[error("Expected a parent beacon block root for a Cancun block, found none")]
+    NoParentBeaconBlockRoot,
+    #[error("Computed blob gas used {computed} does not match header blob gas used {header}")]
+    BlobGasUsed { computed: u64, header: u64 },
+    #[error("Unable to get trace writer for transaction (tx_index {index}) {error}")]
+    TraceSinkError { index: usize, error: String },
+    #[error("Computed gas used {computed} does not match header gas used {header}")]
+    GasUsed { computed: u64, header: u64 },
+    #[error("Computed logs bloom {computed} does not match header logs bloom {header}")]
+    LogsBloom { computed: String, header: String },
+    #[error("Computed receipts root {computed} does not match header receipts root {header}")]
+    ReceiptsRoot { computed: String, header: String },
+    #[error("Trace of transaction (tx_index {index}) is not valid JSON {error}")]
+    TraceJson { index: usize, error: String },
+    #[error("Transaction index {index} is not in the block of {count} transaction(s)")]
+    TxIndexOutOfRange { index: usize, count: usize },
+    #[error("Unable to execute call (before tx_index {index}) {source}")]
+    CallError { source: EvmError, index: usize },
+    #[error("Gas estimation failed, the call fails with gas {gas} {error}")]
+    GasEstimation { gas: u64, error: String },
+}
+
+/// The trace of a transaction in a block, as returned by geth `debug_traceBlock*`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTxTrace {
+    pub tx_hash: H256,
+    pub result: Value,
 }
 
 /// Whether after tracing a block the post-execution state root should be computed
@@ -48,96 +105,398 @@ pub enum PostExecutionProof {
     Ignore,
 }
 
+/// The outcome of executing a block.
+pub struct ExecutedBlock<T: StateForEvm> {
+    /// State for the block. Updated to post-block state if the post-execution proof
+    /// was requested.
+    pub state: T,
+    /// Receipts for the transactions in the block, in order.
+    pub receipts: Vec<TransactionReceipt>,
+}
+
+/// The database of a BlockExecutor, which reads state from the block state data on demand.
+///
+/// Inspectors passed to a BlockExecutor inspect an EVM with this database.
+pub type BlockDb<T> = CacheDB<LazyStateDb<T>>;
+
 /// Holds an EVM configured for single block execution.
 pub struct BlockExecutor<T: StateForEvm> {
-    block_evm: BlockEvm,
-    block: Block<Transaction>,
-    /// After transactions are applied, the delta is applied to get post-execution proofs and
+    /// The EVM reads state from the block state data as it is needed. After transactions
+    /// are applied, the delta is applied to the data to get post-execution proofs and
     /// state root.
-    block_proof_cache: T,
+    block_evm: BlockEvm<BlockDb<T>>,
+    block: Block<Transaction>,
     /// Flag to check post-execution state root or not.
     root_check: PostExecutionProof,
+    /// Headers of the uncles (ommers) included in the block. Used for pre-merge
+    /// block rewards.
+    uncles: Vec<Block<H256>>,
+    /// Format of the traces sent to a sink.
+    trace_format: TraceFormat,
+    /// Whether transactions that read state missing from the block state data fail.
+    strict_state: bool,
+    /// Whether the receipts, logs bloom and gas used are checked against the block header.
+    header_check: bool,
 }
 
 impl<T: StateForEvm> BlockExecutor<T> {
-    /// Loads the tracer so that it is ready to trace a block.
+    /// Loads the tracer so that it is ready to trace a mainnet block.
     pub fn load(
         block: Block<Transaction>,
         block_proofs: T,
         root_check: PostExecutionProof,
     ) -> Result<Self, TraceError> {
-        // For all important states, load into db.
-        let mut cache_db = build_state_from_proofs(&block_proofs)?;
-        cache_db.block_hashes = block_proofs.get_blockhash_accesses()?;
+        Self::load_with_config(block, block_proofs, root_check, ChainConfig::mainnet())
+    }
+    /// Loads the tracer so that it is ready to trace a block from the specified chain.
+    pub fn load_with_config(
+        block: Block<Transaction>,
+        block_proofs: T,
+        root_check: PostExecutionProof,
+        chain: ChainConfig,
+    ) -> Result<Self, TraceError> {
+        // State is read from the block state data when the EVM first needs it.
+        let cache_db = CacheDB::new(LazyStateDb::new(block_proofs));
         let mut block_evm = BlockEvm::init_from_db(cache_db);
-        warn!("Did not set spec_id for hard fork");
         block_evm
-            .add_chain_id(U256::from(1))
-            .add_spec_id(&block)? // TODO
+            .add_chain_config(chain)
+            .add_spec_id(&block)?
             .add_block_environment(&block)?;
         Ok(BlockExecutor {
             block_evm,
             block,
-            block_proof_cache: block_proofs,
             root_check,
+            uncles: vec![],
+            trace_format: TraceFormat::default(),
+            strict_state: false,
+            header_check: true,
         })
     }
+    /// Adds the headers of the uncles (ommers) of the block, in the order they appear in
+    /// the block. Required to apply the rewards for pre-merge blocks that include uncles.
+    pub fn add_uncles(&mut self, uncles: Vec<Block<H256>>) -> Result<&mut Self, TraceError> {
+        if uncles.len() != self.block.uncles.len() {
+            return Err(TraceError::UncleCount {
+                expected: self.block.uncles.len(),
+                provided: uncles.len(),
+            });
+        }
+        for (uncle, expected) in uncles.iter().zip(self.block.uncles.iter()) {
+            if uncle.hash != Some(*expected) {
+                return Err(TraceError::UncleMismatch {
+                    expected: hex_encode(expected),
+                    provided: uncle.hash.map(hex_encode).unwrap_or_default(),
+                });
+            }
+        }
+        self.uncles = uncles;
+        Ok(self)
+    }
+    /// Sets whether executing a transaction that reads state that is missing from the
+    /// block state data is an error (TraceError::TxExecutionError with a missing state
+    /// EvmStateError), rather than the state being read as empty.
+    ///
+    /// Only state read by transactions is checked. State changed outside of transactions
+    /// (beacon root storage, block rewards and withdrawals) is not part of any trace and
+    /// may be absent from the data.
+    pub fn set_strict_state(&mut self, strict: bool) -> &mut Self {
+        self.strict_state = strict;
+        self
+    }
+    /// Sets whether the receipts root, logs bloom and gas used of the executed transactions
+    /// are checked against the block header (checked by default).
+    ///
+    /// Inspectors that change execution (E.g., by halting a call, as a call depth limit
+    /// does) produce different receipts than the block, so the check should be disabled.
+    pub fn set_header_check(&mut self, check: bool) -> &mut Self {
+        self.header_check = check;
+        self
+    }
+    /// Sets the format of the traces produced (EIP-3155 by default).
+    pub fn set_trace_format(&mut self, format: TraceFormat) -> &mut Self {
+        self.trace_format = format;
+        self
+    }
     /// Traces a single transaction in the block.
     ///
     /// The entire block is executed but only the specified transaction is inspected
-    /// (trace sent to stdout)
-    pub fn trace_transaction(mut self, target_tx_index: usize) -> Result<T, TraceError> {
-        let mut post_block_state_delta = PostBlockStateDelta::default();
+    /// (trace sent to the sink, e.g., StdoutSink).
+    pub fn trace_transaction<S: TraceSink>(
+        self,
+        target_tx_index: usize,
+        sink: &mut S,
+    ) -> Result<T, TraceError> {
+        let executed = self.execute_internal(|index| match index == target_tx_index {
+            true => sink_writer(sink, index).map(Some),
+            false => Ok(None),
+        })?;
+        Ok(executed.state)
+    }
+    /// Traces every transaction in the block. Each trace is sent to the sink separately.
+    pub fn trace_block<S: TraceSink>(self, sink: &mut S) -> Result<T, TraceError> {
+        let executed = self.execute_internal(|index| sink_writer(sink, index).map(Some))?;
+        Ok(executed.state)
+    }
+    /// Traces every transaction in the block and returns the traces in the form of
+    /// geth `debug_traceBlock*` (`[{txHash, result}]`).
+    ///
+    /// For formats with one JSON line per step (EIP-3155), the result is an array of lines.
+    pub fn trace_block_json(self) -> Result<(T, Vec<BlockTxTrace>), TraceError> {
+        let tx_hashes: Vec<H256> = self.block.transactions.iter().map(|tx| tx.hash).collect();
+        let mut sink = MemorySink::new();
+        let state = self.trace_block(&mut sink)?;
+        let traces = tx_hashes
+            .into_iter()
+            .enumerate()
+            .map(|(index, tx_hash)| {
+                let trace = sink.trace(index).unwrap_or_default();
+                Ok(BlockTxTrace {
+                    tx_hash,
+                    result: trace_json(index, &trace)?,
+                })
+            })
+            .collect::<Result<Vec<BlockTxTrace>, TraceError>>()?;
+        Ok((state, traces))
+    }
+    /// Traces a block in the form of OpenEthereum/Erigon `trace_replayBlockTransactions`,
+    /// with one result per transaction. The trace format is set to produce the trace types
+    /// requested.
+    pub fn replay_block_transactions(
+        mut self,
+        types: ParityTraceTypes,
+    ) -> Result<(T, Vec<ParityTraceResult>), TraceError> {
+        let tx_count = self.block.transactions.len();
+        self.trace_format = TraceFormat::Parity(types);
+        let mut sink = MemorySink::new();
+        let state = self.trace_block(&mut sink)?;
+        let results = (0..tx_count)
+            .map(|index| {
+                let trace = sink.trace(index).unwrap_or_default();
+                serde_json::from_slice(&trace).map_err(|e| TraceError::TraceJson {
+                    index,
+                    error: e.to_string(),
+                })
+            })
+            .collect::<Result<Vec<ParityTraceResult>, TraceError>>()?;
+        Ok((state, results))
+    }
+    /// Trace a block without producing a trace. Used for debugging.
+    pub fn trace_block_silent(self) -> Result<T, TraceError> {
+        let executed = self.execute_internal(|_| Ok(None))?;
+        Ok(executed.state)
+    }
+    /// Executes a block without producing a trace and returns the transaction receipts
+    /// along with the state.
+    pub fn execute_block(self) -> Result<ExecutedBlock<T>, TraceError> {
+        self.execute_internal(|_| Ok(None))
+    }
+    /// Executes a block without producing a trace, keeping the state changes of each
+    /// transaction.
+    ///
+    /// The snapshots can then be used to trace any transaction (repeatedly, with any
+    /// inspector) without executing the block again. They hold the changes of every
+    /// transaction and a copy of the state accessed every 32 transactions.
+    pub fn execute_with_snapshots(self) -> Result<(ExecutedBlock<T>, BlockSnapshots), TraceError> {
+        let (executed, snapshots) = self.execute_recorded(
+            |index, block_evm| {
+                block_evm
+                    .execute_without_inspector()
+                    .map_err(|source| TraceError::TxExecutionError { source, index })
+            },
+            true,
+        )?;
+        let snapshots = snapshots.expect("Snapshots are recorded when requested");
+        Ok((executed, snapshots))
+    }
+    /// Executes a block without producing a trace and returns the state changed by each
+    /// transaction, with the values before and after the transaction.
+    pub fn execute_with_state_diffs(
+        self,
+    ) -> Result<(ExecutedBlock<T>, Vec<TxStateDiff>), TraceError> {
+        let (executed, snapshots) = self.execute_with_snapshots()?;
+        let diffs = snapshots.state_diffs()?;
+        Ok((executed, diffs))
+    }
+    /// Executes the block and a modified list of transactions (E.g., with transactions
+    /// replaced, dropped or inserted) from the same pre-block state, and reports where the
+    /// outcomes of the modified list diverge from those of the block.
+    ///
+    /// Missing state is an error in both executions. The block is checked against the
+    /// header as usual, the modified list is not. Invalid transactions in the modified list
+    /// (E.g., with a nonce that is too high because an earlier transaction was dropped) are
+    /// skipped and reported as diverged. Transactions are matched by hash, so an inserted
+    /// transaction must have a hash that is not in the block.
+    pub fn execute_what_if(
+        mut self,
+        transactions: Vec<Transaction>,
+    ) -> Result<(ExecutedBlock<T>, WhatIfReport), TraceError> {
+        let chain = self.block_evm.chain().clone();
+        let db = self.block_evm.evm.db.take().ok_or(EvmError::NoDatabase)?;
+        // The modified list is executed first, as it only reads the state data.
+        let mut modified_block = self.block.clone();
+        modified_block.transactions = transactions;
+        let mut modified = BlockExecutor::load_with_config(
+            modified_block,
+            db.db.into_state(),
+            PostExecutionProof::Ignore,
+            chain.clone(),
+        )?;
+        modified.set_strict_state(true);
+        let (modified_outcomes, state) = modified.execute_unchecked()?;
 
-        for (check_idx, tx) in self.block.transactions.into_iter().enumerate() {
-            let index = tx
-                .transaction_index
-                .ok_or(TraceError::TxWithoutIndex)?
-                .as_u64() as usize;
-            assert_eq!(check_idx, index);
-            let primed = self
-                .block_evm
+        let mut canonical =
+            BlockExecutor::load_with_config(self.block, state, self.root_check, chain)?;
+        canonical.uncles = self.uncles;
+        canonical.set_strict_state(true);
+        let (executed, snapshots) = canonical.execute_with_snapshots()?;
+        let canonical_receipts = executed.receipts.iter().cloned().map(Ok).collect();
+        let canonical_outcomes = tx_outcomes(canonical_receipts, snapshots.state_diffs()?);
+        let report = WhatIfReport::new(canonical_outcomes, modified_outcomes);
+        Ok((executed, report))
+    }
+    /// Executes the transactions before the given index and returns the EVM, which then
+    /// holds the state before the transaction at that index. An index equal to the number
+    /// of transactions gives the state after every transaction (before block rewards and
+    /// withdrawals).
+    ///
+    /// If strict state is set it remains set for any further use of the EVM.
+    pub(crate) fn into_evm_before(
+        mut self,
+        index: usize,
+    ) -> Result<BlockEvm<BlockDb<T>>, TraceError> {
+        let count = self.block.transactions.len();
+        if index > count {
+            return Err(TraceError::TxIndexOutOfRange { index, count });
+        }
+        let spec = self.block_evm.evm.env.cfg.spec_id;
+        self.prepare_block(&mut PostBlockStateDelta::new(spec))?;
+        self.set_db_strict(self.strict_state)?;
+        let transactions = std::mem::take(&mut self.block.transactions);
+        for (index, tx) in transactions.into_iter().take(index).enumerate() {
+            self.block_evm
                 .add_transaction_environment(tx)
-                .map_err(|source| TraceError::TxEnvError { source, index })?;
-
-            let post_tx = match index {
-                i if i == target_tx_index => {
-                    // Execute with tracing.
-                    primed
-                        .execute_with_inspector_eip3155()
-                        .map_err(|source| TraceError::TxExecutionError { source, index })?
-                }
-                _ => {
-                    // Execute without tracing.
-                    primed
-                        .execute_without_inspector()
-                        .map_err(|source| TraceError::TxExecutionError { source, index })?
-                }
-            };
-            post_block_state_delta.append_tx_changes(post_tx.state)?;
+                .map_err(|source| TraceError::TxEnvError { source, index })?
+                .execute_without_inspector()
+                .map_err(|source| TraceError::TxExecutionError { source, index })?;
         }
-
-        post_execution_check(
-            self.root_check,
-            self.block.state_root,
-            &mut self.block_proof_cache,
-            post_block_state_delta,
+        Ok(self.block_evm)
+    }
+    /// Executes a block with a user-supplied inspector, which inspects every transaction
+    /// in the block.
+    ///
+    /// The inspector is borrowed, so that whatever it records can be read afterwards. If the
+    /// inspector changes execution, disable the header check (see set_header_check()).
+    pub fn execute_with_inspector<I>(
+        self,
+        inspector: &mut I,
+    ) -> Result<ExecutedBlock<T>, TraceError>
+    where
+        I: Inspector<BlockDb<T>>,
+    {
+        let (executed, _) = self.execute_recorded(
+            |index, block_evm| {
+                block_evm
+                    .execute_with_inspector(&mut *inspector)
+                    .map_err(|source| TraceError::TxExecutionError { source, index })
+            },
+            false,
         )?;
-        Ok(self.block_proof_cache)
+        Ok(executed)
     }
-    /// Traces every transaction in the block.
-    pub fn trace_block(self) -> Result<T, TraceError> {
-        self.trace_block_internal(false)
+    /// Executes a block with an inspector for each transaction, made by the factory from
+    /// the transaction index. Transactions that the factory makes no inspector for are
+    /// executed without one.
+    ///
+    /// Inspectors can hold shared state (E.g., `Rc<RefCell<_>>`) to report what they
+    /// record. If an inspector changes execution, disable the header check (see
+    /// set_header_check()).
+    pub fn execute_with_inspector_factory<'a, F>(
+        self,
+        mut factory: F,
+    ) -> Result<ExecutedBlock<T>, TraceError>
+    where
+        F: FnMut(usize) -> Option<Box<dyn Inspector<BlockDb<T>> + 'a>>,
+    {
+        let (executed, _) = self.execute_recorded(
+            |index, block_evm| {
+                match factory(index) {
+                    Some(inspector) => block_evm.execute_with_inspector(inspector),
+                    None => block_evm.execute_without_inspector(),
+                }
+                .map_err(|source| TraceError::TxExecutionError { source, index })
+            },
+            false,
+        )?;
+        Ok(executed)
     }
-    /// Trace a block without producing a trace to stdout. Used for debugging.
-    pub fn trace_block_silent(self) -> Result<T, TraceError> {
-        self.trace_block_internal(true)
+    /// Executes a block and returns the access list (EIP-2930) of each transaction: every
+    /// address and storage key the transaction touched during execution.
+    ///
+    /// The sender and recipient are included, so the lists can be used to select the state
+    /// each transaction needs.
+    pub fn execute_with_access_lists(
+        self,
+    ) -> Result<(ExecutedBlock<T>, Vec<AccessList>), TraceError> {
+        let mut access_lists = vec![];
+        let (executed, _) = self.execute_recorded(
+            |index, block_evm| {
+                let mut inspector = AccessListInspector::new();
+                let outcome = block_evm
+                    .execute_with_inspector(&mut inspector)
+                    .map_err(|source| TraceError::TxExecutionError { source, index })?;
+                access_lists.push(inspector.into_access_list());
+                Ok(outcome)
+            },
+            false,
+        )?;
+        Ok((executed, access_lists))
     }
-    /// Executes a block. The execution trace can be toggled off.
-    fn trace_block_internal(mut self, silent: bool) -> Result<T, TraceError> {
+    /// Executes a block, producing a trace for each transaction that a writer is
+    /// provided for.
+    fn execute_internal<F>(self, mut writer_for: F) -> Result<ExecutedBlock<T>, TraceError>
+    where
+        F: FnMut(usize) -> Result<Option<Box<dyn Write>>, TraceError>,
+    {
+        let trace_format = self.trace_format.clone();
+        let (executed, _) = self.execute_recorded(
+            |index, block_evm| {
+                match writer_for(index)? {
+                    Some(writer) => block_evm.execute_with_trace(trace_format.clone(), writer),
+                    None => block_evm.execute_without_inspector(),
+                }
+                .map_err(|source| TraceError::TxExecutionError { source, index })
+            },
+            false,
+        )?;
+        Ok(executed)
+    }
+    /// Executes a block, with each transaction executed by the given function once its
+    /// environment is loaded. If requested, the state before each transaction is recorded.
+    ///
+    /// The receipts, logs bloom and gas used are checked against the block header, unless
+    /// the check is disabled.
+    fn execute_recorded<F>(
+        mut self,
+        mut execute_tx: F,
+        record: bool,
+    ) -> Result<(ExecutedBlock<T>, Option<BlockSnapshots>), TraceError>
+    where
+        F: FnMut(usize, &mut BlockEvm<BlockDb<T>>) -> Result<ResultAndState, TraceError>,
+    {
         info!("Executing block using pre-state and transactions");
-        let mut post_block_state_delta = PostBlockStateDelta::default();
-        for (check_idx, tx) in self.block.transactions.into_iter().enumerate() {
+        let spec = self.block_evm.evm.env.cfg.spec_id;
+        let mut post_block_state_delta = PostBlockStateDelta::new(spec);
+        let pre_block_changes = self.prepare_block(&mut post_block_state_delta)?;
+        // Environment for snapshots, the state is added once the block has been executed.
+        let snapshot_base =
+            record.then(|| self.block_evm.with_db(CacheDB::new(EmptyDB::default())));
+        let mut tx_changes = vec![];
+        self.set_db_strict(self.strict_state)?;
+        let transactions = std::mem::take(&mut self.block.transactions);
+        let mut receipts = vec![];
+        let mut cumulative_gas_used = 0;
+        let mut log_count = 0;
+        for (check_idx, tx) in transactions.into_iter().enumerate() {
             let index = tx
                 .transaction_index
                 .ok_or(TraceError::TxWithoutIndex)?
@@ -145,31 +504,274 @@ impl<T: StateForEvm> BlockExecutor<T> {
             assert_eq!(check_idx, index);
             let primed = self
                 .block_evm
-                .add_transaction_environment(tx)
+                .add_transaction_environment(tx.clone())
                 .map_err(|source| TraceError::TxEnvError { source, index })?;
-
-            let post_tx = match silent {
-                false => primed
-                    .execute_with_inspector_eip3155()
-                    .map_err(|source| TraceError::TxExecutionError { source, index })?,
-                true => primed
-                    .execute_without_inspector()
-                    .map_err(|source| TraceError::TxExecutionError { source, index })?,
-            };
-
-            let _result = post_tx.result;
+            let post_tx = execute_tx(index, primed)?;
+            let receipt = build_receipt(
+                &tx,
+                &post_tx.result,
+                cumulative_gas_used,
+                log_count,
+                SpecId::enabled(spec, SpecId::BYZANTIUM),
+            );
+            cumulative_gas_used = receipt.cumulative_gas_used.as_u64();
+            log_count += receipt.logs.len();
+            receipts.push(receipt);
+            if record {
+                tx_changes.push((tx, post_tx.state.clone()));
+            }
             // Update a proof object with state that changed after a transaction was executed.
             post_block_state_delta.append_tx_changes(post_tx.state)?;
         }
+        self.set_db_strict(false)?;
+        self.finalise_block(&mut post_block_state_delta)?;
+        if self.header_check {
+            receipts_ok(&self.block, &receipts, spec)?;
+        }
 
+        let db = self.block_evm.evm.db.take().ok_or(EvmError::NoDatabase)?;
+        let snapshots = match snapshot_base {
+            Some(base) => Some(block_snapshots(
+                base,
+                &db.db,
+                pre_block_changes,
+                tx_changes,
+            )?),
+            None => None,
+        };
+        let mut block_proof_cache = db.db.into_state();
         post_execution_check(
             self.root_check,
             self.block.state_root,
-            &mut self.block_proof_cache,
+            &mut block_proof_cache,
             post_block_state_delta,
         )?;
-        Ok(self.block_proof_cache)
+        let executed = ExecutedBlock {
+            state: block_proof_cache,
+            receipts,
+        };
+        Ok((executed, snapshots))
+    }
+    /// Executes the transactions without checking the results against the block header,
+    /// for transactions that are not those of the block. Block rewards and withdrawals
+    /// are not applied.
+    ///
+    /// Transactions that revm rejects as invalid are skipped, with no state changes. The
+    /// outcome of each transaction is returned along with the state data.
+    fn execute_unchecked(mut self) -> Result<(Vec<TxOutcome>, T), TraceError> {
+        let spec = self.block_evm.evm.env.cfg.spec_id;
+        let pre_block_changes = self.prepare_block(&mut PostBlockStateDelta::new(spec))?;
+        let snapshot_base = self.block_evm.with_db(CacheDB::new(EmptyDB::default()));
+        self.set_db_strict(self.strict_state)?;
+        let transactions = std::mem::take(&mut self.block.transactions);
+        let mut tx_changes = vec![];
+        let mut receipts = vec![];
+        let mut cumulative_gas_used = 0;
+        let mut log_count = 0;
+        for (index, tx) in transactions.into_iter().enumerate() {
+            let outcome = self
+                .block_evm
+                .add_transaction_environment(tx.clone())
+                .map_err(|source| TraceError::TxEnvError { source, index })?
+                .execute_without_inspector();
+            let post_tx = match outcome {
+                Ok(post_tx) => post_tx,
+                Err(EvmError::RevmError(reason)) => {
+                    self.block_evm.unload_transaction();
+                    receipts.push(Err(reason));
+                    tx_changes.push((tx, rHashMap::new()));
+                    continue;
+                }
+                Err(source) => return Err(TraceError::TxExecutionError { source, index }),
+            };
+            let receipt = build_receipt(
+                &tx,
+                &post_tx.result,
+                cumulative_gas_used,
+                log_count,
+                SpecId::enabled(spec, SpecId::BYZANTIUM),
+            );
+            cumulative_gas_used = receipt.cumulative_gas_used.as_u64();
+            log_count += receipt.logs.len();
+            receipts.push(Ok(receipt));
+            tx_changes.push((tx, post_tx.state));
+        }
+        let db = self.block_evm.evm.db.take().ok_or(EvmError::NoDatabase)?;
+        let snapshots = block_snapshots(snapshot_base, &db.db, pre_block_changes, tx_changes)?;
+        let outcomes = tx_outcomes(receipts, snapshots.state_diffs()?);
+        Ok((outcomes, db.db.into_state()))
+    }
+    /// Sets whether reading state that is missing from the block state data is an error.
+    fn set_db_strict(&mut self, strict: bool) -> Result<(), TraceError> {
+        let db = self.block_evm.evm.db().ok_or(EvmError::NoDatabase)?;
+        db.db.set_strict(strict);
+        Ok(())
+    }
+    /// Applies state changes that occur before any transactions in a block are executed.
+    ///
+    /// - Post-Cancun: the parent beacon block root is stored (EIP-4788).
+    ///
+    /// The changes are returned.
+    fn prepare_block(
+        &mut self,
+        delta: &mut PostBlockStateDelta,
+    ) -> Result<rHashMap<Address, Account>, TraceError> {
+        let spec = self.block_evm.evm.env.cfg.spec_id;
+        if !SpecId::enabled(spec, SpecId::CANCUN) {
+            return Ok(rHashMap::new());
+        }
+        let root = self
+            .block
+            .parent_beacon_block_root
+            .ok_or(TraceError::NoParentBeaconBlockRoot)?;
+        let timestamp = eu256_to_u64(self.block.timestamp);
+        let changes = self
+            .block_evm
+            .store_beacon_root(timestamp, B256::from(root.0))?;
+        delta.append_tx_changes(changes.clone())?;
+        Ok(changes)
+    }
+    /// Applies state changes that occur after all transactions in a block are executed.
+    ///
+    /// - Pre-merge: block reward to the miner and uncle rewards to uncle miners.
+    /// - Post-Shanghai: withdrawals from the beacon chain.
+    ///
+    /// For post-Cancun blocks the blob gas used by the transactions is checked against
+    /// the header.
+    fn finalise_block(&mut self, delta: &mut PostBlockStateDelta) -> Result<(), TraceError> {
+        let spec = self.block_evm.evm.env.cfg.spec_id;
+        if SpecId::enabled(spec, SpecId::CANCUN) {
+            let header = self
+                .block
+                .blob_gas_used
+                .map(eu256_to_u64)
+                .unwrap_or_default();
+            let computed = self.block_evm.blob_gas_used();
+            if computed != header {
+                return Err(TraceError::BlobGasUsed { computed, header });
+            }
+        }
+        let mut increments = block_rewards(&self.block, &self.uncles, spec)?;
+        let number = self.block.number.ok_or(EvmError::NoBlockNumber)?.as_u64();
+        let timestamp = eu256_to_u64(self.block.timestamp);
+        if self.block_evm.chain().withdrawals_active(number, timestamp) {
+            increments.extend(withdrawal_amounts(&self.block));
+        }
+        if !increments.is_empty() {
+            let changes = self.block_evm.increment_balances(&increments)?;
+            delta.append_tx_changes(changes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the trace of a transaction. Traces with multiple JSON values (e.g., one per
+/// line) are combined into an array.
+fn trace_json(index: usize, trace: &[u8]) -> Result<Value, TraceError> {
+    let mut values = serde_json::Deserializer::from_slice(trace)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<Value>, _>>()
+        .map_err(|e| TraceError::TraceJson {
+            index,
+            error: e.to_string(),
+        })?;
+    match values.len() {
+        1 => Ok(values.remove(0)),
+        _ => Ok(Value::Array(values)),
+    }
+}
+
+/// Creates the snapshots of an executed block from an EVM environment for the block, the
+/// state read during execution and the changes made before and during each transaction.
+fn block_snapshots<T: StateForEvm>(
+    mut base: BlockEvm,
+    db: &LazyStateDb<T>,
+    pre_block_changes: rHashMap<Address, Account>,
+    tx_changes: Vec<(Transaction, rHashMap<Address, Account>)>,
+) -> Result<BlockSnapshots, TraceError> {
+    // Transactions only read state that was read during execution.
+    let mut pre_tx_state = db.read_state_db()?;
+    pre_tx_state.commit(pre_block_changes);
+    base.evm.database(pre_tx_state);
+    let mut snapshots = BlockSnapshots::new(base);
+    for (tx, changes) in tx_changes {
+        snapshots.record(tx, changes);
+    }
+    Ok(snapshots)
+}
+
+/// Gets the writer for the trace of a transaction from a sink.
+pub(crate) fn sink_writer<S: TraceSink>(
+    sink: &mut S,
+    index: usize,
+) -> Result<Box<dyn Write>, TraceError> {
+    sink.writer(index).map_err(|e| TraceError::TraceSinkError {
+        index,
+        error: e.to_string(),
+    })
+}
+
+/// Wei per gwei. Withdrawal amounts are denominated in gwei.
+const WEI_PER_GWEI: u64 = 1_000_000_000;
+
+/// Gets the recipients and amounts (in wei) of withdrawals in a block (EIP-4895).
+fn withdrawal_amounts(block: &Block<Transaction>) -> Vec<(Address, U256)> {
+    block
+        .withdrawals
+        .iter()
+        .flatten()
+        .map(|withdrawal| {
+            let amount = U256::from(withdrawal.amount.as_u64()) * U256::from(WEI_PER_GWEI);
+            (Address::from(withdrawal.address.0), amount)
+        })
+        .collect()
+}
+
+/// Wei per ether.
+const WEI_PER_ETH: u64 = 1_000_000_000_000_000_000;
+
+/// The static block reward for a pre-merge block (EIP-649, EIP-1234). Post-merge blocks
+/// have no reward.
+fn base_block_reward(spec: SpecId) -> Option<U256> {
+    let eth = match spec {
+        s if SpecId::enabled(s, SpecId::MERGE) => return None,
+        s if SpecId::enabled(s, SpecId::CONSTANTINOPLE) => 2,
+        s if SpecId::enabled(s, SpecId::BYZANTIUM) => 3,
+        _ => 5,
+    };
+    Some(U256::from(eth) * U256::from(WEI_PER_ETH))
+}
+
+/// Computes the rewards for the miner of a pre-merge block and the miners of any uncles.
+///
+/// - Miner: base + base/32 per uncle included.
+/// - Uncle miner: (uncle_number + 8 - block_number) * base / 8
+fn block_rewards(
+    block: &Block<Transaction>,
+    uncles: &[Block<H256>],
+    spec: SpecId,
+) -> Result<Vec<(Address, U256)>, TraceError> {
+    let Some(base) = base_block_reward(spec) else {
+        return Ok(vec![]);
+    };
+    if uncles.len() != block.uncles.len() {
+        return Err(TraceError::UncleCount {
+            expected: block.uncles.len(),
+            provided: uncles.len(),
+        });
     }
+    let number = block.number.ok_or(EvmError::NoBlockNumber)?.as_u64();
+    let miner = Address::from(block.author.ok_or(TraceError::NoBlockAuthor)?.0);
+    let mut rewards = vec![];
+    let nephew_reward = base / U256::from(32) * U256::from(uncles.len());
+    rewards.push((miner, base + nephew_reward));
+    for uncle in uncles {
+        let uncle_number = uncle.number.ok_or(TraceError::IncompleteUncle)?.as_u64();
+        let uncle_miner = Address::from(uncle.author.ok_or(TraceError::IncompleteUncle)?.0);
+        let reward = U256::from((uncle_number + 8).saturating_sub(number)) * base / U256::from(8);
+        rewards.push((uncle_miner, reward));
+    }
+    Ok(rewards)
 }
 
 /// If required, updates the state multiproof with the changes acquired from block execution, then
@@ -200,9 +802,54 @@ fn post_execution_check<T: StateForEvm>(
     Ok(())
 }
 
+/// Checks the receipts produced by execution against the block header.
+///
+/// The receipts root is not checked for blocks before Byzantium, as their receipts contain
+/// intermediate state roots.
+fn receipts_ok(
+    block: &Block<Transaction>,
+    receipts: &[TransactionReceipt],
+    spec: SpecId,
+) -> Result<(), TraceError> {
+    let computed_gas = receipts
+        .last()
+        .map(|receipt| receipt.cumulative_gas_used)
+        .unwrap_or_default();
+    if computed_gas != block.gas_used {
+        return Err(TraceError::GasUsed {
+            computed: computed_gas.as_u64(),
+            header: block.gas_used.as_u64(),
+        });
+    }
+    let mut computed_bloom = Bloom::zero();
+    for receipt in receipts {
+        computed_bloom.accrue_bloom(&receipt.logs_bloom);
+    }
+    let header_bloom = block.logs_bloom.unwrap_or_default();
+    if computed_bloom != header_bloom {
+        return Err(TraceError::LogsBloom {
+            computed: hex_encode(computed_bloom),
+            header: hex_encode(header_bloom),
+        });
+    }
+    if !SpecId::enabled(spec, SpecId::BYZANTIUM) {
+        warn!("Skipped receipts root verification for pre-Byzantium block");
+        return Ok(());
+    }
+    let computed_root = receipts_root(receipts)?;
+    if computed_root != block.receipts_root {
+        return Err(TraceError::ReceiptsRoot {
+            computed: hex_encode(computed_root),
+            header: hex_encode(block.receipts_root),
+        });
+    }
+    info!("Receipts root, logs bloom and gas used verified.");
+    Ok(())
+}
+
 /// Checks that the post-block state root matches the state root in the block header.
 fn post_root_ok(&header_root: &H256, computed_root: &B256) -> Result<(), TraceError> {
-    let header_root = B256::from(header_root);
+    let header_root = B256::from(header_root.0);
     if computed_root != &header_root {
         return Err(TraceError::PostBlockStateRoot {
             computed_root: hex_encode(computed_root),
@@ -219,15 +866,41 @@ fn post_root_ok(&header_root: &H256, computed_root: &B256) -> Result<(), TraceEr
 /// account, the storage slot changes should be included from both transactions. Later
 /// changes overwrite earlier changes.
 ///
-/// Other members in Account (.is_destroyed, etc) are not updated and are not used elsewhere.
-#[derive(Default, Debug, Clone)]
-pub struct PostBlockStateDelta(HashMap<B160, Account>);
+/// Accounts created during the block (e.g., contract deployments) are included, even though
+/// they are absent from the pre-block state.
+///
+/// Accounts removed from state are marked as self-destructed in the net changes. An account is
+/// removed if:
+/// - It executes SELFDESTRUCT. From Cancun (EIP-6780), the EVM only marks the account as
+///   self-destructed if it was created in the same transaction.
+/// - It is touched and empty (EIP-161, from Spurious Dragon).
+///
+/// If an account is present again after removal, it is marked as created so that
+/// its storage starts from an empty trie.
+#[derive(Debug, Clone)]
+pub struct PostBlockStateDelta {
+    accounts: HashMap<Address, Account>,
+    spec: SpecId,
+}
+
+impl Default for PostBlockStateDelta {
+    fn default() -> Self {
+        Self::new(SpecId::LATEST)
+    }
+}
 
 impl PostBlockStateDelta {
+    /// Creates an accumulator that applies the account removal rules of the given fork.
+    fn new(spec: SpecId) -> Self {
+        Self {
+            accounts: HashMap::default(),
+            spec,
+        }
+    }
     /// Add state changes for multiple accounts to the state delta accumulator.
     fn append_tx_changes(
         &mut self,
-        changed_accounts: rHashMap<B160, Account>,
+        changed_accounts: rHashMap<Address, Account>,
     ) -> Result<(), TraceError> {
         for (address, account) in changed_accounts {
             self.append_account_changes(address, account)?;
@@ -237,42 +910,79 @@ impl PostBlockStateDelta {
     /// Add state changes for one account to the state delta accumulator.
     fn append_account_changes(
         &mut self,
-        address: B160,
-        changes: Account,
+        address: Address,
+        mut changes: Account,
     ) -> Result<(), TraceError> {
-        let summary = match self.0.get_mut(&address) {
+        if is_removed(&changes, self.spec) {
+            self.accounts.insert(address, removed_account());
+            return Ok(());
+        }
+        changes.unmark_selfdestruct();
+        let summary = match self.accounts.get_mut(&address) {
             Some(acc) => acc,
             None => {
-                self.0.insert(address, changes);
+                self.accounts.insert(address, changes);
                 return Ok(());
             }
         };
+        if summary.is_selfdestructed() {
+            if changes.is_touched() {
+                // Present again after removal. Prior storage no longer exists.
+                changes.mark_created();
+                *summary = changes;
+            }
+            return Ok(());
+        }
+        // Update account components
+        if changes.is_created() {
+            summary.mark_created();
+        }
+        if changes.is_touched() {
+            summary.mark_touch();
+        }
+        summary.info = changes.info;
         // Overwrite any new slot changes individually.
         for (key, val) in changes.storage {
             summary.storage.insert(key, val);
         }
-        // Update account components
-        summary.info = changes.info;
         Ok(())
     }
     /// Returns the inner map of account changes.
-    fn get_changes(self) -> HashMap<B160, Account> {
-        self.0
+    fn get_changes(self) -> HashMap<Address, Account> {
+        self.accounts
+    }
+}
+
+/// Net changes for an account that is absent from post-block state.
+fn removed_account() -> Account {
+    Account {
+        info: AccountInfo::default(),
+        storage: rHashMap::default(),
+        status: AccountStatus::Created
+            | AccountStatus::SelfDestructed
+            | AccountStatus::Touched
+            | AccountStatus::LoadedAsNotExisting,
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::str::FromStr;
+    use std::{cell::Cell, rc::Rc, str::FromStr};
 
-    use ethers::types::{EIP1186ProofResponse, H160};
+    use ethers::types::{EIP1186ProofResponse, Withdrawal, H160};
     use revm::{
         db::{CacheDB, DatabaseRef, EmptyDB},
-        primitives::{AccountInfo, HashMap as rHashMap, StorageSlot, U256},
+        interpreter::{CallInputs, Gas, InstructionResult},
+        primitives::{AccountInfo, Bytes, HashMap as rHashMap, StorageSlot, U256},
+        Database, EVMData,
     };
 
-    use crate::state::BlockProofsBasic;
+    use crate::{
+        parity::{Change, Delta},
+        state::BlockProofsBasic,
+        test_utils::{block, recipient, sender_state, transfer, transfer_block},
+    };
 
     /// Tests that a EVM environnment can be constructed from proof data for a block
     /// Values are set for an account, transactions are created and then
@@ -312,13 +1022,153 @@ mod test {
         block.transactions.push(tx);
         let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
         // The dummy block should not successfully execute.
-        assert!(executor.trace_block().is_err());
+        assert!(executor.trace_block(&mut MemorySink::new()).is_err());
+    }
+
+    #[test]
+    fn test_strict_state_rejects_missing_account() {
+        // Recipient is not in the state data.
+        let state = sender_state();
+        let block = block(10_000_000, vec![transfer(1, 0, 0)]);
+        let mut executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        executor.set_strict_state(true);
+        let error = executor.execute_block().err().unwrap();
+        assert!(matches!(
+            error,
+            TraceError::TxExecutionError {
+                source: EvmError::StateError(EvmStateError::MissingAccount(_)),
+                index: 0
+            }
+        ));
+    }
+
+    #[test]
+    fn test_what_if_replaced_transaction() {
+        let (block, state) = transfer_block();
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        let modified = vec![transfer(3, 0, 3), transfer(2, 1, 2)];
+        let (executed, report) = executor.execute_what_if(modified).unwrap();
+        assert_eq!(executed.receipts.len(), 2);
+        assert_eq!(report.modified.len(), 2);
+        // Tx 1 replaced by tx 3. Tx 2 has the same result but starts from another balance.
+        let diverged: Vec<u64> = report
+            .divergences
+            .iter()
+            .map(|d| d.tx_hash.to_low_u64_be())
+            .collect();
+        assert_eq!(diverged, vec![1, 2, 3]);
+        assert_eq!(
+            report.divergences[1].modified.as_ref().unwrap().state_diff[&recipient()].balance,
+            Delta::Changed(Change {
+                from: 3.into(),
+                to: 5.into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_what_if_dropped_transaction_invalidates_later_nonce() {
+        let (block, state) = transfer_block();
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        // Tx 1 (nonce 0) dropped, so tx 2 (nonce 1) from the same sender is invalid.
+        let (executed, report) = executor.execute_what_if(vec![transfer(2, 1, 2)]).unwrap();
+        assert_eq!(executed.receipts.len(), 2);
+        let skipped = &report.modified[0];
+        assert!(skipped.receipt.is_none());
+        assert!(skipped.invalid.is_some());
+        assert!(skipped.state_diff.is_empty());
+        let diverged: Vec<(u64, bool)> = report
+            .divergences
+            .iter()
+            .map(|d| (d.tx_hash.to_low_u64_be(), d.modified.is_some()))
+            .collect();
+        assert_eq!(diverged, vec![(1, false), (2, true)]);
+    }
+
+    /// Counts calls, including the call of each transaction.
+    struct CallCounter(Rc<Cell<usize>>);
+
+    impl<DB: Database> Inspector<DB> for CallCounter {
+        fn call(
+            &mut self,
+            _data: &mut EVMData<'_, DB>,
+            _inputs: &mut CallInputs,
+        ) -> (InstructionResult, Gas, Bytes) {
+            self.0.set(self.0.get() + 1);
+            (InstructionResult::Continue, Gas::new(0), Bytes::new())
+        }
+    }
+
+    #[test]
+    fn test_user_supplied_inspector() {
+        let (block, state) = transfer_block();
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        let mut counter = CallCounter(Rc::new(Cell::new(0)));
+        executor.execute_with_inspector(&mut counter).unwrap();
+        assert_eq!(counter.0.get(), 2);
+    }
+
+    /// Halts every call, including the call of each transaction, as a call depth limit
+    /// of zero would.
+    struct CallHalter;
+
+    impl<DB: Database> Inspector<DB> for CallHalter {
+        fn call(
+            &mut self,
+            _data: &mut EVMData<'_, DB>,
+            _inputs: &mut CallInputs,
+        ) -> (InstructionResult, Gas, Bytes) {
+            (InstructionResult::CallTooDeep, Gas::new(0), Bytes::new())
+        }
+    }
+
+    /// A block with a transfer that has more gas than it uses, which the header records.
+    fn halted_transfer_executor() -> BlockExecutor<BlockProofsBasic> {
+        let (mut block, state) = transfer_block();
+        block.transactions = vec![Transaction {
+            gas: 30_000.into(),
+            ..transfer(1, 0, 1)
+        }];
+        block.gas_used = 21_000.into();
+        BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap()
+    }
+
+    #[test]
+    fn test_inspector_that_halts_a_call() {
+        // A halted transaction uses all its gas, rather than the 21_000 in the header.
+        let executor = halted_transfer_executor();
+        assert_eq!(
+            executor.execute_with_inspector(&mut CallHalter).err(),
+            Some(TraceError::GasUsed {
+                computed: 30_000,
+                header: 21_000
+            })
+        );
+        let mut executor = halted_transfer_executor();
+        executor.set_header_check(false);
+        let executed = executor.execute_with_inspector(&mut CallHalter).unwrap();
+        assert_eq!(executed.receipts[0].gas_used, Some(30_000.into()));
+    }
+
+    #[test]
+    fn test_inspector_factory() {
+        let (block, state) = transfer_block();
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let executed = executor
+            .execute_with_inspector_factory(|index| match index {
+                1 => Some(Box::new(CallCounter(calls.clone()))),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(executed.receipts.len(), 2);
+        assert_eq!(calls.get(), 1);
     }
 
     /// Test case from revm crate.
     #[test]
     pub fn test_replace_account_storage() {
-        let account = 42.into();
+        let account = Address::with_last_byte(42);
         let nonce = 42;
         let mut init_state = CacheDB::new(EmptyDB::default());
         init_state.insert_account_info(
@@ -353,32 +1203,23 @@ mod test {
                 code: None,
             },
             storage: rHashMap::default(),
-            storage_cleared: false,
-            is_destroyed: false,
-            is_touched: false,
-            is_not_existing: false,
+            status: AccountStatus::Loaded,
         }
     }
 
     #[test]
     fn test_slot_changes_from_two_transactions_are_combined() {
         let mut changes = PostBlockStateDelta::default();
-        let address = B160::from_str("0x00000000000000adc04c56bf30ac9d3c0aaf14dc").unwrap();
+        let address = Address::from_str("0x00000000000000adc04c56bf30ac9d3c0aaf14dc").unwrap();
         // First tx (0, 9) (1, 1)
         let mut account_update = account_factory();
         account_update.storage.insert(
             U256::from_str("0x0").unwrap(),
-            StorageSlot {
-                present_value: U256::from_str("0x9").unwrap(),
-                ..Default::default()
-            },
+            StorageSlot::new_changed(U256::ZERO, U256::from_str("0x9").unwrap()),
         );
         account_update.storage.insert(
             U256::from_str("0x1").unwrap(),
-            StorageSlot {
-                present_value: U256::from_str("0x1").unwrap(),
-                ..Default::default()
-            },
+            StorageSlot::new_changed(U256::ZERO, U256::from_str("0x1").unwrap()),
         );
         changes
             .append_account_changes(address, account_update)
@@ -388,17 +1229,11 @@ mod test {
         account_update.info.nonce = 2;
         account_update.storage.insert(
             U256::from_str("0x1").unwrap(),
-            StorageSlot {
-                present_value: U256::from_str("0x100").unwrap(),
-                ..Default::default()
-            },
+            StorageSlot::new_changed(U256::ZERO, U256::from_str("0x100").unwrap()),
         );
         account_update.storage.insert(
             U256::from_str("0x2").unwrap(),
-            StorageSlot {
-                present_value: U256::from_str("0x200").unwrap(),
-                ..Default::default()
-            },
+            StorageSlot::new_changed(U256::ZERO, U256::from_str("0x200").unwrap()),
         );
         changes
             .append_account_changes(address, account_update)
@@ -432,4 +1267,236 @@ mod test {
         );
         assert_eq!(net_account.info.nonce, 2);
     }
+
+    #[test]
+    fn test_selfdestruct_removal() {
+        let address = Address::from_str("0x00000000000000adc04c56bf30ac9d3c0aaf14dc").unwrap();
+        let mut destroyed = account_factory();
+        destroyed.mark_selfdestruct();
+        destroyed.mark_touch();
+        // The EVM only marks the account if removed under the rules of the fork.
+        let mut changes = PostBlockStateDelta::new(SpecId::CANCUN);
+        changes.append_account_changes(address, destroyed).unwrap();
+        assert!(changes
+            .get_changes()
+            .get(&address)
+            .unwrap()
+            .is_selfdestructed());
+    }
+
+    #[test]
+    fn test_touched_empty_account_removal() {
+        let address = Address::from_str("0x00000000000000adc04c56bf30ac9d3c0aaf14dc").unwrap();
+        let empty = Account {
+            info: AccountInfo::default(),
+            status: AccountStatus::Touched,
+            ..account_factory()
+        };
+        // EIP-161
+        let mut changes = PostBlockStateDelta::new(SpecId::SPURIOUS_DRAGON);
+        changes
+            .append_account_changes(address, empty.clone())
+            .unwrap();
+        assert!(changes
+            .get_changes()
+            .get(&address)
+            .unwrap()
+            .is_selfdestructed());
+        // Before EIP-161 empty accounts are kept.
+        let mut changes = PostBlockStateDelta::new(SpecId::TANGERINE);
+        changes
+            .append_account_changes(address, empty.clone())
+            .unwrap();
+        assert!(!changes
+            .get_changes()
+            .get(&address)
+            .unwrap()
+            .is_selfdestructed());
+        // Not touched, not removed.
+        let mut changes = PostBlockStateDelta::new(SpecId::SPURIOUS_DRAGON);
+        let loaded = Account {
+            status: AccountStatus::Loaded,
+            ..empty
+        };
+        changes.append_account_changes(address, loaded).unwrap();
+        assert!(!changes
+            .get_changes()
+            .get(&address)
+            .unwrap()
+            .is_selfdestructed());
+    }
+
+    #[test]
+    fn test_account_recreated_after_removal_has_cleared_storage() {
+        let address = Address::from_str("0x00000000000000adc04c56bf30ac9d3c0aaf14dc").unwrap();
+        let mut changes = PostBlockStateDelta::new(SpecId::SHANGHAI);
+        let mut destroyed = account_factory();
+        destroyed.mark_selfdestruct();
+        changes.append_account_changes(address, destroyed).unwrap();
+        // Recreated in a later transaction.
+        let mut recreated = account_factory();
+        recreated.mark_touch();
+        recreated.storage.insert(
+            U256::from(1),
+            StorageSlot::new_changed(U256::ZERO, U256::from(2)),
+        );
+        changes.append_account_changes(address, recreated).unwrap();
+        let net = changes.get_changes();
+        let net_account = net.get(&address).unwrap();
+        assert!(!net_account.is_selfdestructed());
+        assert!(net_account.is_created());
+        assert_eq!(net_account.storage.len(), 1);
+    }
+
+    #[test]
+    fn test_block_rewards_byzantium_with_uncle() {
+        let miner = H160::from_low_u64_be(1);
+        let uncle_miner = H160::from_low_u64_be(2);
+        let uncle_hash = H256::from_low_u64_be(3);
+        let block: Block<Transaction> = Block {
+            author: Some(miner),
+            number: Some(5_000_000.into()),
+            uncles: vec![uncle_hash],
+            ..Default::default()
+        };
+        let uncle: Block<H256> = Block {
+            author: Some(uncle_miner),
+            number: Some(4_999_998.into()),
+            hash: Some(uncle_hash),
+            ..Default::default()
+        };
+        let rewards = block_rewards(&block, &[uncle], SpecId::BYZANTIUM).unwrap();
+        let eth = U256::from(WEI_PER_ETH);
+        // 3 ETH + 3/32 ETH
+        assert_eq!(
+            rewards[0],
+            (
+                Address::from(miner.0),
+                U256::from(3) * eth + U256::from(3) * eth / U256::from(32)
+            )
+        );
+        // (4_999_998 + 8 - 5_000_000) * 3 / 8 ETH
+        assert_eq!(
+            rewards[1],
+            (
+                Address::from(uncle_miner.0),
+                U256::from(6) * U256::from(3) * eth / U256::from(8)
+            )
+        );
+    }
+
+    #[test]
+    fn test_block_rewards_by_fork() {
+        let block: Block<Transaction> = Block {
+            author: Some(H160::from_low_u64_be(1)),
+            number: Some(1.into()),
+            ..Default::default()
+        };
+        let eth = U256::from(WEI_PER_ETH);
+        let reward = |spec| block_rewards(&block, &[], spec).unwrap();
+        assert_eq!(reward(SpecId::FRONTIER)[0].1, U256::from(5) * eth);
+        assert_eq!(reward(SpecId::BYZANTIUM)[0].1, U256::from(3) * eth);
+        assert_eq!(reward(SpecId::PETERSBURG)[0].1, U256::from(2) * eth);
+        assert!(reward(SpecId::MERGE).is_empty());
+    }
+
+    #[test]
+    fn test_missing_uncle_headers() {
+        let block: Block<Transaction> = Block {
+            author: Some(H160::from_low_u64_be(1)),
+            number: Some(1.into()),
+            uncles: vec![H256::from_low_u64_be(3)],
+            ..Default::default()
+        };
+        assert_eq!(
+            block_rewards(&block, &[], SpecId::LONDON),
+            Err(TraceError::UncleCount {
+                expected: 1,
+                provided: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_withdrawals_in_wei() {
+        let recipient = H160::from_low_u64_be(1);
+        let block: Block<Transaction> = Block {
+            withdrawals: Some(vec![
+                Withdrawal {
+                    address: recipient,
+                    amount: 2.into(),
+                    ..Default::default()
+                },
+                Withdrawal {
+                    address: recipient,
+                    amount: 3.into(),
+                    ..Default::default()
+                },
+            ]),
+            ..Default::default()
+        };
+        let amounts = withdrawal_amounts(&block);
+        assert_eq!(amounts.len(), 2);
+        assert_eq!(
+            amounts[0],
+            (Address::from(recipient.0), U256::from(2_000_000_000u64))
+        );
+    }
+
+    #[test]
+    fn test_cancun_block_requires_parent_beacon_block_root() {
+        let state = BlockProofsBasic {
+            proofs: HashMap::default(),
+            code: HashMap::default(),
+            block_hashes: HashMap::default(),
+        };
+        let block: Block<Transaction> = Block {
+            author: Some(H160::default()),
+            number: Some(19_426_587.into()),
+            timestamp: 1_710_338_135.into(),
+            mix_hash: Some(H256::default()),
+            excess_blob_gas: Some(0.into()),
+            ..Default::default()
+        };
+        let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore).unwrap();
+        assert_eq!(
+            executor.trace_block_silent().err(),
+            Some(TraceError::NoParentBeaconBlockRoot)
+        );
+    }
+
+    #[test]
+    fn test_receipts_check_empty_block() {
+        let mut block: Block<Transaction> = Block {
+            receipts_root: receipts_root(&[]).unwrap(),
+            logs_bloom: Some(Bloom::zero()),
+            ..Default::default()
+        };
+        assert!(receipts_ok(&block, &[], SpecId::LONDON).is_ok());
+        block.gas_used = 21_000.into();
+        assert_eq!(
+            receipts_ok(&block, &[], SpecId::LONDON),
+            Err(TraceError::GasUsed {
+                computed: 0,
+                header: 21_000
+            })
+        );
+    }
+
+    #[test]
+    fn test_trace_json_single_and_multiple_values() {
+        assert_eq!(
+            trace_json(0, br#"{"gas":1}"#).unwrap(),
+            serde_json::json!({"gas": 1})
+        );
+        assert_eq!(
+            trace_json(0, b"{\"pc\":0}\n{\"pc\":2}\n").unwrap(),
+            serde_json::json!([{"pc": 0}, {"pc": 2}])
+        );
+        assert_eq!(trace_json(0, b"").unwrap(), serde_json::json!([]));
+        assert!(matches!(
+            trace_json(3, b"{"),
+            Err(TraceError::TraceJson { index: 3, .. })
+        ));
+    }
 }
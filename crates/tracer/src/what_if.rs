@@ -0,0 +1,210 @@
+//! For comparing the execution of a block with the execution of a modified list of
+//! transactions (E.g., with transactions replaced, dropped or inserted) from the same
+//! pre-block state.
+//!
+//! Transactions are matched between the two executions by hash. A transaction diverges if
+//! it is only present in one execution, or if its result (status, gas used, contract
+//! created and logs) or the state it changed differs.
+//!
+//! A transaction in the modified list may be invalid (E.g., a later transaction of a sender
+//! whose earlier transaction was dropped has a nonce that is too high). It is skipped and
+//! diverges, as it has no receipt.
+
+use std::collections::HashMap;
+
+use ethers::types::{TransactionReceipt, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::{parity::StateDiff, snapshot::TxStateDiff};
+
+/// The outcome of a transaction in an execution.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxOutcome {
+    pub tx_hash: H256,
+    /// Position of the transaction in the execution.
+    pub index: usize,
+    /// Absent if the transaction was invalid and skipped.
+    pub receipt: Option<TransactionReceipt>,
+    /// Why the transaction was invalid, if it was skipped.
+    pub invalid: Option<String>,
+    /// State changed by the transaction, before and after.
+    pub state_diff: StateDiff,
+}
+
+/// A transaction with a different outcome in the two executions. The outcome is absent
+/// for an execution that did not include the transaction.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxDivergence {
+    pub tx_hash: H256,
+    pub canonical: Option<TxOutcome>,
+    pub modified: Option<TxOutcome>,
+}
+
+/// The outcomes of the transactions of a block (canonical) and of a modified list of
+/// transactions, with the transactions that diverge.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhatIfReport {
+    pub canonical: Vec<TxOutcome>,
+    pub modified: Vec<TxOutcome>,
+    /// Transactions of the block in order, then transactions only in the modified list.
+    pub divergences: Vec<TxDivergence>,
+}
+
+impl WhatIfReport {
+    /// Compares the outcomes of the two executions.
+    pub(crate) fn new(canonical: Vec<TxOutcome>, modified: Vec<TxOutcome>) -> Self {
+        let canonical_by_hash: HashMap<H256, &TxOutcome> =
+            canonical.iter().map(|o| (o.tx_hash, o)).collect();
+        let modified_by_hash: HashMap<H256, &TxOutcome> =
+            modified.iter().map(|o| (o.tx_hash, o)).collect();
+        let mut divergences = vec![];
+        for outcome in &canonical {
+            let other = modified_by_hash.get(&outcome.tx_hash).copied();
+            if other.map(|o| same_outcome(outcome, o)).unwrap_or_default() {
+                continue;
+            }
+            divergences.push(TxDivergence {
+                tx_hash: outcome.tx_hash,
+                canonical: Some(outcome.clone()),
+                modified: other.cloned(),
+            });
+        }
+        for outcome in &modified {
+            if !canonical_by_hash.contains_key(&outcome.tx_hash) {
+                divergences.push(TxDivergence {
+                    tx_hash: outcome.tx_hash,
+                    canonical: None,
+                    modified: Some(outcome.clone()),
+                });
+            }
+        }
+        Self {
+            canonical,
+            modified,
+            divergences,
+        }
+    }
+    /// Whether every transaction had the same outcome in both executions.
+    pub fn is_identical(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Combines the receipts (or the reason a transaction was invalid) and state diffs of the
+/// transactions of an execution, in order.
+pub(crate) fn tx_outcomes(
+    receipts: Vec<Result<TransactionReceipt, String>>,
+    state_diffs: Vec<TxStateDiff>,
+) -> Vec<TxOutcome> {
+    receipts
+        .into_iter()
+        .zip(state_diffs)
+        .enumerate()
+        .map(|(index, (receipt, diff))| {
+            let (receipt, invalid) = match receipt {
+                Ok(receipt) => (Some(receipt), None),
+                Err(reason) => (None, Some(reason)),
+            };
+            TxOutcome {
+                tx_hash: diff.tx_hash,
+                index,
+                receipt,
+                invalid,
+                state_diff: diff.state_diff,
+            }
+        })
+        .collect()
+}
+
+/// Compares outcomes, ignoring values that depend on the position of the transaction
+/// (cumulative gas used and log indices).
+fn same_outcome(a: &TxOutcome, b: &TxOutcome) -> bool {
+    let receipts_match = match (&a.receipt, &b.receipt) {
+        (Some(x), Some(y)) => same_receipt(x, y),
+        (None, None) => true,
+        _ => false,
+    };
+    receipts_match && a.invalid == b.invalid && a.state_diff == b.state_diff
+}
+
+/// Compares the results recorded in receipts, ignoring values that depend on the position
+/// of the transaction.
+fn same_receipt(a: &TransactionReceipt, b: &TransactionReceipt) -> bool {
+    let logs_match = a.logs.len() == b.logs.len()
+        && a.logs
+            .iter()
+            .zip(&b.logs)
+            .all(|(x, y)| x.address == y.address && x.topics == y.topics && x.data == y.data);
+    a.status == b.status
+        && a.gas_used == b.gas_used
+        && a.contract_address == b.contract_address
+        && logs_match
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::{Log, U256};
+
+    use super::*;
+
+    fn outcome(hash: u64, index: usize, gas_used: u64) -> TxOutcome {
+        TxOutcome {
+            tx_hash: H256::from_low_u64_be(hash),
+            index,
+            receipt: Some(TransactionReceipt {
+                gas_used: Some(U256::from(gas_used)),
+                cumulative_gas_used: U256::from(gas_used * (index as u64 + 1)),
+                logs: vec![Log {
+                    log_index: Some(U256::from(index)),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            invalid: None,
+            state_diff: StateDiff::new(),
+        }
+    }
+
+    #[test]
+    fn test_what_if_divergences() {
+        let canonical = vec![outcome(1, 0, 100), outcome(2, 1, 100), outcome(3, 2, 100)];
+        // Tx 1 dropped, tx 4 inserted and tx 3 uses different gas.
+        let modified = vec![outcome(4, 0, 100), outcome(2, 1, 100), outcome(3, 2, 50)];
+        let report = WhatIfReport::new(canonical, modified);
+        let diverged: Vec<(u64, bool, bool)> = report
+            .divergences
+            .iter()
+            .map(|d| {
+                (
+                    d.tx_hash.to_low_u64_be(),
+                    d.canonical.is_some(),
+                    d.modified.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            diverged,
+            vec![(1, true, false), (3, true, true), (4, false, true)]
+        );
+    }
+
+    #[test]
+    fn test_what_if_position_ignored() {
+        let report = WhatIfReport::new(vec![outcome(1, 0, 100)], vec![outcome(1, 3, 100)]);
+        assert!(report.is_identical());
+    }
+
+    #[test]
+    fn test_what_if_invalid_transaction_diverges() {
+        let mut skipped = outcome(1, 0, 0);
+        skipped.receipt = None;
+        skipped.invalid = Some(String::from("nonce too high"));
+        let report = WhatIfReport::new(vec![outcome(1, 0, 100)], vec![skipped]);
+        assert_eq!(report.divergences.len(), 1);
+        let modified = report.divergences[0].modified.as_ref().unwrap();
+        assert!(modified.invalid.is_some());
+    }
+}
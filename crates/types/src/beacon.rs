@@ -0,0 +1,61 @@
+//! For EIP-4788: Beacon block root in the EVM.
+//!
+//! Before the transactions in a block are executed, the parent beacon block root is stored in
+//! the beacon roots contract. Transactions read recent roots by calling the contract with a
+//! timestamp. The contract keeps the timestamps and roots in a ring buffer in storage.
+
+use revm::primitives::{Address, B256, U256};
+
+/// Address of the beacon roots contract (0x000F3df6D732807Ef1319fB7B8bB8522d0Beac02).
+pub const BEACON_ROOTS_ADDRESS: Address = Address::new([
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+]);
+
+/// Number of roots held by the contract ring buffer.
+pub const HISTORY_BUFFER_LENGTH: u64 = 8191;
+
+/// Storage slot that holds the timestamp for a root.
+pub fn timestamp_slot(timestamp: u64) -> U256 {
+    U256::from(timestamp % HISTORY_BUFFER_LENGTH)
+}
+
+/// Storage slot that holds the root for a timestamp.
+pub fn root_slot(timestamp: u64) -> U256 {
+    U256::from(timestamp % HISTORY_BUFFER_LENGTH + HISTORY_BUFFER_LENGTH)
+}
+
+/// The storage (slot, value) pairs in the beacon roots contract for a root and the
+/// timestamp of the block it was stored in.
+pub fn beacon_root_storage(timestamp: u64, root: B256) -> [(U256, U256); 2] {
+    [
+        (timestamp_slot(timestamp), U256::from(timestamp)),
+        (root_slot(timestamp), U256::from_be_bytes(root.0)),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_beacon_roots_address() {
+        assert_eq!(
+            BEACON_ROOTS_ADDRESS,
+            Address::from_str("0x000F3df6D732807Ef1319fB7B8bB8522d0Beac02").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_beacon_root_storage() {
+        let timestamp = 1_710_338_135;
+        let root = B256::with_last_byte(7);
+        let [(ts_slot, ts_value), (root_slot, root_value)] = beacon_root_storage(timestamp, root);
+        assert_eq!(ts_slot, U256::from(timestamp % 8191));
+        assert_eq!(ts_value, U256::from(timestamp));
+        assert_eq!(root_slot, ts_slot + U256::from(8191));
+        assert_eq!(root_value, U256::from(7));
+    }
+}
@@ -0,0 +1,295 @@
+//! For describing the chain that a block belongs to.
+
+use std::{fs, path::Path};
+
+use revm::primitives::SpecId;
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::fork::{ForkActivation, ForkSchedule};
+
+#[derive(Debug, Error)]
+pub enum ChainConfigError {
+    #[error("Unable to read genesis file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unable to parse genesis config: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Parameters that define how blocks on a particular chain are executed.
+///
+/// ### DAO fork
+/// The block at which the DAO fork activated is recorded. The irregular state change that
+/// occurred at that block is not applied during execution.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChainConfig {
+    /// Chain id, used for transaction replay protection (EIP-155).
+    pub chain_id: u64,
+    /// Hard forks and the points at which they activate.
+    pub forks: ForkSchedule,
+    /// Block at which the DAO fork activated, if the chain supports it.
+    pub dao_fork_block: Option<u64>,
+    /// Point at which withdrawals from the beacon chain are processed (EIP-4895).
+    pub withdrawals: Option<ForkActivation>,
+}
+
+impl ChainConfig {
+    /// Ethereum mainnet.
+    pub fn mainnet() -> Self {
+        let forks = ForkSchedule::mainnet();
+        Self {
+            chain_id: 1,
+            withdrawals: forks.activation(SpecId::SHANGHAI),
+            forks,
+            dao_fork_block: Some(1_920_000),
+        }
+    }
+    /// Sepolia testnet.
+    pub fn sepolia() -> Self {
+        use ForkActivation::*;
+        let shanghai = Timestamp(1_677_557_088);
+        Self {
+            chain_id: 11_155_111,
+            forks: ForkSchedule::new(vec![
+                (SpecId::FRONTIER, Block(0)),
+                (SpecId::HOMESTEAD, Block(0)),
+                (SpecId::TANGERINE, Block(0)),
+                (SpecId::SPURIOUS_DRAGON, Block(0)),
+                (SpecId::BYZANTIUM, Block(0)),
+                (SpecId::CONSTANTINOPLE, Block(0)),
+                (SpecId::PETERSBURG, Block(0)),
+                (SpecId::ISTANBUL, Block(0)),
+                (SpecId::MUIR_GLACIER, Block(0)),
+                (SpecId::BERLIN, Block(0)),
+                (SpecId::LONDON, Block(0)),
+                (SpecId::MERGE, Block(1_450_409)),
+                (SpecId::SHANGHAI, shanghai),
+                (SpecId::CANCUN, Timestamp(1_706_655_072)),
+            ]),
+            dao_fork_block: None,
+            withdrawals: Some(shanghai),
+        }
+    }
+    /// Holesky testnet.
+    pub fn holesky() -> Self {
+        use ForkActivation::*;
+        let shanghai = Timestamp(1_696_000_704);
+        Self {
+            chain_id: 17_000,
+            forks: ForkSchedule::new(vec![
+                (SpecId::FRONTIER, Block(0)),
+                (SpecId::HOMESTEAD, Block(0)),
+                (SpecId::TANGERINE, Block(0)),
+                (SpecId::SPURIOUS_DRAGON, Block(0)),
+                (SpecId::BYZANTIUM, Block(0)),
+                (SpecId::CONSTANTINOPLE, Block(0)),
+                (SpecId::PETERSBURG, Block(0)),
+                (SpecId::ISTANBUL, Block(0)),
+                (SpecId::BERLIN, Block(0)),
+                (SpecId::LONDON, Block(0)),
+                (SpecId::MERGE, Block(0)),
+                (SpecId::SHANGHAI, shanghai),
+                (SpecId::CANCUN, Timestamp(1_707_305_664)),
+            ]),
+            dao_fork_block: None,
+            withdrawals: Some(shanghai),
+        }
+    }
+    /// Returns the built-in configuration for a chain id, if there is one.
+    pub fn from_chain_id(chain_id: u64) -> Option<Self> {
+        [Self::mainnet(), Self::sepolia(), Self::holesky()]
+            .into_iter()
+            .find(|config| config.chain_id == chain_id)
+    }
+    /// Reads a genesis-style JSON file (as used by execution clients) that has a
+    /// "config" member containing the chain id and fork activations.
+    pub fn from_genesis_file<P: AsRef<Path>>(path: P) -> Result<Self, ChainConfigError> {
+        let json = fs::read_to_string(path)?;
+        Self::from_genesis_json(&json)
+    }
+    /// Parses a genesis-style JSON string. See from_genesis_file().
+    ///
+    /// ### Merge
+    /// The merge is activated at genesis if the `terminalTotalDifficulty` is zero. A chain
+    /// that reached a non-zero terminal total difficulty does not record the block of the
+    /// merge, which must then be provided with with_merge_block(). The `mergeNetsplitBlock`
+    /// is not used, as it may be later than the merge (E.g., on Sepolia).
+    pub fn from_genesis_json(json: &str) -> Result<Self, ChainConfigError> {
+        let genesis: Genesis = serde_json::from_str(json)?;
+        Ok(genesis.config.into())
+    }
+    /// Activates the merge at the given block, replacing any activation in the config.
+    pub fn with_merge_block(mut self, block: u64) -> Self {
+        self.forks
+            .set_activation(SpecId::MERGE, ForkActivation::Block(block));
+        self
+    }
+    /// Returns the most recent fork active for a block with the given number and timestamp.
+    pub fn spec_id(&self, block_number: u64, timestamp: u64) -> SpecId {
+        self.forks.spec_id(block_number, timestamp)
+    }
+    /// Returns true if withdrawals are processed in the block with the given number and timestamp.
+    pub fn withdrawals_active(&self, block_number: u64, timestamp: u64) -> bool {
+        self.withdrawals
+            .map(|activation| activation.is_active(block_number, timestamp))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+/// Genesis file, of which only the chain config is used.
+#[derive(Debug, Deserialize)]
+struct Genesis {
+    config: GenesisConfig,
+}
+
+/// Chain config member of a genesis file. Absent forks are not activated.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenesisConfig {
+    chain_id: u64,
+    homestead_block: Option<u64>,
+    dao_fork_block: Option<u64>,
+    dao_fork_support: Option<bool>,
+    eip150_block: Option<u64>,
+    eip158_block: Option<u64>,
+    byzantium_block: Option<u64>,
+    constantinople_block: Option<u64>,
+    petersburg_block: Option<u64>,
+    istanbul_block: Option<u64>,
+    muir_glacier_block: Option<u64>,
+    berlin_block: Option<u64>,
+    london_block: Option<u64>,
+    arrow_glacier_block: Option<u64>,
+    gray_glacier_block: Option<u64>,
+    /// May exceed u64, only used to detect a chain that starts post-merge (zero).
+    terminal_total_difficulty: Option<Value>,
+    shanghai_time: Option<u64>,
+    cancun_time: Option<u64>,
+}
+
+impl From<GenesisConfig> for ChainConfig {
+    fn from(config: GenesisConfig) -> Self {
+        use ForkActivation::*;
+        let dao_fork_block = match config.dao_fork_support {
+            Some(true) => config.dao_fork_block,
+            _ => None,
+        };
+        let merge_at_genesis = config
+            .terminal_total_difficulty
+            .as_ref()
+            .and_then(Value::as_u64)
+            == Some(0);
+        let merge = merge_at_genesis.then_some(0);
+        let shanghai = config.shanghai_time.map(Timestamp);
+        let forks = [
+            (SpecId::FRONTIER, Some(Block(0))),
+            (SpecId::HOMESTEAD, config.homestead_block.map(Block)),
+            (SpecId::DAO_FORK, dao_fork_block.map(Block)),
+            (SpecId::TANGERINE, config.eip150_block.map(Block)),
+            (SpecId::SPURIOUS_DRAGON, config.eip158_block.map(Block)),
+            (SpecId::BYZANTIUM, config.byzantium_block.map(Block)),
+            (
+                SpecId::CONSTANTINOPLE,
+                config.constantinople_block.map(Block),
+            ),
+            (SpecId::PETERSBURG, config.petersburg_block.map(Block)),
+            (SpecId::ISTANBUL, config.istanbul_block.map(Block)),
+            (SpecId::MUIR_GLACIER, config.muir_glacier_block.map(Block)),
+            (SpecId::BERLIN, config.berlin_block.map(Block)),
+            (SpecId::LONDON, config.london_block.map(Block)),
+            (SpecId::ARROW_GLACIER, config.arrow_glacier_block.map(Block)),
+            (SpecId::GRAY_GLACIER, config.gray_glacier_block.map(Block)),
+            (SpecId::MERGE, merge.map(Block)),
+            (SpecId::SHANGHAI, shanghai),
+            (SpecId::CANCUN, config.cancun_time.map(Timestamp)),
+        ]
+        .into_iter()
+        .filter_map(|(spec, activation)| activation.map(|a| (spec, a)))
+        .collect();
+        ChainConfig {
+            chain_id: config.chain_id,
+            forks: ForkSchedule::new(forks),
+            dao_fork_block,
+            withdrawals: shanghai,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DEVNET_GENESIS: &str = r#"{
+        "config": {
+            "chainId": 32382,
+            "homesteadBlock": 0,
+            "eip150Block": 0,
+            "eip155Block": 0,
+            "eip158Block": 0,
+            "byzantiumBlock": 0,
+            "constantinopleBlock": 0,
+            "petersburgBlock": 0,
+            "istanbulBlock": 0,
+            "berlinBlock": 0,
+            "londonBlock": 0,
+            "terminalTotalDifficulty": 0,
+            "shanghaiTime": 1700000000,
+            "cancunTime": 1710000000
+        },
+        "alloc": {},
+        "difficulty": "0x01",
+        "gasLimit": "0x1c9c380"
+    }"#;
+
+    #[test]
+    fn test_chain_config_from_genesis_json() {
+        let config = ChainConfig::from_genesis_json(DEVNET_GENESIS).unwrap();
+        assert_eq!(config.chain_id, 32382);
+        assert_eq!(config.dao_fork_block, None);
+        assert_eq!(config.spec_id(0, 0), SpecId::MERGE);
+        assert_eq!(config.spec_id(100, 1_700_000_000), SpecId::SHANGHAI);
+        assert_eq!(config.spec_id(200, 1_710_000_001), SpecId::CANCUN);
+        assert!(!config.withdrawals_active(10, 1_699_999_999));
+        assert!(config.withdrawals_active(100, 1_700_000_000));
+    }
+
+    #[test]
+    fn test_chain_config_presets() {
+        assert_eq!(ChainConfig::from_chain_id(1), Some(ChainConfig::mainnet()));
+        assert_eq!(
+            ChainConfig::from_chain_id(11_155_111),
+            Some(ChainConfig::sepolia())
+        );
+        assert_eq!(
+            ChainConfig::from_chain_id(17_000),
+            Some(ChainConfig::holesky())
+        );
+        assert_eq!(ChainConfig::from_chain_id(5), None);
+        assert_eq!(ChainConfig::sepolia().spec_id(1_450_408, 0), SpecId::LONDON);
+        assert_eq!(ChainConfig::sepolia().spec_id(1_450_409, 0), SpecId::MERGE);
+        assert!(ChainConfig::mainnet().withdrawals_active(17_034_870, 1_681_338_479));
+    }
+
+    #[test]
+    fn test_genesis_merge_after_terminal_total_difficulty() {
+        let genesis = DEVNET_GENESIS.replace(
+            r#""terminalTotalDifficulty": 0"#,
+            r#""terminalTotalDifficulty": 100, "mergeNetsplitBlock": 70"#,
+        );
+        let config = ChainConfig::from_genesis_json(&genesis).unwrap();
+        // The merge block is not recorded in the genesis file, the netsplit block is ignored.
+        assert_eq!(config.spec_id(50, 0), SpecId::LONDON);
+        assert_eq!(config.spec_id(70, 0), SpecId::LONDON);
+        let config = config.with_merge_block(50);
+        assert_eq!(config.spec_id(49, 0), SpecId::LONDON);
+        assert_eq!(config.spec_id(50, 0), SpecId::MERGE);
+        assert_eq!(config.spec_id(60, 1_700_000_000), SpecId::SHANGHAI);
+    }
+}
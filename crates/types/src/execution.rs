@@ -2,7 +2,7 @@
 
 use std::collections::HashMap;
 
-use revm::primitives::{Account, AccountInfo, HashMap as rHashMap, B160, B256, U256};
+use revm::primitives::{Account, AccountInfo, Address, HashMap as rHashMap, B256, U256};
 use thiserror::Error;
 
 use crate::{utils::UtilsError, proof::{DisplayProof, DisplayStorageProof}};
@@ -24,6 +24,14 @@ pub enum EvmStateError {
     InvalidStorageKey(String),
     #[error("Unable to display proof: {0}")]
     DisplayError(String),
+    #[error("Account {0} is not in the block state data")]
+    MissingAccount(String),
+    #[error("Storage slot {slot} of account {address} is not in the block state data")]
+    MissingStorage { address: String, slot: String },
+    #[error("Code with hash {0} is not in the block state data")]
+    MissingCode(String),
+    #[error("Block hash of block {0} is not in the block state data")]
+    MissingBlockHash(String),
 }
 
 /// Behaviour that any proof-based format must provide to be convertible into
@@ -33,11 +41,14 @@ pub enum EvmStateError {
 pub trait StateForEvm {
     /// Gets account information in a format that can be inserted into a
     /// revm db. This includes contract bytecode.
-    fn get_account_info(&self, address: &B160) -> Result<AccountInfo, EvmStateError>;
+    fn get_account_info(&self, address: &Address) -> Result<AccountInfo, EvmStateError>;
     /// Gets all the addresses.
-    fn addresses(&self) -> Vec<B160>;
+    fn addresses(&self) -> Vec<Address>;
     /// Gets the storage key-val pairs for the account of the address.
-    fn get_account_storage(&self, address: &B160) -> Result<rHashMap<U256, U256>, EvmStateError>;
+    fn get_account_storage(
+        &self,
+        address: &Address,
+    ) -> Result<rHashMap<U256, U256>, EvmStateError>;
     /// Gets BLOCKAHSH opcode accesses required for the block.
     /// Pairs are (block_number, block_hash).
     fn get_blockhash_accesses(&self) -> Result<rHashMap<U256, B256>, EvmStateError>;
@@ -53,7 +64,7 @@ pub trait StateForEvm {
     /// provided.
     fn state_root_post_block(
         &mut self,
-        changes: HashMap<B160, Account>,
+        changes: HashMap<Address, Account>,
     ) -> Result<B256, EvmStateError>;
     /// Print an account proof.
     fn print_account_proof<T: AsRef<str>>(&self, account_address: T) -> Result<DisplayProof, EvmStateError>;
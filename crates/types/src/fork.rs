@@ -0,0 +1,124 @@
+//! For selecting the hard fork rules that apply to a block.
+
+use revm::primitives::SpecId;
+
+/// The point at which a hard fork becomes active.
+///
+/// Forks up to and including the merge are activated by block number. Later forks
+/// are activated by block timestamp.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ForkActivation {
+    Block(u64),
+    Timestamp(u64),
+}
+
+impl ForkActivation {
+    /// Returns true if a block with the given number and timestamp includes the fork.
+    pub fn is_active(&self, block_number: u64, timestamp: u64) -> bool {
+        match self {
+            ForkActivation::Block(number) => block_number >= *number,
+            ForkActivation::Timestamp(time) => timestamp >= *time,
+        }
+    }
+}
+
+/// An ordered list of hard forks and the points at which they activate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ForkSchedule(Vec<(SpecId, ForkActivation)>);
+
+impl ForkSchedule {
+    /// Creates a schedule from (fork, activation) pairs. Forks must be in
+    /// chronological order.
+    pub fn new(forks: Vec<(SpecId, ForkActivation)>) -> Self {
+        Self(forks)
+    }
+    /// The Ethereum mainnet hard fork schedule.
+    ///
+    /// Constantinople and Petersburg activated at the same block, the latter
+    /// taking precedence.
+    pub fn mainnet() -> Self {
+        use ForkActivation::*;
+        Self(vec![
+            (SpecId::FRONTIER, Block(0)),
+            (SpecId::FRONTIER_THAWING, Block(200_000)),
+            (SpecId::HOMESTEAD, Block(1_150_000)),
+            (SpecId::DAO_FORK, Block(1_920_000)),
+            (SpecId::TANGERINE, Block(2_463_000)),
+            (SpecId::SPURIOUS_DRAGON, Block(2_675_000)),
+            (SpecId::BYZANTIUM, Block(4_370_000)),
+            (SpecId::CONSTANTINOPLE, Block(7_280_000)),
+            (SpecId::PETERSBURG, Block(7_280_000)),
+            (SpecId::ISTANBUL, Block(9_069_000)),
+            (SpecId::MUIR_GLACIER, Block(9_200_000)),
+            (SpecId::BERLIN, Block(12_244_000)),
+            (SpecId::LONDON, Block(12_965_000)),
+            (SpecId::ARROW_GLACIER, Block(13_773_000)),
+            (SpecId::GRAY_GLACIER, Block(15_050_000)),
+            (SpecId::MERGE, Block(15_537_394)),
+            (SpecId::SHANGHAI, Timestamp(1_681_338_455)),
+            (SpecId::CANCUN, Timestamp(1_710_338_135)),
+        ])
+    }
+    /// Returns the most recent fork active for a block with the given number and timestamp.
+    pub fn spec_id(&self, block_number: u64, timestamp: u64) -> SpecId {
+        self.0
+            .iter()
+            .rev()
+            .find(|(_, activation)| activation.is_active(block_number, timestamp))
+            .map(|(spec, _)| *spec)
+            .unwrap_or(SpecId::FRONTIER)
+    }
+    /// Sets the activation point of a fork, replacing any existing activation. A fork not
+    /// in the schedule is inserted in chronological order.
+    pub fn set_activation(&mut self, spec: SpecId, activation: ForkActivation) {
+        match self.0.iter_mut().find(|(fork, _)| *fork == spec) {
+            Some(entry) => entry.1 = activation,
+            None => {
+                let index = self
+                    .0
+                    .iter()
+                    .position(|(fork, _)| *fork as u8 > spec as u8)
+                    .unwrap_or(self.0.len());
+                self.0.insert(index, (spec, activation));
+            }
+        }
+    }
+    /// Returns the activation point of a fork, if it is in the schedule.
+    pub fn activation(&self, spec: SpecId) -> Option<ForkActivation> {
+        self.0
+            .iter()
+            .find(|(fork, _)| *fork == spec)
+            .map(|(_, activation)| *activation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mainnet_block_forks() {
+        let schedule = ForkSchedule::mainnet();
+        assert_eq!(schedule.spec_id(0, 0), SpecId::FRONTIER);
+        assert_eq!(schedule.spec_id(1_919_999, 0), SpecId::HOMESTEAD);
+        assert_eq!(schedule.spec_id(1_920_000, 0), SpecId::DAO_FORK);
+        assert_eq!(schedule.spec_id(7_280_000, 0), SpecId::PETERSBURG);
+        assert_eq!(schedule.spec_id(12_965_000, 0), SpecId::LONDON);
+        assert_eq!(
+            schedule.spec_id(15_537_393, 1_663_224_162),
+            SpecId::GRAY_GLACIER
+        );
+        assert_eq!(schedule.spec_id(15_537_394, 1_663_224_179), SpecId::MERGE);
+    }
+
+    #[test]
+    fn test_mainnet_timestamp_forks() {
+        let schedule = ForkSchedule::mainnet();
+        assert_eq!(schedule.spec_id(17_034_869, 1_681_338_443), SpecId::MERGE);
+        assert_eq!(
+            schedule.spec_id(17_034_870, 1_681_338_479),
+            SpecId::SHANGHAI
+        );
+        assert_eq!(schedule.spec_id(19_426_587, 1_710_338_135), SpecId::CANCUN);
+    }
+}
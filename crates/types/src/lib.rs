@@ -1,6 +1,9 @@
 pub mod alias;
+pub mod beacon;
+pub mod chain;
 pub mod constants;
 pub mod execution;
+pub mod fork;
 pub mod proof;
 pub mod oracle;
 pub mod state;
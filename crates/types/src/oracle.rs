@@ -8,8 +8,15 @@ use ethers::types::H160;
 /// block pre-state proofs so that post-state proofs can be computed.
 ///
 /// The oracle stores for each key, the proof nodes at and below the traversal index.
+///
+/// Storage tries and the account trie are kept separately. Account trie nodes are required
+/// when an account is removed (SELFDESTRUCT or EIP-161) and the account trie is reorganised.
 #[derive(Debug, Default, Clone)]
-pub struct TrieNodeOracle(HashMap<OracleTarget, Vec<Vec<u8>>>);
+pub struct TrieNodeOracle {
+    storage: HashMap<OracleTarget, Vec<Vec<u8>>>,
+    /// Traversal to target (nibbles) -> nodes.
+    accounts: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+}
 
 impl TrieNodeOracle {
     /// Make an addition to the oracle.
@@ -19,7 +26,7 @@ impl TrieNodeOracle {
         traversal_to_target: Vec<u8>,
         nodes: Vec<Vec<u8>>,
     ) {
-        self.0.insert(
+        self.storage.insert(
             OracleTarget {
                 address,
                 traversal_to_target,
@@ -32,13 +39,25 @@ impl TrieNodeOracle {
     /// The node returned will be the specific node that requires the oracle. This
     /// will be the grandparent of a removed node.
     pub fn lookup(&self, address: H160, traversal_to_target: Vec<u8>) -> Option<Vec<Vec<u8>>> {
-        self.0
+        self.storage
             .get(&OracleTarget {
                 address,
                 traversal_to_target,
             })
             .map(|x| x.to_owned())
     }
+    /// Make an addition to the oracle for the account trie.
+    pub fn insert_account_nodes(&mut self, traversal_to_target: Vec<u8>, nodes: Vec<Vec<u8>>) {
+        self.accounts.insert(traversal_to_target, nodes);
+    }
+    /// Retrieve data from the oracle for the account trie.
+    ///
+    /// The node returned will be the grandparent of a removed account leaf.
+    pub fn lookup_account(&self, traversal_to_target: Vec<u8>) -> Option<Vec<Vec<u8>>> {
+        self.accounts
+            .get(&traversal_to_target)
+            .map(|x| x.to_owned())
+    }
 }
 
 /// The key used to look up items in the oracle. Two storage key lookups are permitted to
@@ -20,7 +20,7 @@ use crate::{
 };
 
 use revm::primitives::{
-    keccak256, Account, AccountInfo, Bytecode, BytecodeState, Bytes, HashMap as rHashMap, B160,
+    keccak256, Account, AccountInfo, Address, Bytecode, BytecodeState, Bytes, HashMap as rHashMap,
     B256, U256,
 };
 
@@ -52,6 +52,10 @@ pub enum StateError {
 /// - contract code.
 /// - account trie node.
 /// - storage trie node.
+/// - BLOCKHASH opcode reads.
+///
+/// Beacon block roots read from the beacon roots contract (EIP-4788) are in the storage
+/// proofs of the contract.
 #[derive(PartialEq, Eq, Debug, Default, SimpleSerialize)]
 pub struct RequiredBlockState {
     pub compact_eip1186_proofs: CompactEip1186Proofs,
@@ -129,7 +133,7 @@ impl RequiredBlockState {
 }
 
 impl StateForEvm for RequiredBlockState {
-    fn get_account_info(&self, address: &B160) -> Result<AccountInfo, EvmStateError> {
+    fn get_account_info(&self, address: &Address) -> Result<AccountInfo, EvmStateError> {
         let target = SszH160::try_from(address.0.to_vec()).unwrap();
         for account in self.compact_eip1186_proofs.iter() {
             if account.address == target {
@@ -144,7 +148,6 @@ impl StateForEvm for RequiredBlockState {
                         let len = bytes.len();
                         Bytecode {
                             bytecode: Bytes::from(bytes),
-                            hash: code_hash,
                             state: BytecodeState::Checked { len },
                         }
                     });
@@ -161,14 +164,17 @@ impl StateForEvm for RequiredBlockState {
         Err(EvmStateError::NoProofForAddress(address.to_string()))
     }
 
-    fn addresses(&self) -> Vec<B160> {
+    fn addresses(&self) -> Vec<Address> {
         self.compact_eip1186_proofs
             .iter()
-            .map(|proof| B160::from_slice(&proof.address))
+            .map(|proof| Address::from_slice(&proof.address))
             .collect()
     }
 
-    fn get_account_storage(&self, address: &B160) -> Result<rHashMap<U256, U256>, EvmStateError> {
+    fn get_account_storage(
+        &self,
+        address: &Address,
+    ) -> Result<rHashMap<U256, U256>, EvmStateError> {
         let target = SszH160::try_from(address.0.to_vec()).unwrap();
         let mut storage_map = rHashMap::default();
         for account in self.compact_eip1186_proofs.iter() {
@@ -195,7 +201,7 @@ impl StateForEvm for RequiredBlockState {
 
     fn state_root_post_block(
         &mut self,
-        _changes: HashMap<B160, Account>,
+        _changes: HashMap<Address, Account>,
     ) -> Result<B256, EvmStateError> {
         unimplemented!(
             "Post execution root check is not implemented for RequiredBlockState data format."
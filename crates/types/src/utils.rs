@@ -1,6 +1,6 @@
-use ethers::types::transaction::eip2930::AccessList;
+use ethers::types::transaction::eip2930::{AccessList, AccessListItem};
 use hex::FromHexError;
-use revm::primitives::{B160, B256, U256};
+use revm::primitives::{Address, B256, U256};
 use thiserror::Error;
 
 use crate::alias::{SszH256, SszU256, SszU64};
@@ -78,21 +78,19 @@ pub fn ru256_to_eh256(input: U256) -> ethers::types::H256 {
 
 /// revm B256 to ethers H256
 pub fn rb256_to_eh256(input: revm::primitives::B256) -> ethers::types::H256 {
-    let bytes: &[u8; 32] = input.as_fixed_bytes();
-    bytes.into()
+    input.0.into()
 }
 
-/// revm B160 to ethers H160
-pub fn rb160_to_eh160(input: &B160) -> ethers::types::H160 {
-    let bytes: &[u8; 20] = input.as_fixed_bytes();
-    bytes.into()
+/// revm Address to ethers H160
+pub fn rb160_to_eh160(input: &Address) -> ethers::types::H160 {
+    input.0 .0.into()
 }
 
 /// Helper for revm access list type conversion.
 type RevmAccessList = Vec<RevmAccessesListItem>;
 
 /// Helper for revm access list item type conversion.
-type RevmAccessesListItem = (B160, Vec<U256>);
+type RevmAccessesListItem = (Address, Vec<U256>);
 
 /// Ethers AccessList to revm access list
 pub fn access_list_e_to_r(input: AccessList) -> RevmAccessList {
@@ -100,13 +98,25 @@ pub fn access_list_e_to_r(input: AccessList) -> RevmAccessList {
         .0
         .into_iter()
         .map(|list| {
-            let out_address: B160 = list.address.0.into();
+            let out_address: Address = list.address.0.into();
             let out_values: Vec<U256> = list.storage_keys.into_iter().map(eh256_to_ru256).collect();
             (out_address, out_values)
         })
         .collect()
 }
 
+/// revm access list to Ethers AccessList
+pub fn access_list_r_to_e(input: RevmAccessList) -> AccessList {
+    let items = input
+        .into_iter()
+        .map(|(address, keys)| AccessListItem {
+            address: rb160_to_eh160(&address),
+            storage_keys: keys.into_iter().map(ru256_to_eh256).collect(),
+        })
+        .collect();
+    AccessList(items)
+}
+
 /// Convert SSZ U256 equivalent to revm U256.
 ///
 /// Input is big endian.
@@ -139,7 +149,7 @@ pub fn ssz_h256_to_rb256(input: &SszH256) -> B256 {
 
 #[cfg(test)]
 mod test {
-    use ethers::types::{transaction::eip2930::AccessListItem, H160, H256};
+    use ethers::types::{H160, H256};
     use revm::primitives::B256;
 
     use super::*;
@@ -220,7 +230,7 @@ mod test {
     #[test]
     fn test_rb160_to_eh160() {
         let hash_string = "0x0000000000000000000000000000000000001234";
-        let input = B160::from_str(hash_string).unwrap();
+        let input = Address::from_str(hash_string).unwrap();
         let derived = rb160_to_eh160(&input);
         let expected = ethers::types::H160::from_str(hash_string).unwrap();
         assert_eq!(derived, expected);
@@ -236,12 +246,22 @@ mod test {
         }]);
 
         let derived = access_list_e_to_r(input);
-        let address = B160::from_str(address).unwrap();
+        let address = Address::from_str(address).unwrap();
         let storage = U256::try_from_be_slice(&hex_decode(hash).unwrap()).unwrap();
         let expected: RevmAccessList = vec![(address, vec![storage])];
         assert_eq!(derived, expected);
     }
 
+    #[test]
+    fn test_access_list_r_to_e() {
+        let input: AccessList = AccessList(vec![AccessListItem {
+            address: H160::from_low_u64_be(0x9876),
+            storage_keys: vec![H256::from_low_u64_be(0x1234), H256::zero()],
+        }]);
+        let derived = access_list_r_to_e(access_list_e_to_r(input.clone()));
+        assert_eq!(derived, input);
+    }
+
     #[test]
     fn test_ssz_u64_to_u64() {
         let expected = 123456789_u64;
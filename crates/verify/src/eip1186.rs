@@ -90,7 +90,7 @@ pub fn verify_proof(
             |source| VerifyProofError::StorageError {
                 source,
                 account: hex_encode(proof.address),
-                storage_key: hex_encode(storage_proof.key),
+                storage_key: hex_encode(<[u8; 32]>::from(storage_proof.key)),
             },
         )?;
     }
@@ -141,7 +141,7 @@ fn verify_account_storage_component(
     let storage_prover = SingleProofPath {
         proof: storage_proof.proof,
         root: *storage_hash,
-        path: keccak256(storage_proof.key),
+        path: keccak256(<[u8; 32]>::from(storage_proof.key)),
         claimed_value: rlp_value,
     };
 
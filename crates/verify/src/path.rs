@@ -349,7 +349,7 @@ impl TryFrom<&[u8]> for PrefixEncoding {
 }
 
 /// Represents byte as an array of nibbles: 0xbc -> [0xb, 0xc]
-fn byte_to_nibbles(byte: &u8) -> [u8; 2] {
+pub fn byte_to_nibbles(byte: &u8) -> [u8; 2] {
     // 0xbc -> 0xb
     let high = byte >> 4;
     // 0xbc -> 0xc
@@ -3,7 +3,10 @@ use archors_inventory::cache::{
     get_block_from_cache, get_required_state_from_cache, store_block_with_transactions,
     store_required_state,
 };
-use archors_tracer::trace::{BlockExecutor, PostExecutionProof};
+use archors_tracer::{
+    sink::StdoutSink,
+    trace::{BlockExecutor, PostExecutionProof},
+};
 
 /// Create, cache and then use the RequiredBlockState data type.
 ///
@@ -32,7 +35,7 @@ async fn main() -> Result<()> {
     let executor = BlockExecutor::load(block, state, PostExecutionProof::Ignore)?;
 
     // Either trace the full block or a single transaction of interest.
-    executor.trace_transaction(13)?;
-    //executor.trace_block()?;
+    executor.trace_transaction(13, &mut StdoutSink)?;
+    //executor.trace_block(&mut StdoutSink)?;
     Ok(())
 }
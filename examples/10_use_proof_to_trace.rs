@@ -5,6 +5,7 @@ use archors_inventory::cache::{
 };
 use archors_multiproof::{EIP1186MultiProof, StateForEvm};
 use archors_tracer::{
+    sink::StdoutSink,
     state::BlockProofsBasic,
     trace::{BlockExecutor, PostExecutionProof},
 };
@@ -67,8 +68,8 @@ fn main() -> Result<()> {
 }
 
 fn re_execute_block<T: StateForEvm>(executor: BlockExecutor<T>) -> Result<()> {
-    //let post_state = executor.trace_block()?;
-    let post_state = executor.trace_transaction(8)?;
+    //let post_state = executor.trace_block(&mut StdoutSink)?;
+    let post_state = executor.trace_transaction(8, &mut StdoutSink)?;
     Ok(())
 }
 
@@ -119,7 +119,7 @@ fn test_individual_account_updates_from_block_17190873() {
             for storage in expected.storage_proof {
                 let expected_proof =
                     DisplayProof::init(storage.proof.into_iter().map(|p| p.to_vec()).collect());
-                let key_string: String = hex_encode(storage.key.as_bytes());
+                let key_string: String = hex_encode(<[u8; 32]>::from(storage.key));
                 let address_string: String = hex_encode(address.as_bytes());
                 let computed_proof = computed_proofs
                     .print_storage_proof(&address_string, &key_string)
@@ -128,7 +128,7 @@ fn test_individual_account_updates_from_block_17190873() {
                     true => {
                         println!(
                             "Proof for key {} has incorrect value. Expected proof: {}\nGot: {}",
-                            hex_encode(storage.key),
+                            hex_encode(<[u8; 32]>::from(storage.key)),
                             expected_proof,
                             computed_proof.storage
                         );
@@ -140,7 +140,7 @@ fn test_individual_account_updates_from_block_17190873() {
                             if divergence_index != 0 {
                                 println!(
                                     "key {} has bad proof (divergence index {}) but value is ok. Expected proof: {}\nGot: {}",
-                                    hex_encode(storage.key),
+                                    hex_encode(<[u8; 32]>::from(storage.key)),
                                     divergence_index,
                                     expected_proof,
                                     computed_proof.storage